@@ -1,12 +1,18 @@
 use anyhow::Result;
 use clap::{Parser, Subcommand, ValueEnum};
 use colored::*;
-use serde_yaml;
 use std::path::PathBuf;
 
+use anvil_engine::engine::ProcessedTemplate;
+use anvil_engine::generator::ProgressCallback;
 use anvil_engine::{
-    CompositionEngine, Context, FileGenerator, ServiceCategory, ServiceCombination, ServiceConfig,
-    ServiceDefinition, ServicePromptType, ServiceSelection, TemplateConfig, TemplateEngine,
+    diff_directory, evaluate_features, load_answers, resolve_features, variable_applies,
+    variable_order, CompositionEngine, ConflictMode, Context, EngineError, FeatureSelection,
+    FileDiffKind, FileGenerator, FileReport, FileStatus, FileUpdateKind, GenerationResult,
+    HookCommand, HookRunner, HookStage, Manifest, ProjectAnswers, ProjectDiff, ProjectUpdater,
+    ServiceCategory, ServiceCombination, ServiceConfig, ServiceDefinition, ServicePromptType,
+    ServiceSelection, TemplateConfig, TemplateEngine, TemplateVariable, UpdatePlan, VariableType,
+    ANSWERS_FILE, MANIFEST_FILE,
 };
 
 #[derive(Parser)]
@@ -25,6 +31,7 @@ pub struct Cli {
 }
 
 #[derive(Subcommand)]
+#[allow(clippy::large_enum_variant)]
 pub enum Commands {
     Create {
         name: String,
@@ -44,12 +51,54 @@ pub enum Commands {
         #[arg(long)]
         github: bool,
 
-        #[arg(long)]
+        /// How to handle generated files that already exist with different content:
+        /// overwrite, skip, prompt, merge, or backup
+        #[arg(long, value_name = "MODE")]
+        on_conflict: Option<ConflictMode>,
+
+        /// Shorthand for --on-conflict overwrite
+        #[arg(long, conflicts_with = "on_conflict")]
         force: bool,
 
         #[arg(long)]
         dry_run: bool,
 
+        /// Show how the output directory would change instead of generating
+        #[arg(long, conflicts_with = "hooks_only")]
+        diff: bool,
+
+        /// Format of the --dry-run report
+        #[arg(long, value_enum, default_value = "table", requires = "dry_run")]
+        format: OutputFormat,
+
+        /// Skip the template's pre/post generation hooks
+        #[arg(long, conflicts_with = "hooks_only")]
+        no_hooks: bool,
+
+        /// Only run the template's hooks against an existing project directory
+        #[arg(long)]
+        hooks_only: bool,
+
+        /// Enable a template feature (repeatable)
+        #[arg(long = "feature", value_name = "FEATURE")]
+        features: Vec<String>,
+
+        /// Disable a template feature (repeatable)
+        #[arg(long = "no-feature", value_name = "FEATURE")]
+        disabled_features: Vec<String>,
+
+        /// Set a template variable (repeatable)
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_variable_assignment)]
+        vars: Vec<(String, String)>,
+
+        /// Read template variable values from a YAML or JSON file
+        #[arg(long, value_name = "FILE")]
+        answers: Option<PathBuf>,
+
+        /// Regenerate a project from a recorded .anvil/answers.yaml
+        #[arg(long, value_name = "FILE")]
+        replay: Option<PathBuf>,
+
         // Service selection flags
         #[arg(long)]
         auth: Option<String>,
@@ -80,6 +129,99 @@ pub enum Commands {
         preset: Option<String>,
     },
 
+    /// Bring a generated project up to date with its template, merging in local changes
+    Update {
+        /// Project directory to update
+        #[arg(default_value = ".")]
+        path: PathBuf,
+
+        /// Templates directory containing the version the project was generated from
+        #[arg(long, value_name = "DIR")]
+        from: Option<PathBuf>,
+
+        #[arg(long)]
+        no_input: bool,
+
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Set a template variable (repeatable)
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_variable_assignment)]
+        vars: Vec<(String, String)>,
+    },
+
+    /// Add a shared service to a generated project
+    Add {
+        /// Service category, e.g. auth, payments or monitoring
+        #[arg(value_parser = parse_service_category)]
+        category: ServiceCategory,
+
+        /// Service provider, e.g. stripe
+        provider: String,
+
+        /// Project directory
+        #[arg(long, default_value = ".")]
+        path: PathBuf,
+
+        #[arg(long)]
+        no_input: bool,
+
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Skip the service's pre/post generation hooks
+        #[arg(long)]
+        no_hooks: bool,
+
+        /// How to handle existing non-JSON files the service also provides:
+        /// overwrite, skip, prompt, merge, or backup
+        #[arg(long, value_name = "MODE", default_value = "backup")]
+        on_conflict: ConflictMode,
+    },
+
+    /// Remove a shared service from a generated project
+    Remove {
+        /// Service category to remove, e.g. auth
+        #[arg(value_parser = parse_service_category)]
+        category: ServiceCategory,
+
+        /// Project directory
+        #[arg(long, default_value = ".")]
+        path: PathBuf,
+
+        #[arg(long)]
+        no_input: bool,
+
+        #[arg(long)]
+        dry_run: bool,
+    },
+
+    /// Compare a generated project with what its template renders today
+    Diff {
+        /// Project directory
+        #[arg(default_value = ".")]
+        path: PathBuf,
+
+        /// Templates directory to render from instead of the installed templates
+        #[arg(long, value_name = "DIR")]
+        from: Option<PathBuf>,
+
+        #[arg(long)]
+        no_input: bool,
+
+        /// Set a template variable (repeatable)
+        #[arg(long = "var", value_name = "NAME=VALUE", value_parser = parse_variable_assignment)]
+        vars: Vec<(String, String)>,
+
+        /// Only list the files that differ
+        #[arg(long)]
+        name_only: bool,
+
+        /// Exit with an error when the project differs from the template
+        #[arg(long)]
+        exit_code: bool,
+    },
+
     List {
         #[arg(short, long)]
         language: Option<String>,
@@ -96,8 +238,9 @@ pub enum Commands {
     },
 }
 
-#[derive(ValueEnum, Clone)]
+#[derive(ValueEnum, Clone, Debug, Default, PartialEq)]
 pub enum OutputFormat {
+    #[default]
     Table,
     Json,
     Yaml,
@@ -118,8 +261,18 @@ async fn main() -> Result<()> {
             no_input,
             git,
             github,
+            on_conflict,
             force,
             dry_run,
+            diff,
+            format,
+            no_hooks,
+            hooks_only,
+            features,
+            disabled_features,
+            vars,
+            answers,
+            replay,
             auth,
             payments,
             database,
@@ -137,8 +290,21 @@ async fn main() -> Result<()> {
                 no_input,
                 git,
                 github,
-                force,
+                on_conflict: if force {
+                    Some(ConflictMode::Overwrite)
+                } else {
+                    on_conflict
+                },
                 dry_run,
+                diff,
+                format,
+                no_hooks,
+                hooks_only,
+                features,
+                disabled_features,
+                vars,
+                answers,
+                replay,
                 verbose: cli.verbose,
                 auth,
                 payments,
@@ -152,6 +318,78 @@ async fn main() -> Result<()> {
             })
             .await?;
         }
+        Commands::Update {
+            path,
+            from,
+            no_input,
+            dry_run,
+            vars,
+        } => {
+            update_project(UpdateOptions {
+                path,
+                from,
+                no_input,
+                dry_run,
+                vars,
+                verbose: cli.verbose,
+            })
+            .await?;
+        }
+        Commands::Add {
+            category,
+            provider,
+            path,
+            no_input,
+            dry_run,
+            no_hooks,
+            on_conflict,
+        } => {
+            add_service(AddOptions {
+                category,
+                provider,
+                path,
+                no_input,
+                dry_run,
+                no_hooks,
+                on_conflict,
+                verbose: cli.verbose,
+            })
+            .await?;
+        }
+        Commands::Remove {
+            category,
+            path,
+            no_input,
+            dry_run,
+        } => {
+            remove_service(RemoveOptions {
+                category,
+                path,
+                no_input,
+                dry_run,
+                verbose: cli.verbose,
+            })
+            .await?;
+        }
+        Commands::Diff {
+            path,
+            from,
+            no_input,
+            vars,
+            name_only,
+            exit_code,
+        } => {
+            diff_project(DiffOptions {
+                path,
+                from,
+                no_input,
+                vars,
+                name_only,
+                exit_code,
+                verbose: cli.verbose,
+            })
+            .await?;
+        }
         Commands::List { language, format } => {
             list_templates(language, format).await?;
         }
@@ -163,7 +401,7 @@ async fn main() -> Result<()> {
     Ok(())
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 struct CreateOptions {
     name: String,
     template: Option<String>,
@@ -171,8 +409,17 @@ struct CreateOptions {
     no_input: bool,
     git: bool,
     github: bool,
-    force: bool,
+    on_conflict: Option<ConflictMode>,
     dry_run: bool,
+    diff: bool,
+    format: OutputFormat,
+    no_hooks: bool,
+    hooks_only: bool,
+    features: Vec<String>,
+    disabled_features: Vec<String>,
+    vars: Vec<(String, String)>,
+    answers: Option<PathBuf>,
+    replay: Option<PathBuf>,
     verbose: bool,
     // Service selections
     auth: Option<String>,
@@ -187,11 +434,48 @@ struct CreateOptions {
 }
 
 async fn create_project(options: CreateOptions) -> Result<()> {
-    println!(
-        "{} Creating project '{}'...",
-        "🛠️".bright_blue(),
-        options.name.bright_green()
-    );
+    // A machine-readable report must be the only thing on stdout
+    let quiet = options.format != OutputFormat::Table;
+    if !quiet {
+        println!(
+            "{} Creating project '{}'...",
+            "🛠️".bright_blue(),
+            options.name.bright_green()
+        );
+    }
+
+    let mut options = options;
+    let replay = match &options.replay {
+        Some(path) => Some(
+            ProjectAnswers::from_file(path)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to load replay file: {}", e))?,
+        ),
+        None => None,
+    };
+
+    if let Some(replay) = &replay {
+        match &options.template {
+            Some(template) if *template != replay.template => {
+                return Err(anyhow::anyhow!(
+                    "--template '{}' does not match the replayed template '{}'",
+                    template,
+                    replay.template
+                ));
+            }
+            _ => options.template = Some(replay.template.clone()),
+        }
+        if options.language.is_none() {
+            options.language = replay.language.clone();
+        }
+        if !quiet {
+            println!(
+                "{} Replaying answers for template '{}'",
+                "🔁".bright_blue(),
+                replay.template.bright_yellow()
+            );
+        }
+    }
 
     let template_name = match &options.template {
         Some(name) => name.clone(),
@@ -210,7 +494,7 @@ async fn create_project(options: CreateOptions) -> Result<()> {
         }
     };
 
-    let output_dir = if options.output == PathBuf::from(".") {
+    let output_dir = if options.output == std::path::Path::new(".") {
         std::env::current_dir()?.join(&options.name)
     } else {
         options.output.join(&options.name)
@@ -247,6 +531,7 @@ async fn create_project(options: CreateOptions) -> Result<()> {
     let template_config = TemplateConfig::from_file(&config_path)
         .await
         .map_err(|e| anyhow::anyhow!("Failed to load template config: {}", e))?;
+    warn_bare_feature_conditions(&template_config);
 
     if options.verbose {
         println!(
@@ -262,13 +547,93 @@ async fn create_project(options: CreateOptions) -> Result<()> {
         );
     }
 
-    let context = build_context(&template_config, &options).await?;
+    if let Some(replay) = &replay {
+        if replay.template_version != template_config.version {
+            eprintln!(
+                "{} Answers were recorded with {} v{}, but the template is now v{}. The result may differ.",
+                "⚠️".bright_yellow(),
+                replay.template,
+                replay.template_version,
+                template_config.version
+            );
+        }
+    }
+
+    let mut context = build_context(&template_config, &options, replay.as_ref()).await?;
+
+    if options.hooks_only {
+        if !output_dir.is_dir() {
+            return Err(anyhow::anyhow!(
+                "Output directory '{}' does not exist. --hooks-only runs hooks against an existing project.",
+                output_dir.display()
+            ));
+        }
+
+        // Services contribute hooks too, so they are selected as for a full run
+        let services = match &replay {
+            Some(replay) => replay_service_selections(replay, &options)?,
+            None => collect_service_selections_interactive(&template_config, &options).await?,
+        };
+        record_service_selections(&mut context, &services);
+        select_features(
+            &template_config,
+            &mut context,
+            &options,
+            false,
+            replay.as_ref(),
+        )?;
+
+        let templates_dir = find_templates_directory()?;
+        let template_hooks =
+            CompositionEngine::new(templates_dir.clone(), templates_dir.join("shared"))
+                .collect_hooks(&template_config, &services)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to collect hooks: {}", e))?;
+        let mut engine = TemplateEngine::new_for_testing()
+            .map_err(|e| anyhow::anyhow!("Failed to create template engine: {}", e))?;
+        let hooks = engine
+            .render_hooks(&template_hooks, &context)
+            .map_err(|e| anyhow::anyhow!("Failed to render hooks: {}", e))?;
+        let pre_generate_hooks = hooks.pre_generate.unwrap_or_default();
+        let post_generate_hooks = hooks.post_generate.unwrap_or_default();
+
+        run_template_hooks(
+            HookStage::PreGenerate,
+            &pre_generate_hooks,
+            &output_dir,
+            &context,
+            &options,
+            None,
+        )
+        .await?;
+        run_template_hooks(
+            HookStage::PostGenerate,
+            &post_generate_hooks,
+            &output_dir,
+            &context,
+            &options,
+            None,
+        )
+        .await?;
+
+        println!("{} Hooks completed successfully!", "✅".bright_green());
+        return Ok(());
+    }
+
+    let conflict_mode = options.on_conflict.unwrap_or_default();
+    if conflict_mode == ConflictMode::Prompt && options.no_input {
+        return Err(anyhow::anyhow!(
+            "--on-conflict prompt cannot be combined with --no-input"
+        ));
+    }
 
     let generator = if options.dry_run {
         FileGenerator::new_dry_run(&output_dir)
     } else {
-        FileGenerator::new(&output_dir)
-    };
+        FileGenerator::new(&output_dir).with_manifest()
+    }
+    .with_conflict_mode(conflict_mode)
+    .with_conflict_prompt(Box::new(prompt_conflict_mode));
 
     let dir_status = generator
         .check_output_directory()
@@ -276,24 +641,28 @@ async fn create_project(options: CreateOptions) -> Result<()> {
         .map_err(|e| anyhow::anyhow!("Failed to check output directory: {}", e))?;
 
     match dir_status {
+        // A diff only reads the directory, so whatever is there is fine
+        _ if options.diff => {}
         anvil_engine::generator::DirectoryStatus::ExistsWithContent => {
-            if !options.force {
+            if options.on_conflict.is_none() {
                 return Err(anyhow::anyhow!(
-                    "Output directory '{}' already exists and is not empty. Use --force to overwrite.",
+                    "Output directory '{}' already exists and is not empty. Use --on-conflict <overwrite|skip|prompt|merge|backup> to generate into it.",
                     output_dir.display()
                 ));
             }
 
-            if !options.dry_run {
-                println!("{} Cleaning existing directory...", "🧹".bright_yellow());
-                generator
-                    .clean_output_directory()
-                    .await
-                    .map_err(|e| anyhow::anyhow!("Failed to clean output directory: {}", e))?;
+            if !quiet {
+                println!(
+                    "{} Generating into existing directory (on conflict: {})...",
+                    "📁".bright_yellow(),
+                    conflict_mode
+                );
             }
         }
         anvil_engine::generator::DirectoryStatus::DoesNotExist => {
-            println!("{} Creating new directory...", "📁".bright_blue());
+            if !quiet {
+                println!("{} Creating new directory...", "📁".bright_blue());
+            }
         }
         anvil_engine::generator::DirectoryStatus::ExistsEmpty => {
             if options.verbose {
@@ -302,7 +671,7 @@ async fn create_project(options: CreateOptions) -> Result<()> {
         }
     }
 
-    let mut engine = TemplateEngine::new_for_testing()
+    let engine = TemplateEngine::new_for_testing()
         .map_err(|e| anyhow::anyhow!("Failed to create template engine: {}", e))?;
 
     engine
@@ -310,55 +679,68 @@ async fn create_project(options: CreateOptions) -> Result<()> {
         .map_err(|e| anyhow::anyhow!("Context validation failed: {}", e))?;
 
     // Check if services are specified to use composition
-    let services = collect_service_selections_interactive(&template_config, &options).await?;
-
-    let processed_template = if !services.is_empty() {
-        println!(
-            "{} Using template composition with {} services...",
-            "⚙️".bright_blue(),
-            services.len()
-        );
-
-        let templates_dir = find_templates_directory()?;
-        let shared_dir = templates_dir.join("shared");
-
-        let composition_engine = CompositionEngine::new(templates_dir, shared_dir);
-
-        if options.verbose {
-            println!(
-                "{} Composing template with {} services...",
-                "🔧".bright_blue(),
-                services.len()
-            );
-        }
-
-        let composed = composition_engine
-            .compose_template_with_context(&template_name, services, Some(&context))
-            .await
-            .map_err(|e| anyhow::anyhow!("Template composition failed: {}", e))?;
+    let services = match &replay {
+        Some(replay) => replay_service_selections(replay, &options)?,
+        None => collect_service_selections_interactive(&template_config, &options).await?,
+    };
 
-        if options.verbose {
-            println!(
-                "{} Composition complete, processing {} files...",
-                "✅".bright_green(),
-                composed.files.len()
-            );
-        }
+    record_service_selections(&mut context, &services);
+    select_features(
+        &template_config,
+        &mut context,
+        &options,
+        !options.no_input && replay.is_none(),
+        replay.as_ref(),
+    )?;
+
+    let project_answers = ProjectAnswers::capture(
+        &template_config,
+        &context,
+        &services,
+        &find_templates_directory()?.join("shared"),
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("Failed to record answers: {}", e))?;
+
+    let mut processed_template = render_template(
+        &find_templates_directory()?,
+        &template_name,
+        &template_config,
+        &context,
+        services,
+        options.verbose,
+        quiet,
+    )
+    .await?;
+
+    processed_template.files.push(
+        project_answers
+            .to_file()
+            .map_err(|e| anyhow::anyhow!("Failed to record answers: {}", e))?,
+    );
 
-        // Convert ComposedTemplate to ProcessedTemplate
-        engine
-            .process_composed_template(composed, &context)
+    if options.diff {
+        let diff = diff_directory(&processed_template, &output_dir)
             .await
-            .map_err(|e| anyhow::anyhow!("Template processing failed: {}", e))?
-    } else {
-        println!("{} Processing template files...", "⚙️".bright_blue());
-        engine
-            .process_template(&template_dir, &context)
-            .await
-            .map_err(|e| anyhow::anyhow!("Template processing failed: {}", e))?
-    };
+            .map_err(|e| {
+                anyhow::anyhow!("Failed to compare with {}: {}", output_dir.display(), e)
+            })?;
+        print_project_diff(&diff, false);
+        return Ok(());
+    }
 
-    let progress_callback = if !options.verbose {
+    let pre_generate_hooks = processed_template
+        .hooks
+        .pre_generate
+        .clone()
+        .unwrap_or_default();
+    let post_generate_hooks = processed_template
+        .hooks
+        .post_generate
+        .clone()
+        .unwrap_or_default();
+
+    let progress_callback = if !options.verbose && !quiet {
         Some(Box::new(|current: usize, total: usize, _msg: &str| {
             print!(
                 "\r{} Processing files: {}/{}",
@@ -372,99 +754,1429 @@ async fn create_project(options: CreateOptions) -> Result<()> {
         None
     };
 
-    let result = generator
-        .generate_files(processed_template, progress_callback)
-        .await
-        .map_err(|e| anyhow::anyhow!("File generation failed: {}", e))?;
+    // Pre-generate hooks run in the output directory itself, so whatever they add is
+    // removed again if the run does not finish
+    let snapshot = if !options.dry_run && !options.no_hooks && !pre_generate_hooks.is_empty() {
+        let snapshot = OutputSnapshot::take(&output_dir)?;
+        tokio::fs::create_dir_all(&output_dir).await?;
+        Some(snapshot)
+    } else {
+        None
+    };
+    let pre_generate = tokio::select! {
+        result = run_template_hooks(
+            HookStage::PreGenerate,
+            &pre_generate_hooks,
+            &output_dir,
+            &context,
+            &options,
+            None,
+        ) => result,
+        _ = tokio::signal::ctrl_c() => {
+            Err(anyhow::anyhow!("Interrupted while running pre-generate hooks"))
+        }
+    };
+    let generated = match pre_generate {
+        Ok(()) => generate_interruptibly(&generator, processed_template, progress_callback).await,
+        Err(e) => Err(e),
+    };
+    let result = match generated {
+        Ok(result) => result,
+        Err(e) => {
+            if let Some(snapshot) = &snapshot {
+                snapshot.restore().await.map_err(|restore_error| {
+                    anyhow::anyhow!(
+                        "{}; removing what the pre-generate hooks added also failed: {}",
+                        e,
+                        restore_error
+                    )
+                })?;
+            }
+            return Err(e);
+        }
+    };
+
+    if options.dry_run && quiet {
+        print_generation_report(&result, &options.format)?;
+    } else if options.dry_run {
+        println!("{} Dry run completed successfully!", "✅".bright_green());
+        println!(
+            "  {} {} files would be created",
+            "📄".bright_blue(),
+            result.files_created
+        );
+        println!(
+            "  {} {} directories would be created",
+            "📁".bright_blue(),
+            result.directories_created
+        );
+        println!(
+            "  {} {} bytes would be written",
+            "💾".bright_blue(),
+            result.bytes_written
+        );
+        print_file_tree(&result.files);
+        print_conflict_report(&result);
+    } else {
+        println!("{} Project created successfully!", "✅".bright_green());
+        println!(
+            "  {} {} files created",
+            "📄".bright_blue(),
+            result.files_created
+        );
+        println!(
+            "  {} {} directories created",
+            "📁".bright_blue(),
+            result.directories_created
+        );
+        println!(
+            "  {} {} bytes written",
+            "💾".bright_blue(),
+            result.bytes_written
+        );
+        print_conflict_report(&result);
+        println!(
+            "  {} Location: {}",
+            "📍".bright_blue(),
+            result
+                .output_directory
+                .display()
+                .to_string()
+                .bright_yellow()
+        );
+    }
+
+    run_template_hooks(
+        HookStage::PostGenerate,
+        &post_generate_hooks,
+        &output_dir,
+        &context,
+        &options,
+        Some(&result),
+    )
+    .await?;
+
+    if options.git && !options.dry_run {
+        println!(
+            "{} Git initialization is a WIP",
+            "🔮".bright_magenta()
+        );
+    }
+
+    if options.github && !options.dry_run {
+        println!(
+            "{} GitHub integration is a WIP",
+            "🔮".bright_magenta()
+        );
+    }
+
+    Ok(())
+}
+
+#[derive(Debug)]
+struct AddOptions {
+    category: ServiceCategory,
+    provider: String,
+    path: PathBuf,
+    no_input: bool,
+    dry_run: bool,
+    no_hooks: bool,
+    on_conflict: ConflictMode,
+    verbose: bool,
+}
+
+#[derive(Debug)]
+struct RemoveOptions {
+    category: ServiceCategory,
+    path: PathBuf,
+    no_input: bool,
+    dry_run: bool,
+    verbose: bool,
+}
+
+#[derive(Debug)]
+struct DiffOptions {
+    path: PathBuf,
+    from: Option<PathBuf>,
+    no_input: bool,
+    vars: Vec<(String, String)>,
+    name_only: bool,
+    exit_code: bool,
+    verbose: bool,
+}
+
+#[derive(Debug)]
+struct UpdateOptions {
+    path: PathBuf,
+    from: Option<PathBuf>,
+    no_input: bool,
+    dry_run: bool,
+    vars: Vec<(String, String)>,
+    verbose: bool,
+}
+
+async fn update_project(options: UpdateOptions) -> Result<()> {
+    let (project_dir, project_name, answers) = load_project(&options.path).await?;
+
+    let templates_dir = find_templates_directory()?;
+    let target_config = load_template_config(&templates_dir, &answers.template).await?;
+
+    let base_templates_dir = match &options.from {
+        Some(dir) => dir.clone(),
+        None if target_config.version == answers.template_version => templates_dir.clone(),
+        None => {
+            return Err(anyhow::anyhow!(
+                "The project was generated from {} v{}, but the available template is v{}. \
+                 Pass --from <DIR> with a templates directory containing v{} so local changes can be merged.",
+                answers.template,
+                answers.template_version,
+                target_config.version,
+                answers.template_version
+            ));
+        }
+    };
+    let base_config = load_template_config(&base_templates_dir, &answers.template).await?;
+    if base_config.version != answers.template_version {
+        println!(
+            "{} The project was generated from v{}, but --from provides v{}. Merges may be less accurate.",
+            "⚠️".bright_yellow(),
+            answers.template_version,
+            base_config.version
+        );
+    }
+
+    println!(
+        "{} Updating '{}' from {} v{} to v{}...",
+        "🔄".bright_blue(),
+        project_name.bright_green(),
+        answers.template,
+        answers.template_version,
+        target_config.version.bright_yellow()
+    );
+
+    let replay_options = CreateOptions {
+        name: project_name,
+        template: Some(answers.template.clone()),
+        no_input: options.no_input,
+        vars: options.vars.clone(),
+        language: answers.language.clone(),
+        verbose: options.verbose,
+        ..Default::default()
+    };
+
+    // New variables are prompted for (or defaulted) once, for the target version
+    let (target, target_context) =
+        render_from_answers(&templates_dir, &target_config, &answers, &replay_options).await?;
+
+    // Values that were never recorded (secrets and passwords) are shared with the target render
+    let mut base_answers = answers.clone();
+    for variable in &base_config.variables {
+        if !base_answers.variables.contains_key(&variable.name) {
+            if let Some(value) = target_context.get_variable(&variable.name) {
+                base_answers
+                    .variables
+                    .insert(variable.name.clone(), value.clone());
+            }
+        }
+    }
+    let base_options = CreateOptions {
+        no_input: true,
+        ..replay_options
+    };
+    let (mut base, _) = render_from_answers(
+        &base_templates_dir,
+        &base_config,
+        &base_answers,
+        &base_options,
+    )
+    .await?;
+    // The recorded answers are the base for the answers file itself
+    if let Some(file) = base
+        .files
+        .iter_mut()
+        .find(|file| file.output_path == std::path::Path::new(ANSWERS_FILE))
+    {
+        file.content = answers
+            .to_yaml()
+            .map_err(|e| anyhow::anyhow!("Failed to record answers: {}", e))?
+            .into();
+    }
+
+    let updater = ProjectUpdater::new(&project_dir);
+    let plan = updater
+        .plan(&base, &target)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to plan update: {}", e))?;
+
+    if plan.is_empty() {
+        println!("{} Project is already up to date", "✅".bright_green());
+        return Ok(());
+    }
+
+    print_update_plan(&plan);
+
+    if options.dry_run {
+        println!(
+            "{} Dry run: {} files would change",
+            "🔍".bright_blue(),
+            plan.files.len()
+        );
+        return Ok(());
+    }
+
+    updater
+        .apply(&plan)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to apply update: {}", e))?;
+
+    // Projects generated before the manifest existed start from what the old template produced
+    let mut manifest = match load_manifest(&project_dir).await? {
+        Some(manifest) => manifest,
+        None => Manifest::from_files(&base.files),
+    };
+    plan.update_manifest(&mut manifest);
+    write_manifest(&project_dir, &manifest).await?;
+
+    let conflicted: Vec<String> = plan
+        .conflicts()
+        .map(|file| file.path.display().to_string())
+        .collect();
+    if conflicted.is_empty() {
+        println!(
+            "{} Project updated to {} v{}",
+            "✅".bright_green(),
+            answers.template,
+            target_config.version
+        );
+    } else {
+        println!(
+            "{} Project updated to {} v{} with conflicts in {} file{}. \
+             Resolve the <<<<<<< / >>>>>>> markers in: {}",
+            "⚠️".bright_yellow(),
+            answers.template,
+            target_config.version,
+            conflicted.len(),
+            if conflicted.len() == 1 { "" } else { "s" },
+            conflicted.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/* Prints one line per planned file change */
+fn print_update_plan(plan: &UpdatePlan) {
+    for file in &plan.files {
+        let path = file.path.display().to_string();
+        match &file.kind {
+            FileUpdateKind::Added => println!("  {} {}", "added     ".bright_green(), path),
+            FileUpdateKind::Updated => println!("  {} {}", "updated   ".bright_blue(), path),
+            FileUpdateKind::Merged => println!("  {} {}", "merged    ".bright_cyan(), path),
+            FileUpdateKind::Conflicted { conflicts } => println!(
+                "  {} {} ({} conflict{})",
+                "conflict  ".bright_red(),
+                path,
+                conflicts,
+                if *conflicts == 1 { "" } else { "s" }
+            ),
+            FileUpdateKind::Removed => println!("  {} {}", "removed   ".bright_yellow(), path),
+            FileUpdateKind::Skipped { reason } => {
+                println!("  {} {} ({})", "skipped   ".dimmed(), path, reason)
+            }
+        }
+    }
+}
+
+/*
+Renders the project's template from its recorded answers and shows where the files
+on disk differ from it.
+*/
+async fn diff_project(options: DiffOptions) -> Result<()> {
+    let (project_dir, project_name, answers) = load_project(&options.path).await?;
+
+    let templates_dir = match &options.from {
+        Some(dir) => dir.clone(),
+        None => find_templates_directory()?,
+    };
+    let config = load_template_config(&templates_dir, &answers.template).await?;
+
+    println!(
+        "{} Comparing '{}' with {} v{} (generated from v{})...",
+        "🔍".bright_blue(),
+        project_name.bright_green(),
+        answers.template,
+        config.version,
+        answers.template_version
+    );
+
+    let replay_options = CreateOptions {
+        name: project_name,
+        template: Some(answers.template.clone()),
+        no_input: options.no_input,
+        vars: options.vars.clone(),
+        language: answers.language.clone(),
+        verbose: options.verbose,
+        ..Default::default()
+    };
+
+    // Secrets are never recorded, so instead of asking for them again they render as a
+    // marker, and the files that use one are left out of the comparison
+    let mut replay_answers = answers.clone();
+    let mut secret_markers = Vec::new();
+    for variable in &config.variables {
+        let VariableType::Secret { min_length } = variable.var_type else {
+            continue;
+        };
+        if replay_answers.variables.contains_key(&variable.name)
+            || options.vars.iter().any(|(name, _)| *name == variable.name)
+        {
+            continue;
+        }
+        let marker = format!(
+            "{:*<1$}",
+            format!("anvil-secret-{}", variable.name),
+            min_length
+        );
+        replay_answers
+            .variables
+            .insert(variable.name.clone(), marker.clone().into());
+        secret_markers.push(marker);
+    }
+
+    let (rendered, _) =
+        render_from_answers(&templates_dir, &config, &replay_answers, &replay_options).await?;
+    let uses_secrets: Vec<PathBuf> = rendered
+        .files
+        .iter()
+        .filter(|file| {
+            file.content
+                .as_text()
+                .is_some_and(|text| secret_markers.iter().any(|marker| text.contains(marker)))
+        })
+        .map(|file| file.output_path.clone())
+        .collect();
+
+    let mut diff = diff_directory(&rendered, &project_dir)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to compare with the template: {}", e))?;
+    diff.files.retain(|file| !uses_secrets.contains(&file.path));
+    if !uses_secrets.is_empty() {
+        println!(
+            "{} Not comparing {} (uses secrets that are not recorded; pass them with --var to compare)",
+            "ℹ️".bright_blue(),
+            uses_secrets
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+    }
+    print_project_diff(&diff, options.name_only);
+
+    if options.exit_code && !diff.is_clean() {
+        return Err(anyhow::anyhow!(
+            "'{}' differs from its template",
+            project_dir.display()
+        ));
+    }
+    Ok(())
+}
+
+/* Prints each differing file, followed by its unified diff unless `name_only` is set */
+fn print_project_diff(diff: &ProjectDiff, name_only: bool) {
+    if diff.is_clean() {
+        println!("{} No differences", "✅".bright_green());
+        return;
+    }
+
+    for file in &diff.files {
+        match &file.kind {
+            FileDiffKind::Unchanged => {}
+            FileDiffKind::Added => {
+                println!("  {} {}", "new      ".bright_green(), file.path.display())
+            }
+            FileDiffKind::Extra => {
+                println!("  {} {}", "extra    ".dimmed(), file.path.display())
+            }
+            FileDiffKind::ExtraDirectory => {
+                println!("  {} {}/", "extra    ".dimmed(), file.path.display())
+            }
+            FileDiffKind::Changed { patch } => {
+                println!("  {} {}", "changed  ".bright_yellow(), file.path.display());
+                if !name_only {
+                    print_patch(patch);
+                }
+            }
+        }
+    }
+
+    println!(
+        "{} {} new, {} changed, {} not produced by the template",
+        "📋".bright_blue(),
+        diff.added().count(),
+        diff.changed().count(),
+        diff.extra().count()
+    );
+}
+
+fn print_patch(patch: &str) {
+    for line in patch.lines() {
+        if line.starts_with("+++") || line.starts_with("---") {
+            println!("{}", line.bold());
+        } else if line.starts_with('+') {
+            println!("{}", line.green());
+        } else if line.starts_with('-') {
+            println!("{}", line.red());
+        } else if line.starts_with("@@") {
+            println!("{}", line.cyan());
+        } else {
+            println!("{}", line);
+        }
+    }
+}
+
+/*
+Writes the generated files, and if Ctrl-C arrives before they have been moved into
+place, throws the staged copies away so the output directory is left as it was.
+*/
+async fn generate_interruptibly(
+    generator: &FileGenerator,
+    template: ProcessedTemplate,
+    progress_callback: Option<ProgressCallback>,
+) -> Result<GenerationResult> {
+    tokio::select! {
+        result = generator.generate_files(template, progress_callback) => {
+            result.map_err(|e| anyhow::anyhow!("File generation failed: {}", e))
+        }
+        _ = tokio::signal::ctrl_c() => {
+            generator
+                .discard_staging()
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to clean up staged files: {}", e))?;
+            Err(anyhow::anyhow!(
+                "Generation interrupted; {} was left unchanged",
+                generator.output_directory().display()
+            ))
+        }
+    }
+}
+
+/*
+What the output directory held before pre-generate hooks ran in it, so that a run
+that fails afterwards can remove what the hooks added: the whole directory if it
+did not exist, otherwise any new top-level entries.
+*/
+struct OutputSnapshot {
+    dir: PathBuf,
+    entries: Option<std::collections::HashSet<std::ffi::OsString>>,
+}
+
+impl OutputSnapshot {
+    fn take(dir: &std::path::Path) -> Result<Self> {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => Some(
+                entries
+                    .map(|entry| entry.map(|entry| entry.file_name()))
+                    .collect::<std::io::Result<_>>()?,
+            ),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            entries,
+        })
+    }
+
+    async fn restore(&self) -> Result<()> {
+        let Some(entries) = &self.entries else {
+            return match tokio::fs::remove_dir_all(&self.dir).await {
+                Ok(()) => Ok(()),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+                Err(e) => Err(e.into()),
+            };
+        };
+
+        let mut current = tokio::fs::read_dir(&self.dir).await?;
+        while let Some(entry) = current.next_entry().await? {
+            if entries.contains(&entry.file_name()) {
+                continue;
+            }
+            if entry.file_type().await?.is_dir() {
+                tokio::fs::remove_dir_all(entry.path()).await?;
+            } else {
+                tokio::fs::remove_file(entry.path()).await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/* Prints the dry-run report as JSON or YAML for tooling */
+fn print_generation_report(result: &GenerationResult, format: &OutputFormat) -> Result<()> {
+    let report = match format {
+        OutputFormat::Json => serde_json::to_string_pretty(result)?,
+        OutputFormat::Yaml => serde_yaml::to_string(result)?,
+        OutputFormat::Table => return Ok(()),
+    };
+    println!("{}", report);
+    Ok(())
+}
+
+#[derive(Default)]
+struct FileTree<'a> {
+    directories: std::collections::BTreeMap<String, FileTree<'a>>,
+    files: std::collections::BTreeMap<String, &'a FileReport>,
+}
+
+impl<'a> FileTree<'a> {
+    fn build(files: &'a [FileReport]) -> Self {
+        let mut root = Self::default();
+        for file in files {
+            let mut components: Vec<String> = file
+                .path
+                .components()
+                .map(|component| component.as_os_str().to_string_lossy().into_owned())
+                .collect();
+            let Some(name) = components.pop() else {
+                continue;
+            };
+            let mut directory = &mut root;
+            for component in components {
+                directory = directory.directories.entry(component).or_default();
+            }
+            directory.files.insert(name, file);
+        }
+        root
+    }
+
+    /* Flattens the tree into drawn lines, directories before files */
+    fn lines(&self, prefix: &str, lines: &mut Vec<(String, Option<&'a FileReport>)>) {
+        let count = self.directories.len() + self.files.len();
+        let mut index = 0;
+        for (name, directory) in &self.directories {
+            index += 1;
+            let (branch, indent) = if index == count {
+                ("└── ", "    ")
+            } else {
+                ("├── ", "│   ")
+            };
+            lines.push((format!("{}{}{}/", prefix, branch, name), None));
+            directory.lines(&format!("{}{}", prefix, indent), lines);
+        }
+        for (name, file) in &self.files {
+            index += 1;
+            let branch = if index == count {
+                "└── "
+            } else {
+                "├── "
+            };
+            lines.push((format!("{}{}{}", prefix, branch, name), Some(*file)));
+        }
+    }
+}
+
+/*
+Prints the files as a tree, each with its size, where it came from (base template,
+a service, or a merge of several) and whether it will be executable.
+*/
+fn print_file_tree(files: &[FileReport]) {
+    let mut lines = Vec::new();
+    FileTree::build(files).lines("", &mut lines);
+    let width = lines
+        .iter()
+        .map(|(tree, _)| tree.chars().count())
+        .max()
+        .unwrap_or(0);
+
+    for (tree, file) in lines {
+        let Some(file) = file else {
+            println!("  {}", tree.bright_blue());
+            continue;
+        };
+
+        let mut provenance = file.source.to_string();
+        if let Some(strategy) = &file.merge_strategy {
+            provenance.push_str(&format!(" [{:?}]", strategy).to_lowercase());
+        }
+        let mut notes = Vec::new();
+        if file.executable {
+            notes.push("executable".bright_green().to_string());
+        }
+        if file.status != FileStatus::Create {
+            let status = format!("{:?}", file.status).to_lowercase();
+            notes.push(status.bright_yellow().to_string());
+        }
+
+        let mut line = format!(
+            "  {}{}  {:>9}  {}",
+            tree,
+            " ".repeat(width - tree.chars().count()),
+            format_size(file.size),
+            provenance.dimmed()
+        );
+        if !notes.is_empty() {
+            line.push_str(&format!("  {}", notes.join(" ")));
+        }
+        println!("{}", line);
+    }
+}
+
+fn format_size(bytes: u64) -> String {
+    match bytes {
+        0..=1023 => format!("{} B", bytes),
+        1024..=1_048_575 => format!("{:.1} KB", bytes as f64 / 1024.0),
+        _ => format!("{:.1} MB", bytes as f64 / 1_048_576.0),
+    }
+}
+
+/* Lists the existing files generation overwrote, skipped, merged, or backed up */
+fn print_conflict_report(result: &GenerationResult) {
+    for path in &result.overwritten_files {
+        println!("  {} {}", "overwritten".bright_yellow(), path.display());
+    }
+    for path in &result.skipped_files {
+        println!("  {} {}", "skipped    ".dimmed(), path.display());
+    }
+    for path in &result.pending_files {
+        println!("  {} {}", "would ask  ".bright_magenta(), path.display());
+    }
+    for path in &result.merged_files {
+        if result.conflicted_files.contains(path) {
+            println!("  {} {}", "conflict   ".bright_red(), path.display());
+        } else {
+            println!("  {} {}", "merged     ".bright_cyan(), path.display());
+        }
+    }
+    for path in &result.backup_files {
+        println!("  {} {}", "backed up  ".bright_blue(), path.display());
+    }
+
+    if !result.conflicted_files.is_empty() {
+        println!(
+            "{} {} file(s) contain conflict markers. Resolve them before continuing.",
+            "⚠️".bright_yellow(),
+            result.conflicted_files.len()
+        );
+    }
+}
+
+/* Asks what to do with one generated file that clashes with an existing one */
+fn prompt_conflict_mode(path: &std::path::Path) -> anvil_engine::EngineResult<ConflictMode> {
+    let choices = vec![
+        ConflictMode::Skip,
+        ConflictMode::Overwrite,
+        ConflictMode::Merge,
+        ConflictMode::Backup,
+    ];
+    inquire::Select::new(
+        &format!("{} already exists with different content:", path.display()),
+        choices,
+    )
+    .prompt()
+    .map_err(|e| EngineError::cancelled(format!("no choice made for {}: {}", path.display(), e)))
+}
+
+async fn load_manifest(project_dir: &std::path::Path) -> Result<Option<Manifest>> {
+    let manifest_path = project_dir.join(MANIFEST_FILE);
+    if !manifest_path.exists() {
+        return Ok(None);
+    }
+
+    Manifest::from_file(&manifest_path)
+        .await
+        .map(Some)
+        .map_err(|e| anyhow::anyhow!("Failed to load {}: {}", MANIFEST_FILE, e))
+}
+
+async fn write_manifest(project_dir: &std::path::Path, manifest: &Manifest) -> Result<()> {
+    let content = manifest
+        .to_json()
+        .map_err(|e| anyhow::anyhow!("Failed to write manifest: {}", e))?;
+    tokio::fs::write(project_dir.join(MANIFEST_FILE), content).await?;
+    Ok(())
+}
+
+async fn remove_service(options: RemoveOptions) -> Result<()> {
+    let (project_dir, project_name, answers) = load_project(&options.path).await?;
+    let category_name = format!("{:?}", options.category).to_lowercase();
+
+    let service = answers
+        .service_selections()
+        .into_iter()
+        .find(|service| service.category == options.category && service.provider != "none")
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "'{}' has no {} service to remove",
+                project_name,
+                category_name
+            )
+        })?;
+
+    let mut manifest = load_manifest(&project_dir).await?.ok_or_else(|| {
+        anyhow::anyhow!(
+            "{} not found. The project predates file provenance tracking, so its {} files must be removed by hand.",
+            MANIFEST_FILE,
+            category_name
+        )
+    })?;
+
+    println!(
+        "{} Removing {} '{}' from '{}'...",
+        "➖".bright_blue(),
+        category_name,
+        service.provider.bright_yellow(),
+        project_name.bright_green()
+    );
+
+    let templates_dir = find_templates_directory()?;
+    let template_config = load_template_config(&templates_dir, &answers.template).await?;
+    if let Some(definition) = template_config
+        .services
+        .iter()
+        .find(|definition| definition.category == options.category && definition.required)
+    {
+        return Err(anyhow::anyhow!(
+            "Template '{}' requires the {} service",
+            answers.template,
+            definition.name
+        ));
+    }
+    if template_config.version != answers.template_version {
+        println!(
+            "{} The project was generated from {} v{}, but the available template is v{}. \
+             Consider running 'anvil update' first.",
+            "⚠️".bright_yellow(),
+            answers.template,
+            answers.template_version,
+            template_config.version
+        );
+    }
+
+    let mut remaining_answers = answers.clone();
+    remaining_answers
+        .services
+        .retain(|recorded| recorded.category != options.category);
+
+    let replay_options = CreateOptions {
+        name: project_name,
+        template: Some(answers.template.clone()),
+        no_input: options.no_input,
+        language: answers.language.clone(),
+        verbose: options.verbose,
+        ..Default::default()
+    };
+
+    // What the project looks like without the service, and what the service contributed
+    let (remaining, remaining_context) = render_from_answers(
+        &templates_dir,
+        &template_config,
+        &remaining_answers,
+        &replay_options,
+    )
+    .await?;
+
+    let remaining_services = remaining_answers.service_selections();
+    let mut service_context = remaining_context.clone();
+    let mut all_services = remaining_services.clone();
+    all_services.push(service.clone());
+    record_service_selections(&mut service_context, &all_services);
+
+    let composition_engine =
+        CompositionEngine::new(templates_dir.clone(), templates_dir.join("shared"));
+    let composed = composition_engine
+        .compose_service_addition(
+            &answers.template,
+            &remaining_services,
+            service,
+            Some(&service_context),
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to render {} files: {}", category_name, e))?;
+    let mut engine = TemplateEngine::new_for_testing()
+        .map_err(|e| anyhow::anyhow!("Failed to create template engine: {}", e))?;
+    let service_files = engine
+        .process_composed_template(composed, &service_context)
+        .await
+        .map_err(|e| anyhow::anyhow!("Template processing failed: {}", e))?
+        .files;
+
+    let updater = ProjectUpdater::new(&project_dir);
+    let plan = updater
+        .plan_service_removal(&manifest, &options.category, &service_files, &remaining)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to plan removal: {}", e))?;
+
+    print_update_plan(&plan);
+
+    if options.dry_run {
+        println!(
+            "{} Dry run: {} files would change",
+            "🔍".bright_blue(),
+            plan.files.len()
+        );
+        return Ok(());
+    }
+
+    updater
+        .apply(&plan)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to apply removal: {}", e))?;
+    plan.update_manifest(&mut manifest);
+    write_manifest(&project_dir, &manifest).await?;
+    tokio::fs::write(
+        project_dir.join(ANSWERS_FILE),
+        remaining_answers
+            .to_yaml()
+            .map_err(|e| anyhow::anyhow!("Failed to record answers: {}", e))?,
+    )
+    .await?;
+
+    let skipped: Vec<String> = plan
+        .files
+        .iter()
+        .filter(|file| matches!(file.kind, FileUpdateKind::Skipped { .. }))
+        .map(|file| file.path.display().to_string())
+        .collect();
+    if skipped.is_empty() {
+        println!("{} Removed {} service", "✅".bright_green(), category_name);
+    } else {
+        println!(
+            "{} Removed {} service. These edited files were left in place; remove its code from them by hand: {}",
+            "⚠️".bright_yellow(),
+            category_name,
+            skipped.join(", ")
+        );
+    }
+
+    Ok(())
+}
+
+/*
+Locates a generated project from its recorded answers, returning its directory,
+name and answers. Projects recorded without a name fall back to the directory name.
+*/
+async fn load_project(path: &std::path::Path) -> Result<(PathBuf, String, ProjectAnswers)> {
+    let project_dir = path
+        .canonicalize()
+        .map_err(|e| anyhow::anyhow!("Project directory '{}' not found: {}", path.display(), e))?;
+    let answers_path = project_dir.join(ANSWERS_FILE);
+    if !answers_path.exists() {
+        return Err(anyhow::anyhow!(
+            "'{}' is not an Anvil project: {} not found",
+            project_dir.display(),
+            ANSWERS_FILE
+        ));
+    }
+    let answers = ProjectAnswers::from_file(&answers_path)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to load {}: {}", ANSWERS_FILE, e))?;
+
+    let recorded_name = answers
+        .variables
+        .get("project_name")
+        .and_then(|name| name.as_str())
+        .map(|name| name.to_string());
+    let project_name = recorded_name
+        .or_else(|| {
+            project_dir
+                .file_name()
+                .map(|name| name.to_string_lossy().to_string())
+        })
+        .ok_or_else(|| anyhow::anyhow!("Cannot determine the project name"))?;
+
+    Ok((project_dir, project_name, answers))
+}
+
+async fn add_service(options: AddOptions) -> Result<()> {
+    let (project_dir, project_name, answers) = load_project(&options.path).await?;
+    let category_name = format!("{:?}", options.category).to_lowercase();
+    if options.on_conflict == ConflictMode::Prompt && options.no_input {
+        return Err(anyhow::anyhow!(
+            "--on-conflict prompt cannot be combined with --no-input"
+        ));
+    }
+
+    println!(
+        "{} Adding {} '{}' to '{}'...",
+        "➕".bright_blue(),
+        category_name,
+        options.provider.bright_yellow(),
+        project_name.bright_green()
+    );
+
+    let templates_dir = find_templates_directory()?;
+    let template_config = load_template_config(&templates_dir, &answers.template).await?;
+    if template_config.version != answers.template_version {
+        println!(
+            "{} The project was generated from {} v{}, but the available template is v{}. \
+             Consider running 'anvil update' first.",
+            "⚠️".bright_yellow(),
+            answers.template,
+            answers.template_version,
+            template_config.version
+        );
+    }
+
+    let create_options = CreateOptions {
+        name: project_name,
+        template: Some(answers.template.clone()),
+        no_input: options.no_input,
+        dry_run: options.dry_run,
+        no_hooks: options.no_hooks,
+        language: answers.language.clone(),
+        verbose: options.verbose,
+        ..Default::default()
+    };
+
+    let composition_engine =
+        CompositionEngine::new(templates_dir.clone(), templates_dir.join("shared"));
+    let mut context = build_context(&template_config, &create_options, Some(&answers)).await?;
+    let existing_services = answers.service_selections();
+    let service = ServiceSelection {
+        category: options.category.clone(),
+        provider: options.provider.clone(),
+        config: prompt_for_service_configuration(
+            &options.provider,
+            &options.category,
+            &composition_engine,
+            &create_options,
+        )
+        .await?,
+    };
+
+    let mut services = existing_services.clone();
+    services.push(service.clone());
+    record_service_selections(&mut context, &services);
+    select_features(
+        &template_config,
+        &mut context,
+        &create_options,
+        false,
+        Some(&answers),
+    )?;
+
+    let composed = composition_engine
+        .compose_service_addition(
+            &answers.template,
+            &existing_services,
+            service.clone(),
+            Some(&context),
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("Cannot add {}/{}: {}", category_name, service.provider, e))?;
+
+    let mut engine = TemplateEngine::new_for_testing()
+        .map_err(|e| anyhow::anyhow!("Failed to create template engine: {}", e))?;
+    let processed = engine
+        .process_composed_template(composed, &context)
+        .await
+        .map_err(|e| anyhow::anyhow!("Template processing failed: {}", e))?;
+
+    let mut files = composition_engine
+        .merge_into_project(
+            &service,
+            processed.files,
+            &project_dir,
+            &template_config
+                .composition
+                .as_ref()
+                .map(|composition| composition.file_merging_strategy.clone())
+                .unwrap_or_default(),
+        )
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to merge service files: {}", e))?;
+
+    for file in &files {
+        let status = if !project_dir.join(&file.output_path).exists() {
+            "added   ".bright_green()
+        } else if file.merge_strategy.is_some() {
+            "merged  ".bright_cyan()
+        } else {
+            "conflict".bright_yellow()
+        };
+        println!("  {} {}", status, file.output_path.display());
+    }
+
+    let recorded = ProjectAnswers::capture(
+        &template_config,
+        &context,
+        &services,
+        &templates_dir.join("shared"),
+    )
+    .await
+    .map_err(|e| anyhow::anyhow!("Failed to record answers: {}", e))?;
+    files.push(
+        recorded
+            .to_file()
+            .map_err(|e| anyhow::anyhow!("Failed to record answers: {}", e))?,
+    );
+
+    let pre_generate_hooks = processed.hooks.pre_generate.unwrap_or_default();
+    let post_generate_hooks = processed.hooks.post_generate.unwrap_or_default();
+
+    run_template_hooks(
+        HookStage::PreGenerate,
+        &pre_generate_hooks,
+        &project_dir,
+        &context,
+        &create_options,
+        None,
+    )
+    .await?;
+
+    let generator = if options.dry_run {
+        FileGenerator::new_dry_run(&project_dir)
+    } else {
+        FileGenerator::new(&project_dir).with_manifest()
+    }
+    .with_conflict_mode(options.on_conflict)
+    .with_conflict_prompt(Box::new(prompt_conflict_mode));
+    let result = generate_interruptibly(
+        &generator,
+        ProcessedTemplate {
+            files,
+            hooks: Default::default(),
+        },
+        None,
+    )
+    .await?;
+
+    run_template_hooks(
+        HookStage::PostGenerate,
+        &post_generate_hooks,
+        &project_dir,
+        &context,
+        &create_options,
+        Some(&result),
+    )
+    .await?;
+
+    if options.dry_run {
+        println!("{} Dry run completed successfully!", "✅".bright_green());
+    } else {
+        println!(
+            "{} Added {} '{}'",
+            "✅".bright_green(),
+            category_name,
+            service.provider
+        );
+    }
+
+    Ok(())
+}
+
+async fn load_template_config(
+    templates_dir: &std::path::Path,
+    template_name: &str,
+) -> Result<TemplateConfig> {
+    let config_path = templates_dir.join(template_name).join("anvil.yaml");
+    if !config_path.exists() {
+        return Err(anyhow::anyhow!(
+            "Template '{}' not found in {}",
+            template_name,
+            templates_dir.display()
+        ));
+    }
+
+    let config = TemplateConfig::from_file(&config_path)
+        .await
+        .map_err(|e| anyhow::anyhow!("Failed to load template config: {}", e))?;
+    warn_bare_feature_conditions(&config);
+    Ok(config)
+}
+
+/* Conditions should say `feature_<name>`; a bare feature name still works */
+fn warn_bare_feature_conditions(config: &TemplateConfig) {
+    for (condition, feature) in config.bare_feature_conditions() {
+        eprintln!(
+            "{} Condition '{}' names feature '{}' without its prefix; write 'feature_{}' instead.",
+            "⚠️".bright_yellow(),
+            condition,
+            feature,
+            feature
+        );
+    }
+}
+
+/*
+Renders a template non-interactively from recorded answers, including a fresh
+answers file, and returns the context it was rendered with.
+*/
+async fn render_from_answers(
+    templates_dir: &std::path::Path,
+    config: &TemplateConfig,
+    answers: &ProjectAnswers,
+    options: &CreateOptions,
+) -> Result<(ProcessedTemplate, Context)> {
+    let mut context = build_context(config, options, Some(answers)).await?;
+    let services = replay_service_selections(answers, options)?;
+    record_service_selections(&mut context, &services);
+    select_features(config, &mut context, options, false, Some(answers))?;
+
+    let recorded =
+        ProjectAnswers::capture(config, &context, &services, &templates_dir.join("shared"))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to record answers: {}", e))?;
+    let mut processed = render_template(
+        templates_dir,
+        &answers.template,
+        config,
+        &context,
+        services,
+        options.verbose,
+        false,
+    )
+    .await?;
+    processed.files.push(
+        recorded
+            .to_file()
+            .map_err(|e| anyhow::anyhow!("Failed to record answers: {}", e))?,
+    );
+
+    Ok((processed, context))
+}
+
+/*
+Renders a template into memory, composing the selected shared services into it when
+there are any.
+*/
+async fn render_template(
+    templates_dir: &std::path::Path,
+    template_name: &str,
+    template_config: &TemplateConfig,
+    context: &Context,
+    services: Vec<ServiceSelection>,
+    verbose: bool,
+    quiet: bool,
+) -> Result<ProcessedTemplate> {
+    let mut engine = TemplateEngine::new_for_testing()
+        .map_err(|e| anyhow::anyhow!("Failed to create template engine: {}", e))?;
+
+    if !services.is_empty() {
+        if !quiet {
+            println!(
+                "{} Using template composition with {} services...",
+                "⚙️".bright_blue(),
+                services.len()
+            );
+        }
+
+        let shared_dir = templates_dir.join("shared");
+        let composition_engine = CompositionEngine::new(templates_dir.to_path_buf(), shared_dir);
+
+        if verbose {
+            println!(
+                "{} Composing template with {} services...",
+                "🔧".bright_blue(),
+                services.len()
+            );
+        }
+
+        let composed = composition_engine
+            .compose_template_with_context(template_name, services, Some(context))
+            .await
+            .map_err(|e| anyhow::anyhow!("Template composition failed: {}", e))?;
+
+        if verbose {
+            println!(
+                "{} Composition complete, processing {} files...",
+                "✅".bright_green(),
+                composed.files.len()
+            );
+        }
+
+        // Convert ComposedTemplate to ProcessedTemplate
+        engine
+            .process_composed_template(composed, context)
+            .await
+            .map_err(|e| anyhow::anyhow!("Template processing failed: {}", e))
+    } else {
+        println!("{} Processing template files...", "⚙️".bright_blue());
+        let mut processed = engine
+            .process_template(&templates_dir.join(template_name), template_config, context)
+            .await
+            .map_err(|e| anyhow::anyhow!("Template processing failed: {}", e))?;
+        processed.hooks = engine
+            .render_hooks(&template_config.hooks.clone().unwrap_or_default(), context)
+            .map_err(|e| anyhow::anyhow!("Failed to render hooks: {}", e))?;
+        Ok(processed)
+    }
+}
+
+/* Runs one stage of template hooks, honoring --no-hooks and --dry-run */
+async fn run_template_hooks(
+    stage: HookStage,
+    hooks: &[HookCommand],
+    output_dir: &std::path::Path,
+    context: &Context,
+    options: &CreateOptions,
+    generation: Option<&GenerationResult>,
+) -> Result<()> {
+    if hooks.is_empty() {
+        return Ok(());
+    }
+
+    if options.no_hooks {
+        if options.verbose {
+            println!(
+                "{} Skipping {} {} hooks (--no-hooks)",
+                "⏭️".bright_yellow(),
+                hooks.len(),
+                stage.name()
+            );
+        }
+        return Ok(());
+    }
+
+    let mut runner = HookRunner::new(output_dir);
+    if let Some(generation) = generation {
+        runner = runner.with_generation(generation.clone());
+    }
 
     if options.dry_run {
-        println!("{} Dry run completed successfully!", "✅".bright_green());
-        println!(
-            "  {} {} files would be created",
-            "📄".bright_blue(),
-            result.files_created
-        );
-        println!(
-            "  {} {} directories would be created",
-            "📁".bright_blue(),
-            result.directories_created
-        );
-        println!(
-            "  {} {} bytes would be written",
-            "💾".bright_blue(),
-            result.bytes_written
-        );
-    } else {
-        println!("{} Project created successfully!", "✅".bright_green());
-        println!(
-            "  {} {} files created",
-            "📄".bright_blue(),
-            result.files_created
-        );
-        println!(
-            "  {} {} directories created",
-            "📁".bright_blue(),
-            result.directories_created
-        );
+        if options.format != OutputFormat::Table {
+            return Ok(());
+        }
+        for hook in hooks {
+            if runner.should_run(hook, context)? {
+                println!(
+                    "  {} Would run {} hook: {}",
+                    "🪝".bright_blue(),
+                    stage.name(),
+                    hook.command.bright_yellow()
+                );
+            }
+        }
+        return Ok(());
+    }
+
+    println!("{} Running {} hooks...", "🪝".bright_blue(), stage.name());
+
+    let progress_callback = Box::new(|current: usize, total: usize, command: &str| {
         println!(
-            "  {} {} bytes written",
-            "💾".bright_blue(),
-            result.bytes_written
+            "  {} [{}/{}] {}",
+            "▶".bright_cyan(),
+            current,
+            total,
+            command.bright_yellow()
         );
+    }) as Box<dyn Fn(usize, usize, &str) + Send + Sync>;
+
+    let report = match runner
+        .run_hooks(stage, hooks, context, Some(progress_callback))
+        .await
+    {
+        Ok(report) => report,
+        Err(e) => {
+            if generation.is_some() && output_dir.exists() {
+                println!(
+                    "{} Project files in '{}' were generated, but a {} hook failed. \
+                     The project may be incomplete; fix the problem and re-run the hooks with --hooks-only.",
+                    "⚠️".bright_yellow(),
+                    output_dir.display(),
+                    stage.name()
+                );
+            }
+            return Err(anyhow::anyhow!("{}", e));
+        }
+    };
+
+    for failure in &report.failed {
         println!(
-            "  {} Location: {}",
-            "📍".bright_blue(),
-            result
-                .output_directory
-                .display()
-                .to_string()
-                .bright_yellow()
+            "  {} Hook '{}' failed (continuing): {}",
+            "⚠️".bright_yellow(),
+            failure.command,
+            failure.reason
         );
+        if let Some(output) = &failure.output {
+            println!("{}", output.trim_end());
+        }
     }
 
-    if options.git && !options.dry_run {
-        println!(
-            "{} Git initialization is a WIP",
-            "🔮".bright_magenta()
-        );
+    if options.verbose {
+        for execution in &report.executed {
+            if let Some(output) = &execution.output {
+                println!("{}", output.trim_end());
+            }
+        }
     }
 
-    if options.github && !options.dry_run {
-        println!(
-            "{} GitHub integration is a WIP",
-            "🔮".bright_magenta()
-        );
+    if options.verbose && !report.skipped.is_empty() {
+        for command in &report.skipped {
+            println!(
+                "  {} Skipped (condition not met): {}",
+                "⏭️".bright_yellow(),
+                command
+            );
+        }
     }
 
     Ok(())
 }
 
-async fn build_context(config: &TemplateConfig, options: &CreateOptions) -> Result<Context> {
-    let mut context_builder = Context::builder().variable("project_name", options.name.clone());
+async fn build_context(
+    config: &TemplateConfig,
+    options: &CreateOptions,
+    replay: Option<&ProjectAnswers>,
+) -> Result<Context> {
+    let mut context = Context::builder()
+        .variable("project_name", options.name.clone())
+        .build();
+    let mut missing_required = Vec::new();
+
+    let mut provided = collect_provided_variables(config, options, replay).await?;
+
+    // Use supplied values first, then prompt (or fall back to defaults with --no-input).
+    // Variables whose `when` condition fails given the answers so far are skipped.
+    for variable in variable_order(&config.variables)? {
+        // project_name always comes from the command line
+        if variable.name == "project_name" {
+            continue;
+        }
 
-    // Add variables based on template configuration with default values
-    for variable in &config.variables {
-        // Don't override project_name if it's already set
-        if variable.name != "project_name" {
-            let value = match variable.default.as_ref() {
-                Some(default) => default.clone(),
-                None => {
-                    // Provide sensible defaults for template variables
-                    match variable.name.as_str() {
-                        "project_description" => {
-                            serde_yaml::Value::String("A modern SaaS application".to_string())
-                        }
-                        "author_name" => serde_yaml::Value::String("".to_string()),
-                        "domain" => serde_yaml::Value::String("myapp.com".to_string()),
-                        _ => serde_yaml::Value::String("".to_string()),
-                    }
-                }
-            };
-            context_builder = context_builder.variable(variable.name.clone(), value);
+        if !variable_applies(variable, &config.variables, &context)? {
+            if provided.remove(&variable.name).is_some() && options.verbose {
+                println!(
+                    "  {} Ignoring value for '{}' (condition not met: {})",
+                    "⏭️".bright_yellow(),
+                    variable.name,
+                    variable.when.as_deref().unwrap_or_default()
+                );
+            }
+            continue;
         }
+
+        let value = if let Some(value) = provided.remove(&variable.name) {
+            variable
+                .coerce_value(value)
+                .map_err(|e| anyhow::anyhow!("{}", e))?
+        } else if options.no_input {
+            match variable
+                .default
+                .clone()
+                .or_else(|| named_fallback_value(variable))
+            {
+                Some(value) => value,
+                None if variable.required => {
+                    missing_required.push(variable.name.clone());
+                    continue;
+                }
+                None => fallback_variable_value(variable),
+            }
+        } else {
+            prompt_for_variable(variable)?
+        };
+
+        context.add_variable(variable.name.clone(), value);
     }
 
-    let mut context = context_builder.build();
+    if !missing_required.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Missing required variables with --no-input: {}. Pass them with --var or --answers, or run without --no-input.",
+            missing_required.join(", ")
+        ));
+    }
 
     // Add language if specified via CLI
     if let Some(language) = &options.language {
@@ -474,11 +2186,6 @@ async fn build_context(config: &TemplateConfig, options: &CreateOptions) -> Resu
         );
     }
 
-    // Add available features from template
-    for feature in &config.features {
-        context.add_feature(feature.name.clone());
-    }
-
     // Add default services object for templates that expect it
     context.add_variable(
         "services".to_string(),
@@ -510,16 +2217,523 @@ async fn build_context(config: &TemplateConfig, options: &CreateOptions) -> Resu
 
     if options.verbose {
         println!(
-            "{} Built context with {} variables and {} features",
+            "{} Built context with {} variables",
             "🎯".bright_blue(),
-            context.variables().len(),
-            context.features().len()
+            context.variables().len()
         );
     }
 
     Ok(context)
 }
 
+/*
+Gathers variable values from --replay, --answers and --var (later sources win),
+rejecting names the template does not declare.
+*/
+async fn collect_provided_variables(
+    config: &TemplateConfig,
+    options: &CreateOptions,
+    replay: Option<&ProjectAnswers>,
+) -> Result<std::collections::BTreeMap<String, serde_yaml::Value>> {
+    // Recorded variables the template has since dropped are ignored
+    let mut provided: std::collections::BTreeMap<String, serde_yaml::Value> = replay
+        .map(|replay| {
+            replay
+                .variables
+                .iter()
+                .filter(|(name, _)| config.get_variable(name).is_some())
+                .map(|(name, value)| (name.clone(), value.clone()))
+                .collect()
+        })
+        .unwrap_or_default();
+
+    if let Some(path) = &options.answers {
+        provided.extend(
+            load_answers(path)
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to load answers: {}", e))?,
+        );
+    }
+
+    // The project name argument is authoritative, so a recorded one is ignored
+    provided.remove("project_name");
+
+    for (name, value) in &options.vars {
+        if name == "project_name" {
+            return Err(anyhow::anyhow!(
+                "project_name is set by the project name argument, not --var"
+            ));
+        }
+        provided.insert(name.clone(), serde_yaml::Value::String(value.clone()));
+    }
+
+    let unknown: Vec<&str> = provided
+        .keys()
+        .filter(|name| config.get_variable(name).is_none())
+        .map(|name| name.as_str())
+        .collect();
+    if !unknown.is_empty() {
+        return Err(anyhow::anyhow!(
+            "Unknown template variables: {}. Template '{}' declares: {}",
+            unknown.join(", "),
+            config.name,
+            config
+                .variables
+                .iter()
+                .map(|v| v.name.as_str())
+                .collect::<Vec<_>>()
+                .join(", ")
+        ));
+    }
+
+    Ok(provided)
+}
+
+fn parse_service_category(input: &str) -> std::result::Result<ServiceCategory, String> {
+    ServiceCategory::all()
+        .iter()
+        .find(|category| format!("{:?}", category).eq_ignore_ascii_case(input))
+        .cloned()
+        .ok_or_else(|| {
+            let names: Vec<String> = ServiceCategory::all()
+                .iter()
+                .map(|category| format!("{:?}", category).to_lowercase())
+                .collect();
+            format!(
+                "unknown service category '{}'. Expected one of: {}",
+                input,
+                names.join(", ")
+            )
+        })
+}
+
+fn parse_variable_assignment(input: &str) -> std::result::Result<(String, String), String> {
+    match input.split_once('=') {
+        Some((name, value)) if !name.trim().is_empty() => {
+            Ok((name.trim().to_string(), value.to_string()))
+        }
+        _ => Err(format!("expected NAME=VALUE, got '{}'", input)),
+    }
+}
+
+/*
+Values supplied for these common string variables when the template gives no default
+and prompting is disabled, whether or not they are required.
+*/
+fn named_fallback_value(variable: &TemplateVariable) -> Option<serde_yaml::Value> {
+    if !matches!(variable.var_type, VariableType::String { .. }) {
+        return None;
+    }
+
+    match variable.name.as_str() {
+        "project_description" => Some(serde_yaml::Value::String(
+            "A modern SaaS application".to_string(),
+        )),
+        "domain" => Some(serde_yaml::Value::String("myapp.com".to_string())),
+        _ => None,
+    }
+}
+
+/*
+Value used for an optional variable without a default when prompting is disabled.
+*/
+fn fallback_variable_value(variable: &TemplateVariable) -> serde_yaml::Value {
+    match &variable.var_type {
+        VariableType::String { .. } => serde_yaml::Value::String(String::new()),
+        VariableType::Boolean => serde_yaml::Value::Bool(false),
+        VariableType::Choice { options } => {
+            serde_yaml::Value::String(options.first().cloned().unwrap_or_default())
+        }
+        VariableType::Number { min, .. } => serde_yaml::Value::from(min.unwrap_or(0)),
+        VariableType::Float { min, .. } => serde_yaml::Value::from(min.unwrap_or(0.0)),
+        VariableType::List { .. } | VariableType::MultiChoice { .. } => {
+            serde_yaml::Value::Sequence(Vec::new())
+        }
+        VariableType::Secret { .. } => serde_yaml::Value::String(String::new()),
+    }
+}
+
+/*
+Prompts for a template variable according to its type, validating input as it is typed.
+The variable's description is shown as help text.
+*/
+fn prompt_for_variable(variable: &TemplateVariable) -> Result<serde_yaml::Value> {
+    use inquire::validator::Validation;
+    use inquire::{Confirm, CustomType, MultiSelect, Password, Select, Text};
+
+    let help = variable.description.as_deref();
+
+    let value = match &variable.var_type {
+        VariableType::String { .. } => {
+            let default = variable.default.as_ref().map(yaml_value_to_string);
+            let validator_variable = variable.clone();
+
+            let mut prompt = Text::new(&variable.prompt).with_validator(move |input: &str| {
+                if validator_variable.required && input.trim().is_empty() {
+                    return Ok(Validation::Invalid("A value is required".into()));
+                }
+                let value = serde_yaml::Value::String(input.to_string());
+                Ok(match validator_variable.validate_value(&value) {
+                    Ok(()) => Validation::Valid,
+                    Err(e) => Validation::Invalid(validation_message(e).into()),
+                })
+            });
+            if let Some(default) = &default {
+                prompt = prompt.with_default(default);
+            }
+            if let Some(help) = help {
+                prompt = prompt.with_help_message(help);
+            }
+
+            serde_yaml::Value::String(
+                prompt
+                    .prompt()
+                    .map_err(|e| anyhow::anyhow!("Failed to get user input: {}", e))?,
+            )
+        }
+        VariableType::Boolean => {
+            let default = variable
+                .default
+                .as_ref()
+                .and_then(|d| d.as_bool())
+                .unwrap_or(false);
+
+            let mut prompt = Confirm::new(&variable.prompt).with_default(default);
+            if let Some(help) = help {
+                prompt = prompt.with_help_message(help);
+            }
+
+            serde_yaml::Value::Bool(
+                prompt
+                    .prompt()
+                    .map_err(|e| anyhow::anyhow!("Failed to get user input: {}", e))?,
+            )
+        }
+        VariableType::Choice { options } => {
+            let default_index = variable
+                .default
+                .as_ref()
+                .and_then(|d| d.as_str())
+                .and_then(|d| options.iter().position(|option| option == d))
+                .unwrap_or(0);
+
+            let mut prompt =
+                Select::new(&variable.prompt, options.clone()).with_starting_cursor(default_index);
+            if let Some(help) = help {
+                prompt = prompt.with_help_message(help);
+            }
+
+            serde_yaml::Value::String(
+                prompt
+                    .prompt()
+                    .map_err(|e| anyhow::anyhow!("Failed to get user input: {}", e))?,
+            )
+        }
+        VariableType::Number { .. } => {
+            let validator_variable = variable.clone();
+
+            let mut prompt = CustomType::<i64>::new(&variable.prompt)
+                .with_error_message("Please enter a whole number")
+                .with_validator(move |input: &i64| {
+                    Ok(
+                        match validator_variable.validate_value(&serde_yaml::Value::from(*input)) {
+                            Ok(()) => Validation::Valid,
+                            Err(e) => Validation::Invalid(validation_message(e).into()),
+                        },
+                    )
+                });
+            if let Some(default) = variable.default.as_ref().and_then(|d| d.as_i64()) {
+                prompt = prompt.with_default(default);
+            }
+            if let Some(help) = help {
+                prompt = prompt.with_help_message(help);
+            }
+
+            serde_yaml::Value::from(
+                prompt
+                    .prompt()
+                    .map_err(|e| anyhow::anyhow!("Failed to get user input: {}", e))?,
+            )
+        }
+        VariableType::Float { .. } => {
+            let validator_variable = variable.clone();
+
+            let mut prompt = CustomType::<f64>::new(&variable.prompt)
+                .with_error_message("Please enter a number")
+                .with_validator(move |input: &f64| {
+                    Ok(
+                        match validator_variable.validate_value(&serde_yaml::Value::from(*input)) {
+                            Ok(()) => Validation::Valid,
+                            Err(e) => Validation::Invalid(validation_message(e).into()),
+                        },
+                    )
+                });
+            if let Some(default) = variable.default.as_ref().and_then(|d| d.as_f64()) {
+                prompt = prompt.with_default(default);
+            }
+            if let Some(help) = help {
+                prompt = prompt.with_help_message(help);
+            }
+
+            serde_yaml::Value::from(
+                prompt
+                    .prompt()
+                    .map_err(|e| anyhow::anyhow!("Failed to get user input: {}", e))?,
+            )
+        }
+        VariableType::List { .. } => {
+            let default = variable.default.as_ref().map(|d| match d {
+                serde_yaml::Value::Sequence(items) => items
+                    .iter()
+                    .map(yaml_value_to_string)
+                    .collect::<Vec<_>>()
+                    .join(", "),
+                other => yaml_value_to_string(other),
+            });
+            let validator_variable = variable.clone();
+
+            let mut prompt = Text::new(&variable.prompt)
+                .with_help_message(help.unwrap_or("Separate items with commas"))
+                .with_validator(move |input: &str| {
+                    if validator_variable.required && input.trim().is_empty() {
+                        return Ok(Validation::Invalid("A value is required".into()));
+                    }
+                    let value = serde_yaml::Value::String(input.to_string());
+                    Ok(match validator_variable.coerce_value(value) {
+                        Ok(_) => Validation::Valid,
+                        Err(e) => Validation::Invalid(validation_message(e).into()),
+                    })
+                });
+            if let Some(default) = &default {
+                prompt = prompt.with_default(default);
+            }
+
+            let input = prompt
+                .prompt()
+                .map_err(|e| anyhow::anyhow!("Failed to get user input: {}", e))?;
+            variable
+                .coerce_value(serde_yaml::Value::String(input))
+                .map_err(|e| anyhow::anyhow!("{}", e))?
+        }
+        VariableType::MultiChoice { options } => {
+            let default_indices: Vec<usize> = match &variable.default {
+                Some(serde_yaml::Value::Sequence(items)) => items
+                    .iter()
+                    .filter_map(|item| item.as_str())
+                    .filter_map(|item| options.iter().position(|option| option == item))
+                    .collect(),
+                _ => Vec::new(),
+            };
+            let required = variable.required;
+
+            let mut prompt = MultiSelect::new(&variable.prompt, options.clone())
+                .with_default(&default_indices)
+                .with_validator(
+                    move |selected: &[inquire::list_option::ListOption<&String>]| {
+                        Ok(if required && selected.is_empty() {
+                            Validation::Invalid("Select at least one option".into())
+                        } else {
+                            Validation::Valid
+                        })
+                    },
+                );
+            if let Some(help) = help {
+                prompt = prompt.with_help_message(help);
+            }
+
+            serde_yaml::Value::Sequence(
+                prompt
+                    .prompt()
+                    .map_err(|e| anyhow::anyhow!("Failed to get user input: {}", e))?
+                    .into_iter()
+                    .map(serde_yaml::Value::String)
+                    .collect(),
+            )
+        }
+        VariableType::Secret { .. } => {
+            let validator_variable = variable.clone();
+
+            let mut prompt = Password::new(&variable.prompt)
+                .without_confirmation()
+                .with_validator(move |input: &str| {
+                    if validator_variable.required && input.is_empty() {
+                        return Ok(Validation::Invalid("A value is required".into()));
+                    }
+                    let value = serde_yaml::Value::String(input.to_string());
+                    Ok(match validator_variable.validate_value(&value) {
+                        Ok(()) => Validation::Valid,
+                        Err(e) => Validation::Invalid(validation_message(e).into()),
+                    })
+                });
+            if let Some(help) = help {
+                prompt = prompt.with_help_message(help);
+            }
+
+            serde_yaml::Value::String(
+                prompt
+                    .prompt()
+                    .map_err(|e| anyhow::anyhow!("Failed to get user input: {}", e))?,
+            )
+        }
+    };
+
+    variable
+        .validate_value(&value)
+        .map_err(|e| anyhow::anyhow!("{}", e))?;
+
+    Ok(value)
+}
+
+fn validation_message(error: EngineError) -> String {
+    match error {
+        EngineError::VariableError { reason, .. } => reason,
+        other => other.to_string(),
+    }
+}
+
+fn yaml_value_to_string(value: &serde_yaml::Value) -> String {
+    match value {
+        serde_yaml::Value::String(s) => s.clone(),
+        serde_yaml::Value::Bool(b) => b.to_string(),
+        serde_yaml::Value::Number(n) => n.to_string(),
+        _ => String::new(),
+    }
+}
+
+/*
+Uses the replayed services, letting service flags such as --auth override a category.
+*/
+fn replay_service_selections(
+    replay: &ProjectAnswers,
+    options: &CreateOptions,
+) -> Result<Vec<ServiceSelection>> {
+    let overrides = collect_service_selections(options)?;
+    let mut services: Vec<ServiceSelection> = replay
+        .service_selections()
+        .into_iter()
+        .filter(|service| {
+            !overrides
+                .iter()
+                .any(|override_| override_.category == service.category)
+        })
+        .collect();
+    services.extend(overrides);
+    Ok(services)
+}
+
+/*
+Records the chosen providers under `services.<category>` so conditions and
+templates can see them.
+*/
+fn record_service_selections(context: &mut Context, services: &[ServiceSelection]) {
+    let mut mapping = match context.get_variable("services") {
+        Some(serde_yaml::Value::Mapping(mapping)) => mapping.clone(),
+        _ => serde_yaml::Mapping::new(),
+    };
+
+    for service in services {
+        mapping.insert(
+            serde_yaml::Value::String(format!("{:?}", service.category).to_lowercase()),
+            serde_yaml::Value::String(service.provider.clone()),
+        );
+    }
+
+    context.add_variable("services".to_string(), serde_yaml::Value::Mapping(mapping));
+}
+
+/*
+Computes the active feature set from enabled_when conditions and --feature/--no-feature,
+optionally letting the user adjust it with a multi-select, then checks dependencies.
+*/
+fn select_features(
+    config: &TemplateConfig,
+    context: &mut Context,
+    options: &CreateOptions,
+    interactive: bool,
+    replay: Option<&ProjectAnswers>,
+) -> Result<()> {
+    let mut selection = match replay {
+        // Replayed features are pinned exactly; features the template has since dropped are ignored
+        Some(replay) => {
+            let (enabled, disabled): (Vec<_>, Vec<_>) = config
+                .features
+                .iter()
+                .map(|feature| feature.name.clone())
+                .partition(|name| replay.features.contains(name));
+            FeatureSelection::new(enabled, disabled)
+        }
+        None => FeatureSelection::default(),
+    };
+    for name in &options.features {
+        selection.disabled.retain(|disabled| disabled != name);
+        if !selection.enabled.contains(name) {
+            selection.enabled.push(name.clone());
+        }
+    }
+    for name in &options.disabled_features {
+        selection.enabled.retain(|enabled| enabled != name);
+        if !selection.disabled.contains(name) {
+            selection.disabled.push(name.clone());
+        }
+    }
+
+    if interactive && !config.features.is_empty() {
+        let defaults = evaluate_features(&config.features, context, &selection)
+            .map_err(|e| anyhow::anyhow!("Failed to evaluate features: {}", e))?;
+        let default_indices: Vec<usize> = config
+            .features
+            .iter()
+            .enumerate()
+            .filter(|(_, feature)| defaults.contains(&feature.name))
+            .map(|(index, _)| index)
+            .collect();
+        let labels: Vec<String> = config
+            .features
+            .iter()
+            .map(|feature| format!("{} - {}", feature.name, feature.description))
+            .collect();
+
+        let chosen = inquire::MultiSelect::new("Select features to enable:", labels)
+            .with_default(&default_indices)
+            .raw_prompt()
+            .map_err(|e| anyhow::anyhow!("Failed to get user input: {}", e))?;
+
+        let (enabled, disabled): (Vec<_>, Vec<_>) = config
+            .features
+            .iter()
+            .enumerate()
+            .partition(|(index, _)| chosen.iter().any(|option| option.index == *index));
+        selection = FeatureSelection::new(
+            enabled.into_iter().map(|(_, f)| f.name.clone()).collect(),
+            disabled.into_iter().map(|(_, f)| f.name.clone()).collect(),
+        );
+    }
+
+    let active = resolve_features(&config.features, context, &selection)
+        .map_err(|e| anyhow::anyhow!("Feature selection failed: {}", e))?;
+
+    if options.verbose {
+        println!(
+            "{} Enabled features: {}",
+            "🎯".bright_blue(),
+            if active.is_empty() {
+                "none".to_string()
+            } else {
+                active.join(", ")
+            }
+        );
+    }
+
+    for feature in &config.features {
+        context.declare_feature(feature.name.clone());
+    }
+    for feature in active {
+        context.add_feature(feature);
+    }
+
+    Ok(())
+}
+
 fn find_template_directory(template_name: &str) -> Result<PathBuf> {
     let templates_dir = std::env::current_dir()?.join("templates");
     let template_dir = templates_dir.join(template_name);
@@ -777,7 +2991,9 @@ async fn apply_service_combination(
                         // Only prompt for configuration that wasn't set in the preset
                         for prompt in &service_config_yaml.configuration_prompts {
                             let config_key = format!("config_{}", prompt.name);
-                            if !final_config.contains_key(&config_key) {
+                            if let std::collections::hash_map::Entry::Vacant(entry) =
+                                final_config.entry(config_key)
+                            {
                                 // This configuration wasn't preset, so we can optionally prompt for it
                                 if prompt.required {
                                     println!(
@@ -801,7 +3017,7 @@ async fn apply_service_combination(
                                             let default = prompt
                                                 .default
                                                 .as_ref()
-                                                .map(|v| value_to_string(v))
+                                                .map(value_to_string)
                                                 .unwrap_or_default();
                                             inquire::Text::new(&prompt.prompt)
                                                 .with_default(&default)
@@ -838,19 +3054,18 @@ async fn apply_service_combination(
                                                 prompt
                                                     .default
                                                     .as_ref()
-                                                    .map(|v| value_to_string(v))
+                                                    .map(value_to_string)
                                                     .unwrap_or_default()
                                             }
                                         }
                                         _ => prompt
                                             .default
                                             .as_ref()
-                                            .map(|v| value_to_string(v))
+                                            .map(value_to_string)
                                             .unwrap_or_default(),
                                     };
 
-                                    final_config
-                                        .insert(config_key, serde_json::Value::String(value));
+                                    entry.insert(serde_json::Value::String(value));
                                 }
                             }
                         }
@@ -1059,7 +3274,7 @@ async fn prompt_for_service_configuration(
                     serde_json::Value::Array(
                         result
                             .into_iter()
-                            .map(|s| serde_json::Value::String(s))
+                            .map(serde_json::Value::String)
                             .collect(),
                     )
                 } else {
@@ -1120,21 +3335,27 @@ async fn list_templates(_language: Option<String>, _format: OutputFormat) -> Res
                                 config.description
                             );
                         }
-                        Err(_) => {
+                        Err(EngineError::IncompatibleAnvilVersion { requirement, .. }) => {
                             println!(
-                                "  {} {} - {}",
+                                "  {} {} - Requires Anvil {}",
                                 "•".bright_red(),
                                 template_name.bright_yellow(),
-                                "Invalid configuration"
+                                requirement
+                            );
+                        }
+                        Err(_) => {
+                            println!(
+                                "  {} {} - Invalid configuration",
+                                "•".bright_red(),
+                                template_name.bright_yellow()
                             );
                         }
                     }
                 } else {
                     println!(
-                        "  {} {} - {}",
+                        "  {} {} - No configuration file",
                         "•".bright_red(),
-                        template_name.bright_yellow(),
-                        "No configuration file"
+                        template_name.bright_yellow()
                     );
                 }
             }
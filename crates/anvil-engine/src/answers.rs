@@ -0,0 +1,308 @@
+/*
+Module for answer files: pre-filled template variable values supplied with
+--answers, and the `.anvil/answers.yaml` record written into generated projects
+so a scaffold can be replayed exactly.
+*/
+
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::composition::{FileSource, ServiceSelection};
+use crate::config::{ServiceCategory, ServiceConfig, ServicePromptType, TemplateConfig};
+use crate::engine::{Context, ProcessedFile};
+use crate::error::{EngineError, EngineResult};
+
+pub const ANSWERS_FILE: &str = ".anvil/answers.yaml";
+
+/*
+Everything needed to regenerate a project: the template and its version, variable
+values, enabled features and selected services. Password values are never recorded.
+*/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ProjectAnswers {
+    pub template: String,
+    pub template_version: String,
+    pub anvil_version: String,
+
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, serde_yaml::Value>,
+
+    #[serde(default)]
+    pub features: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub services: Vec<ServiceAnswer>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ServiceAnswer {
+    pub category: ServiceCategory,
+    pub provider: String,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub config: BTreeMap<String, serde_json::Value>,
+}
+
+impl ProjectAnswers {
+    /*
+    Captures the answers behind a generation. Secret variables are dropped, as are
+    service config values that came from password prompts, using the prompts
+    declared in each service's anvil.yaml under shared_services_path.
+    */
+    pub async fn capture(
+        config: &TemplateConfig,
+        context: &Context,
+        services: &[ServiceSelection],
+        shared_services_path: &Path,
+    ) -> EngineResult<Self> {
+        let variables = config
+            .variables
+            .iter()
+            .filter(|variable| !variable.var_type.is_secret())
+            .filter_map(|variable| {
+                context
+                    .get_variable(&variable.name)
+                    .map(|value| (variable.name.clone(), value.clone()))
+            })
+            .collect();
+
+        let mut service_answers = Vec::new();
+        for service in services {
+            let secret_prompts = Self::password_prompts(service, shared_services_path).await?;
+
+            let config = service
+                .config
+                .iter()
+                .filter(|(key, _)| {
+                    let prompt_name = key.strip_prefix("config_").unwrap_or(key);
+                    !secret_prompts.iter().any(|name| name == prompt_name)
+                })
+                .map(|(key, value)| (key.clone(), value.clone()))
+                .collect();
+
+            service_answers.push(ServiceAnswer {
+                category: service.category.clone(),
+                provider: service.provider.clone(),
+                config,
+            });
+        }
+
+        Ok(Self {
+            template: config.name.clone(),
+            template_version: config.version.clone(),
+            anvil_version: env!("CARGO_PKG_VERSION").to_string(),
+            language: context
+                .get_variable("language")
+                .and_then(|value| value.as_str())
+                .map(|language| language.to_string()),
+            variables,
+            features: context.features().to_vec(),
+            services: service_answers,
+        })
+    }
+
+    async fn password_prompts(
+        service: &ServiceSelection,
+        shared_services_path: &Path,
+    ) -> EngineResult<Vec<String>> {
+        let service_config_path = shared_services_path
+            .join(format!("{:?}", service.category).to_lowercase())
+            .join(&service.provider)
+            .join("anvil.yaml");
+
+        if !service_config_path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let service_config = ServiceConfig::from_file(&service_config_path).await?;
+        Ok(service_config
+            .configuration_prompts
+            .into_iter()
+            .filter(|prompt| matches!(prompt.prompt_type, ServicePromptType::Password))
+            .map(|prompt| prompt.name)
+            .collect())
+    }
+
+    pub async fn from_file(path: &Path) -> EngineResult<Self> {
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| EngineError::file_error(path, e))?;
+
+        Ok(serde_yaml::from_str(&content)?)
+    }
+
+    pub fn to_yaml(&self) -> EngineResult<String> {
+        Ok(format!(
+            "# Generated by Anvil. Regenerate this project with:\n#   anvil create <name> --replay {}\n{}",
+            ANSWERS_FILE,
+            serde_yaml::to_string(self)?
+        ))
+    }
+
+    pub fn to_file(&self) -> EngineResult<ProcessedFile> {
+        Ok(ProcessedFile {
+            output_path: PathBuf::from(ANSWERS_FILE),
+            content: self.to_yaml()?.into(),
+            mode: None,
+            executable: false,
+            source: FileSource::BaseTemplate,
+            merge_strategy: None,
+        })
+    }
+
+    pub fn service_selections(&self) -> Vec<ServiceSelection> {
+        self.services
+            .iter()
+            .map(|service| ServiceSelection {
+                category: service.category.clone(),
+                provider: service.provider.clone(),
+                config: service
+                    .config
+                    .iter()
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect(),
+            })
+            .collect()
+    }
+}
+
+/*
+Loads variable answers from a YAML or JSON file whose top level maps variable names
+to values.
+*/
+pub async fn load_answers(path: &Path) -> EngineResult<BTreeMap<String, serde_yaml::Value>> {
+    let content = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| EngineError::file_error(path, e))?;
+
+    parse_answers(&content)
+        .map_err(|reason| EngineError::invalid_config(format!("{}: {}", path.display(), reason)))
+}
+
+fn parse_answers(content: &str) -> Result<BTreeMap<String, serde_yaml::Value>, String> {
+    // JSON is valid YAML, so one parser covers both formats
+    let value: serde_yaml::Value = serde_yaml::from_str(content).map_err(|e| e.to_string())?;
+
+    let mapping = match value {
+        serde_yaml::Value::Mapping(mapping) => mapping,
+        serde_yaml::Value::Null => return Ok(BTreeMap::new()),
+        _ => return Err("answers must be a mapping of variable names to values".to_string()),
+    };
+
+    mapping
+        .into_iter()
+        .map(|(key, value)| match key {
+            serde_yaml::Value::String(name) => Ok((name, value)),
+            other => Err(format!("answer keys must be strings, found {:?}", other)),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_yaml_and_json_answers() {
+        let yaml = parse_answers("author_name: Ada\nport: 8080\nsetup_git: false\n").unwrap();
+        assert_eq!(yaml["author_name"], serde_yaml::Value::from("Ada"));
+        assert_eq!(yaml["port"], serde_yaml::Value::from(8080));
+        assert_eq!(yaml["setup_git"], serde_yaml::Value::Bool(false));
+
+        let json = parse_answers(r#"{"author_name": "Ada", "port": 8080}"#).unwrap();
+        assert_eq!(json, {
+            let mut expected = yaml.clone();
+            expected.remove("setup_git");
+            expected
+        });
+
+        assert!(parse_answers("- not\n- a mapping\n").is_err());
+        assert!(parse_answers("").unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_capture_excludes_password_values() {
+        let temp_dir = tempfile::TempDir::new().unwrap();
+        let service_dir = temp_dir.path().join("auth/clerk");
+        tokio::fs::create_dir_all(&service_dir).await.unwrap();
+        tokio::fs::write(
+            service_dir.join("anvil.yaml"),
+            r#"
+name: "clerk"
+description: "Clerk auth"
+version: "1.0.0"
+category: "auth"
+configuration_prompts:
+  - name: "secret_key"
+    prompt: "Secret key?"
+    prompt_type: "password"
+  - name: "enable_webhooks"
+    prompt: "Enable webhooks?"
+    prompt_type: "boolean"
+"#,
+        )
+        .await
+        .unwrap();
+
+        let config: TemplateConfig = serde_yaml::from_str(
+            r#"
+name: "test-app"
+description: "Test application"
+version: "1.2.0"
+variables:
+  - name: "project_name"
+    type: { type: "string" }
+    prompt: "Name?"
+  - name: "author_name"
+    type: { type: "string" }
+    prompt: "Author?"
+  - name: "api_token"
+    type: { type: "secret" }
+    prompt: "API token?"
+"#,
+        )
+        .unwrap();
+        let context = Context::builder()
+            .variable("project_name", "demo")
+            .variable("author_name", "Ada")
+            .variable("api_token", "tok_live_456")
+            .variable("language", "typescript")
+            .feature("tailwind")
+            .build();
+        let services = vec![ServiceSelection {
+            category: ServiceCategory::Auth,
+            provider: "clerk".to_string(),
+            config: [
+                ("secret_key".to_string(), serde_json::json!("sk_live_123")),
+                ("enable_webhooks".to_string(), serde_json::json!(true)),
+            ]
+            .into_iter()
+            .collect(),
+        }];
+
+        let answers = ProjectAnswers::capture(&config, &context, &services, temp_dir.path())
+            .await
+            .unwrap();
+        let yaml = answers.to_yaml().unwrap();
+
+        assert!(!yaml.contains("sk_live_123"));
+        assert!(!yaml.contains("tok_live_456"));
+        assert!(yaml.contains("project_name: demo"));
+
+        let replayed: ProjectAnswers = serde_yaml::from_str(&yaml).unwrap();
+        assert_eq!(replayed.template_version, "1.2.0");
+        assert_eq!(replayed.language.as_deref(), Some("typescript"));
+        assert_eq!(
+            replayed.variables["author_name"],
+            serde_yaml::Value::from("Ada")
+        );
+        assert_eq!(replayed.features, vec!["tailwind"]);
+        let selections = replayed.service_selections();
+        assert_eq!(selections[0].provider, "clerk");
+        assert!(selections[0].config.contains_key("enable_webhooks"));
+        assert!(!selections[0].config.contains_key("secret_key"));
+    }
+}
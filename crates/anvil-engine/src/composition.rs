@@ -3,14 +3,20 @@ Module for template composition engine that combines base templates with service
 Handles file merging, conflict resolution, and conditional inclusion logic.
 */
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 use tokio::fs;
 
-use crate::config::{CompositionConfig, FileMergingStrategy, ServiceCategory, TemplateConfig};
+use crate::condition::evaluate_condition;
+use crate::config::{
+    CompositionConfig, FileMergingStrategy, HookCommand, Hooks, ServiceCategory, ServiceConfig,
+    TemplateConfig,
+};
+use crate::engine::{file_mode, FileContent, ProcessedFile};
 use crate::error::{EngineError, EngineResult};
+use crate::exclude::ExcludeRules;
 
 #[derive(Debug, Clone)]
 pub struct CompositionEngine {
@@ -32,25 +38,81 @@ pub struct ComposedTemplate {
     pub merged_dependencies: HashMap<String, Value>,
     pub environment_variables: HashMap<String, String>,
     pub service_context: ServiceContext,
+    pub hooks: Hooks,
 }
 
 #[derive(Debug, Clone)]
 pub struct ComposedFile {
     pub path: PathBuf,
-    pub content: String,
+    pub content: FileContent,
+    pub mode: Option<u32>,
     pub source: FileSource,
     pub merge_strategy: FileMergingStrategy,
     pub is_template: bool,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum FileSource {
     BaseTemplate,
     Service {
         category: ServiceCategory,
         provider: String,
     },
-    Merged,
+    Merged {
+        sources: Vec<FileSource>,
+    },
+}
+
+impl FileSource {
+    /* The source of a file combined from several, keeping every contributor */
+    pub fn merged(files: &[ComposedFile]) -> Self {
+        let mut sources = Vec::new();
+        for file in files {
+            for source in file.source.contributors() {
+                if !sources.contains(source) {
+                    sources.push(source.clone());
+                }
+            }
+        }
+        FileSource::Merged { sources }
+    }
+
+    /* The base template and services behind a file, looking through merges */
+    pub fn contributors(&self) -> Vec<&FileSource> {
+        match self {
+            FileSource::Merged { sources } => sources
+                .iter()
+                .flat_map(|source| source.contributors())
+                .collect(),
+            source => vec![source],
+        }
+    }
+
+    pub fn is_service(&self, category: &ServiceCategory) -> bool {
+        matches!(self, FileSource::Service { category: c, .. } if c == category)
+    }
+}
+
+/* Short labels for reports: `base`, `auth:clerk`, `merged(base, auth:clerk)` */
+impl std::fmt::Display for FileSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FileSource::BaseTemplate => write!(f, "base"),
+            FileSource::Service { category, provider } => {
+                write!(
+                    f,
+                    "{}:{}",
+                    format!("{:?}", category).to_lowercase(),
+                    provider
+                )
+            }
+            FileSource::Merged { sources } => {
+                let labels: Vec<String> = sources.iter().map(|source| source.to_string()).collect();
+                write!(f, "merged({})", labels.join(", "))
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize)]
@@ -186,17 +248,24 @@ impl CompositionEngine {
         let service_context = self.build_service_context(&services).await?;
 
         
-        let mut composed_files = self.collect_base_template_files(template_name).await?;
+        let mut composed_files = self
+            .collect_base_template_files(template_name, &base_config.exclude)
+            .await?;
 
         
         for service in &services {
-            let service_files = self.collect_service_files(&service).await?;
+            let service_files = self.collect_service_files(service).await?;
             composed_files.extend(service_files);
         }
 
         
         let filtered_files = self
-            .apply_conditional_inclusion(composed_files, &services, &base_config.composition)
+            .apply_conditional_inclusion(
+                composed_files,
+                &services,
+                &base_config.composition,
+                context,
+            )
             .await?;
 
         
@@ -210,15 +279,172 @@ impl CompositionEngine {
         
         let environment_variables = self.collect_environment_variables(&services).await?;
 
+        let hooks = self.collect_hooks(&base_config, &services).await?;
+
         Ok(ComposedTemplate {
             base_config,
             files: resolved_files,
             merged_dependencies,
             environment_variables,
             service_context,
+            hooks,
         })
     }
 
+    /*
+    Composes a single service for adding to an already generated project. The service
+    is validated together with the services the project has, and only its own files
+    and hooks are returned.
+    */
+    pub async fn compose_service_addition(
+        &self,
+        template_name: &str,
+        existing_services: &[ServiceSelection],
+        service: ServiceSelection,
+        context: Option<&crate::Context>,
+    ) -> EngineResult<ComposedTemplate> {
+        let base_config_path = self
+            .base_template_path
+            .join(template_name)
+            .join("anvil.yaml");
+        let base_config = TemplateConfig::from_file(&base_config_path).await?;
+
+        if let Some(existing) = existing_services
+            .iter()
+            .find(|s| s.category == service.category && s.provider != "none")
+        {
+            return Err(EngineError::composition_error(format!(
+                "Project already uses '{}' for {:?}",
+                existing.provider, service.category
+            )));
+        }
+
+        let mut services: Vec<ServiceSelection> = existing_services
+            .iter()
+            .filter(|s| s.category != service.category)
+            .cloned()
+            .collect();
+        services.push(service.clone());
+
+        self.validate_service_selections(&base_config, &services, context)
+            .await?;
+
+        let service_context = self.build_service_context(&services).await?;
+        let service_files = self.collect_service_files(&service).await?;
+        let files = self
+            .apply_conditional_inclusion(
+                service_files,
+                &services,
+                &base_config.composition,
+                context,
+            )
+            .await?;
+
+        let added = std::slice::from_ref(&service);
+        let merged_dependencies = self.merge_dependencies(added).await?;
+        let environment_variables = self.collect_environment_variables(added).await?;
+
+        // The base template's hooks already ran when the project was generated
+        let service_only = TemplateConfig {
+            hooks: None,
+            ..base_config.clone()
+        };
+        let hooks = self.collect_hooks(&service_only, added).await?;
+
+        Ok(ComposedTemplate {
+            base_config,
+            files,
+            merged_dependencies,
+            environment_variables,
+            service_context,
+            hooks,
+        })
+    }
+
+    /*
+    Combines rendered service files with the files already in a project, using the
+    template's file merging strategy the same way generation does. Files identical to
+    the project's copy are dropped, and a file whose resolution is just the service's
+    content is left to the generator's conflict mode. Under `merge`, files other than
+    JSON also go to the conflict mode rather than being appended, since appending to
+    a source file that already exists breaks it.
+    */
+    pub async fn merge_into_project(
+        &self,
+        service: &ServiceSelection,
+        files: Vec<ProcessedFile>,
+        project_dir: &Path,
+        strategy: &FileMergingStrategy,
+    ) -> EngineResult<Vec<ProcessedFile>> {
+        let mut merged = Vec::new();
+        for file in files {
+            let existing_path = project_dir.join(&file.output_path);
+            if !existing_path.is_file() {
+                merged.push(file);
+                continue;
+            }
+
+            let existing = FileContent::load(&existing_path)
+                .await
+                .map_err(|e| EngineError::file_error(&existing_path, e))?;
+            if existing == file.content {
+                continue;
+            }
+            if *strategy == FileMergingStrategy::Merge
+                && file.output_path.extension().and_then(|ext| ext.to_str()) != Some("json")
+            {
+                merged.push(file);
+                continue;
+            }
+
+            let resolved = self
+                .resolve_single_conflict(
+                    file.output_path.clone(),
+                    vec![
+                        ComposedFile {
+                            path: file.output_path.clone(),
+                            content: existing.clone(),
+                            mode: None,
+                            source: FileSource::BaseTemplate,
+                            merge_strategy: strategy.clone(),
+                            is_template: false,
+                        },
+                        ComposedFile {
+                            path: file.output_path.clone(),
+                            content: file.content.clone(),
+                            mode: file.mode,
+                            source: FileSource::Service {
+                                category: service.category.clone(),
+                                provider: service.provider.clone(),
+                            },
+                            merge_strategy: strategy.clone(),
+                            is_template: false,
+                        },
+                    ],
+                    strategy,
+                )
+                .await?;
+
+            if resolved.content == existing {
+                continue;
+            }
+            if resolved.content == file.content {
+                merged.push(file);
+                continue;
+            }
+            merged.push(ProcessedFile {
+                output_path: file.output_path,
+                content: resolved.content,
+                mode: file.mode,
+                executable: file.executable,
+                source: resolved.source,
+                merge_strategy: Some(resolved.merge_strategy),
+            });
+        }
+
+        Ok(merged)
+    }
+
     /*
     Validates that selected services are compatible with the base template
     and with each other. Checks dependencies and conflicts.
@@ -449,15 +675,12 @@ impl CompositionEngine {
         
         
 
-        match service_config.name.as_str() {
-            "trpc-api" => {
-                if !project_languages.contains(&"typescript".to_string()) {
-                    return Err(EngineError::composition_error(
-                        "tRPC requires TypeScript for type safety".to_string(),
-                    ));
-                }
-            }
-            _ => {}
+        if service_config.name == "trpc-api"
+            && !project_languages.contains(&"typescript".to_string())
+        {
+            return Err(EngineError::composition_error(
+                "tRPC requires TypeScript for type safety".to_string(),
+            ));
         }
 
         Ok(())
@@ -648,7 +871,7 @@ impl CompositionEngine {
         let mut has_any_auth = false;
         let mut has_any_database = false;
 
-        for (category_str, _service_info) in &service_context.services {
+        for category_str in service_context.services.keys() {
             match category_str.as_str() {
                 "Auth" => has_any_auth = true,
                 "Database" => has_any_database = true,
@@ -677,13 +900,16 @@ impl CompositionEngine {
     async fn collect_base_template_files(
         &self,
         template_name: &str,
+        exclude: &[String],
     ) -> EngineResult<Vec<ComposedFile>> {
         let template_path = self.base_template_path.join(template_name);
+        let rules = ExcludeRules::new(&template_path, exclude)?;
         let mut files = Vec::new();
 
         self.collect_files_recursive(
             &template_path,
             &template_path,
+            &rules,
             FileSource::BaseTemplate,
             &mut files,
         )
@@ -722,19 +948,31 @@ impl CompositionEngine {
             provider: service.provider.clone(),
         };
 
-        self.collect_files_recursive(&service_path, &service_path, source, &mut files)
+        let service_config_path = service_path.join("anvil.yaml");
+        let exclude = if service_config_path.exists() {
+            ServiceConfig::from_file(&service_config_path)
+                .await?
+                .exclude
+        } else {
+            Vec::new()
+        };
+
+        let rules = ExcludeRules::new(&service_path, &exclude)?;
+        self.collect_files_recursive(&service_path, &service_path, &rules, source, &mut files)
             .await?;
 
         Ok(files)
     }
 
     /*
-    Recursively collects files from a directory, preserving relative paths.
+    Recursively collects files from a directory, preserving relative paths and
+    leaving out anything the directory's exclude rules match.
     */
     fn collect_files_recursive<'a>(
         &'a self,
         dir: &'a Path,
         base_path: &'a Path,
+        rules: &'a ExcludeRules,
         source: FileSource,
         files: &'a mut Vec<ComposedFile>,
     ) -> std::pin::Pin<Box<dyn std::future::Future<Output = EngineResult<()>> + Send + 'a>> {
@@ -749,26 +987,41 @@ impl CompositionEngine {
                 .map_err(|e| EngineError::file_error(dir, e))?
             {
                 let path = entry.path();
+                let file_type = entry
+                    .file_type()
+                    .await
+                    .map_err(|e| EngineError::file_error(&path, e))?;
+                let relative_path = path.strip_prefix(base_path).map_err(|_| {
+                    EngineError::composition_error(format!(
+                        "Invalid path structure: {}",
+                        path.display()
+                    ))
+                })?;
+                if rules.is_excluded(relative_path, file_type.is_dir()) {
+                    continue;
+                }
 
-                if path.is_dir() {
-                    self.collect_files_recursive(&path, base_path, source.clone(), files)
+                if file_type.is_dir() {
+                    self.collect_files_recursive(&path, base_path, rules, source.clone(), files)
                         .await?;
-                } else if path.is_file() {
+                } else if file_type.is_file() || file_type.is_symlink() {
                     
                     if path.file_name().and_then(|name| name.to_str()) == Some("anvil.yaml") {
                         continue;
                     }
 
-                    let relative_path = path.strip_prefix(base_path).map_err(|_| {
-                        EngineError::composition_error(format!(
-                            "Invalid path structure: {}",
-                            path.display()
-                        ))
-                    })?;
-
-                    let content = fs::read_to_string(&path)
+                    let content = FileContent::load(&path)
                         .await
                         .map_err(|e| EngineError::file_error(&path, e))?;
+                    let mode = if file_type.is_file() {
+                        let metadata = entry
+                            .metadata()
+                            .await
+                            .map_err(|e| EngineError::file_error(&path, e))?;
+                        file_mode(&metadata)
+                    } else {
+                        None
+                    };
 
                     
                     let is_template =
@@ -790,6 +1043,7 @@ impl CompositionEngine {
                     files.push(ComposedFile {
                         path: output_path,
                         content,
+                        mode,
                         source: source.clone(),
                         merge_strategy: FileMergingStrategy::default(),
                         is_template,
@@ -816,7 +1070,7 @@ impl CompositionEngine {
         for file in files {
             file_map
                 .entry(file.path.clone())
-                .or_insert_with(Vec::new)
+                .or_default()
                 .push(file);
         }
 
@@ -853,6 +1107,16 @@ impl CompositionEngine {
         mut files: Vec<ComposedFile>,
         strategy: &FileMergingStrategy,
     ) -> EngineResult<ComposedFile> {
+        /* Only text can be combined, so for anything else the highest-priority source wins */
+        let strategy = match strategy {
+            FileMergingStrategy::Append | FileMergingStrategy::Merge
+                if files.iter().any(|file| file.content.as_text().is_none()) =>
+            {
+                &FileMergingStrategy::Override
+            }
+            strategy => strategy,
+        };
+
         match strategy {
             FileMergingStrategy::Override => {
                 
@@ -865,19 +1129,19 @@ impl CompositionEngine {
                     }
                     _ => std::cmp::Ordering::Equal,
                 });
-                Ok(files.into_iter().last().unwrap())
+                let source = FileSource::merged(&files);
+                let mut winner = files.into_iter().last().unwrap();
+                winner.source = source;
+                winner.merge_strategy = FileMergingStrategy::Override;
+                Ok(winner)
             }
             FileMergingStrategy::Append => {
                 
-                let mut combined_content = String::new();
-                for file in &files {
-                    combined_content.push_str(&file.content);
-                    combined_content.push('\n');
-                }
                 Ok(ComposedFile {
                     path,
-                    content: combined_content,
-                    source: FileSource::Merged,
+                    content: Self::concatenate(&files),
+                    mode: files.first().and_then(|file| file.mode),
+                    source: FileSource::merged(&files),
                     merge_strategy: FileMergingStrategy::Append,
                     is_template: false,
                 })
@@ -888,27 +1152,36 @@ impl CompositionEngine {
                     self.merge_json_files(path, files).await
                 } else {
                     
-                    let mut combined_content = String::new();
-                    for file in &files {
-                        combined_content.push_str(&file.content);
-                        combined_content.push('\n');
-                    }
                     Ok(ComposedFile {
                         path,
-                        content: combined_content,
-                        source: FileSource::Merged,
+                        content: Self::concatenate(&files),
+                        mode: files.first().and_then(|file| file.mode),
+                        source: FileSource::merged(&files),
                         merge_strategy: FileMergingStrategy::Append,
                         is_template: false,
                     })
                 }
             }
             FileMergingStrategy::Skip => {
-                
-                Ok(files.into_iter().next().unwrap())
+                let source = FileSource::merged(&files);
+                let mut kept = files.into_iter().next().unwrap();
+                kept.source = source;
+                kept.merge_strategy = FileMergingStrategy::Skip;
+                Ok(kept)
             }
         }
     }
 
+    /* Joins text files end to end, one after another */
+    fn concatenate(files: &[ComposedFile]) -> FileContent {
+        let mut combined_content = String::new();
+        for file in files {
+            combined_content.push_str(file.content.as_text().unwrap_or_default());
+            combined_content.push('\n');
+        }
+        combined_content.into()
+    }
+
     /*
     Merges JSON files by combining their objects.
     Handles package.json dependency merging specifically.
@@ -921,28 +1194,23 @@ impl CompositionEngine {
         let mut merged_json = serde_json::Map::new();
 
         for file in &files {
-            let json: serde_json::Value = serde_json::from_str(&file.content).map_err(|e| {
+            let json: serde_json::Value = serde_json::from_slice(file.content.as_bytes()).map_err(|e| {
                 EngineError::composition_error(format!("Invalid JSON in {}: {}", path.display(), e))
             })?;
 
             if let serde_json::Value::Object(obj) = json {
                 for (key, value) in obj {
                     match merged_json.get(&key) {
-                        Some(existing) => {
-                            
+                        Some(existing)
                             if (key == "dependencies" || key == "devDependencies")
                                 && existing.is_object()
-                                && value.is_object()
-                            {
-                                let mut merged_deps = existing.as_object().unwrap().clone();
-                                merged_deps.extend(value.as_object().unwrap().clone());
-                                merged_json.insert(key, serde_json::Value::Object(merged_deps));
-                            } else {
-                                
-                                merged_json.insert(key, value);
-                            }
+                                && value.is_object() =>
+                        {
+                            let mut merged_deps = existing.as_object().unwrap().clone();
+                            merged_deps.extend(value.as_object().unwrap().clone());
+                            merged_json.insert(key, serde_json::Value::Object(merged_deps));
                         }
-                        None => {
+                        _ => {
                             merged_json.insert(key, value);
                         }
                     }
@@ -956,8 +1224,9 @@ impl CompositionEngine {
 
         Ok(ComposedFile {
             path,
-            content: merged_content,
-            source: FileSource::Merged,
+            content: merged_content.into(),
+            mode: files.first().and_then(|file| file.mode),
+            source: FileSource::merged(&files),
             merge_strategy: FileMergingStrategy::Merge,
             is_template: false,
         })
@@ -1098,25 +1367,15 @@ impl CompositionEngine {
         files: Vec<ComposedFile>,
         services: &[ServiceSelection],
         composition_config: &Option<CompositionConfig>,
+        context: Option<&crate::Context>,
     ) -> EngineResult<Vec<ComposedFile>> {
         let mut filtered_files = Vec::new();
 
-        
-        let mut context = HashMap::new();
-
-        
-        for service in services {
-            let category_key = format!("{:?}", service.category).to_lowercase();
-            context.insert(category_key, service.provider.clone());
-            context.insert(
-                format!("has_{}", format!("{:?}", service.category).to_lowercase()),
-                "true".to_string(),
-            );
-        }
+        let condition_context = self.build_condition_context(services, context);
 
         for file in files {
             let should_include = self
-                .evaluate_file_conditions(&file, &context, composition_config)
+                .evaluate_file_conditions(&file, services, &condition_context, composition_config)
                 .await?;
 
             if should_include {
@@ -1128,124 +1387,77 @@ impl CompositionEngine {
     }
 
     /*
-    Evaluates whether a file should be included based on its conditions.
+    Builds the context conditional file expressions are evaluated against: the user's
+    variables plus `services.<category>` (provider or "none"), `has_<category>` and
+    `service_config.<category>.<key>` for the selected services. Keys are the prompt
+    names, without the `config_` prefix prompted values are stored under.
     */
-    async fn evaluate_file_conditions(
+    fn build_condition_context(
         &self,
-        file: &ComposedFile,
-        context: &HashMap<String, String>,
-        composition_config: &Option<CompositionConfig>,
-    ) -> EngineResult<bool> {
-        
-        if let Some(config) = composition_config {
-            for conditional_file in &config.conditional_files {
-                if file.path == PathBuf::from(&conditional_file.path) {
-                    return self
-                        .evaluate_condition(&conditional_file.condition, context)
-                        .await;
-                }
-            }
-        }
-
-        
-        self.evaluate_implicit_conditions(file, context).await
-    }
-
-    /*
-    Evaluates a condition string against the current context.
-    Supports basic conditions like:
-    - "services.auth == 'clerk'"
-    - "services.payments in ['stripe']"
-    - "has_auth && has_payments"
-    */
-    async fn evaluate_condition(
-        &self,
-        condition: &str,
-        context: &HashMap<String, String>,
-    ) -> EngineResult<bool> {
-        let condition = condition.trim();
-
-        
-        if condition.contains("&&") {
-            let parts: Vec<&str> = condition.split("&&").collect();
-            for part in parts {
-                let result = self.evaluate_simple_condition(part.trim(), context)?;
-                if !result {
-                    return Ok(false);
-                }
-            }
-            return Ok(true);
-        }
-
-        
-        if condition.contains("||") {
-            let parts: Vec<&str> = condition.split("||").collect();
-            for part in parts {
-                let result = self.evaluate_simple_condition(part.trim(), context)?;
-                if result {
-                    return Ok(true);
-                }
+        services: &[ServiceSelection],
+        context: Option<&crate::Context>,
+    ) -> crate::Context {
+        let mut condition_context = context.cloned().unwrap_or_default();
+        let mut providers = serde_yaml::Mapping::new();
+        let mut service_config = serde_yaml::Mapping::new();
+
+        for category in ServiceCategory::all() {
+            let category_key = format!("{:?}", category).to_lowercase();
+            let selected = services
+                .iter()
+                .find(|service| &service.category == category && service.provider != "none");
+
+            providers.insert(
+                category_key.clone().into(),
+                selected
+                    .map(|service| service.provider.clone())
+                    .unwrap_or_else(|| "none".to_string())
+                    .into(),
+            );
+            condition_context
+                .add_variable(format!("has_{}", category_key), selected.is_some().into());
+
+            if let Some(service) = selected {
+                let config: serde_yaml::Mapping = service
+                    .config
+                    .iter()
+                    .map(|(key, value)| {
+                        (
+                            key.strip_prefix("config_").unwrap_or(key).into(),
+                            serde_yaml::to_value(value).unwrap_or_default(),
+                        )
+                    })
+                    .collect();
+                service_config.insert(category_key.into(), config.into());
             }
-            return Ok(false);
         }
 
-        
-        self.evaluate_simple_condition(condition, context)
+        condition_context.add_variable("services".to_string(), providers.into());
+        condition_context.add_variable("service_config".to_string(), service_config.into());
+        condition_context
     }
 
     /*
-    Evaluates simple conditions without recursion.
-    Handles basic equality, membership, and boolean conditions.
+    Evaluates whether a file should be included based on its conditions.
     */
-    fn evaluate_simple_condition(
+    async fn evaluate_file_conditions(
         &self,
-        condition: &str,
-        context: &HashMap<String, String>,
+        file: &ComposedFile,
+        services: &[ServiceSelection],
+        context: &crate::Context,
+        composition_config: &Option<CompositionConfig>,
     ) -> EngineResult<bool> {
-        let condition = condition.trim();
-
         
-        if condition.contains("==") {
-            let parts: Vec<&str> = condition.split("==").collect();
-            if parts.len() == 2 {
-                let left = parts[0].trim();
-                let right = parts[1].trim().trim_matches('\'').trim_matches('"');
-
-                if left.starts_with("services.") {
-                    let service_type = left.strip_prefix("services.").unwrap();
-                    return Ok(context.get(service_type).map_or(false, |v| v == right));
-                }
-            }
-        }
-
-        
-        if condition.contains(" in ") {
-            let parts: Vec<&str> = condition.split(" in ").collect();
-            if parts.len() == 2 {
-                let left = parts[0].trim();
-                let right = parts[1].trim();
-
-                if left.starts_with("services.") && right.starts_with('[') && right.ends_with(']') {
-                    let service_type = left.strip_prefix("services.").unwrap();
-                    let options: Vec<&str> = right[1..right.len() - 1]
-                        .split(',')
-                        .map(|s| s.trim().trim_matches('\'').trim_matches('"'))
-                        .collect();
-
-                    return Ok(context
-                        .get(service_type)
-                        .map_or(false, |v| options.contains(&v.as_str())));
+        if let Some(config) = composition_config {
+            for conditional_file in &config.conditional_files {
+                if file.path == Path::new(&conditional_file.path) {
+                    return evaluate_condition(&conditional_file.condition, context);
                 }
             }
         }
 
         
-        if condition.starts_with("has_") {
-            return Ok(context.get(condition).map_or(false, |v| v == "true"));
-        }
-
-        
-        Ok(true)
+        self.evaluate_implicit_conditions(file, services).await
     }
 
     /*
@@ -1255,7 +1467,7 @@ impl CompositionEngine {
     async fn evaluate_implicit_conditions(
         &self,
         file: &ComposedFile,
-        context: &HashMap<String, String>,
+        services: &[ServiceSelection],
     ) -> EngineResult<bool> {
         match &file.source {
             FileSource::BaseTemplate => {
@@ -1264,12 +1476,11 @@ impl CompositionEngine {
             }
             FileSource::Service { category, provider } => {
                 
-                let category_key = format!("{:?}", category).to_lowercase();
-                Ok(context
-                    .get(&category_key)
-                    .map_or(false, |selected| selected == provider))
+                Ok(services
+                    .iter()
+                    .any(|service| &service.category == category && &service.provider == provider))
             }
-            FileSource::Merged => {
+            FileSource::Merged { .. } => {
                 
                 Ok(true)
             }
@@ -1286,6 +1497,57 @@ impl CompositionEngine {
         
         Ok(HashMap::new())
     }
+
+    /*
+    Collects pre/post generation hooks: the base template's hooks run first, followed by
+    service hooks grouped by service category (in category order, then provider name).
+    */
+    pub async fn collect_hooks(
+        &self,
+        base_config: &TemplateConfig,
+        services: &[ServiceSelection],
+    ) -> EngineResult<Hooks> {
+        let mut pre_generate: Vec<HookCommand> = Vec::new();
+        let mut post_generate: Vec<HookCommand> = Vec::new();
+
+        if let Some(hooks) = &base_config.hooks {
+            pre_generate.extend(hooks.pre_generate.iter().flatten().cloned());
+            post_generate.extend(hooks.post_generate.iter().flatten().cloned());
+        }
+
+        let mut ordered: Vec<&ServiceSelection> = services
+            .iter()
+            .filter(|service| service.provider != "none")
+            .collect();
+        ordered.sort_by(|a, b| {
+            a.category
+                .cmp(&b.category)
+                .then_with(|| a.provider.cmp(&b.provider))
+        });
+
+        for service in ordered {
+            let service_config_path = self
+                .shared_services_path
+                .join(format!("{:?}", service.category).to_lowercase())
+                .join(&service.provider)
+                .join("anvil.yaml");
+
+            if !service_config_path.exists() {
+                continue;
+            }
+
+            let service_config = ServiceConfig::from_file(&service_config_path).await?;
+            if let Some(hooks) = service_config.hooks {
+                pre_generate.extend(hooks.pre_generate.into_iter().flatten());
+                post_generate.extend(hooks.post_generate.into_iter().flatten());
+            }
+        }
+
+        Ok(Hooks {
+            pre_generate: (!pre_generate.is_empty()).then_some(pre_generate),
+            post_generate: (!post_generate.is_empty()).then_some(post_generate),
+        })
+    }
 }
 
 #[cfg(test)]
@@ -1356,4 +1618,347 @@ services:
         assert_eq!(composed.base_config.name, "test-app");
         assert!(!composed.files.is_empty());
     }
+
+    #[tokio::test]
+    async fn test_service_hooks_follow_base_hooks_in_category_order() {
+        let temp_dir = create_test_structure().await;
+        let base_path = temp_dir.path();
+
+        fs::write(
+            base_path.join("templates/test-app/anvil.yaml"),
+            r#"
+name: "test-app"
+description: "Test application"
+version: "1.0.0"
+services:
+  - name: "auth"
+    category: "auth"
+    prompt: "Choose auth provider"
+    options: ["clerk"]
+  - name: "database"
+    category: "database"
+    prompt: "Choose database"
+    options: ["neon"]
+hooks:
+  post_generate:
+    - command: "npm install"
+"#,
+        )
+        .await
+        .unwrap();
+
+        fs::create_dir_all(base_path.join("templates/shared/database/neon"))
+            .await
+            .unwrap();
+        fs::write(
+            base_path.join("templates/shared/database/neon/anvil.yaml"),
+            r#"
+name: "neon"
+description: "Neon database"
+version: "1.0.0"
+category: "database"
+hooks:
+  post_generate:
+    - command: "npx drizzle-kit generate:pg"
+"#,
+        )
+        .await
+        .unwrap();
+        fs::write(
+            base_path.join("templates/shared/auth/clerk/anvil.yaml"),
+            r#"
+name: "clerk"
+description: "Clerk auth"
+version: "1.0.0"
+category: "auth"
+hooks:
+  pre_generate:
+    - command: "echo clerk"
+  post_generate:
+    - command: "npx clerk-setup"
+"#,
+        )
+        .await
+        .unwrap();
+
+        let engine = CompositionEngine::new(
+            base_path.join("templates"),
+            base_path.join("templates/shared"),
+        );
+
+        // Selection order must not affect hook order
+        let services = vec![
+            ServiceSelection {
+                category: ServiceCategory::Database,
+                provider: "neon".to_string(),
+                config: HashMap::new(),
+            },
+            ServiceSelection {
+                category: ServiceCategory::Auth,
+                provider: "clerk".to_string(),
+                config: HashMap::new(),
+            },
+        ];
+
+        let composed = engine.compose_template("test-app", services).await.unwrap();
+
+        let commands = |hooks: &Option<Vec<HookCommand>>| -> Vec<String> {
+            hooks
+                .iter()
+                .flatten()
+                .map(|hook| hook.command.clone())
+                .collect()
+        };
+
+        assert_eq!(commands(&composed.hooks.pre_generate), vec!["echo clerk"]);
+        assert_eq!(
+            commands(&composed.hooks.post_generate),
+            vec![
+                "npm install",
+                "npx clerk-setup",
+                "npx drizzle-kit generate:pg"
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_conditional_files_use_condition_language() {
+        let temp_dir = create_test_structure().await;
+        let base_path = temp_dir.path();
+
+        fs::write(
+            base_path.join("templates/test-app/anvil.yaml"),
+            r#"
+name: "test-app"
+description: "Test application"
+version: "1.0.0"
+services:
+  - name: "auth"
+    category: "auth"
+    prompt: "Choose auth provider"
+    options: ["clerk"]
+composition:
+  conditional_files:
+    - path: "billing.ts"
+      condition: "(services.auth == 'clerk' || has_payments) && !use_mocks"
+    - path: "mocks.ts"
+      condition: "use_mocks && services.auth in ['auth0', 'firebase']"
+    - path: "webhooks.ts"
+      condition: "service_config.auth.enable_webhooks == true"
+"#,
+        )
+        .await
+        .unwrap();
+        fs::write(base_path.join("templates/test-app/billing.ts"), "// billing")
+            .await
+            .unwrap();
+        fs::write(base_path.join("templates/test-app/mocks.ts"), "// mocks")
+            .await
+            .unwrap();
+        fs::write(base_path.join("templates/test-app/webhooks.ts"), "// webhooks")
+            .await
+            .unwrap();
+
+        let engine = CompositionEngine::new(
+            base_path.join("templates"),
+            base_path.join("templates/shared"),
+        );
+        // Prompted service values are stored with a `config_` prefix
+        let services = vec![ServiceSelection {
+            category: ServiceCategory::Auth,
+            provider: "clerk".to_string(),
+            config: HashMap::from([(
+                "config_enable_webhooks".to_string(),
+                serde_json::Value::Bool(true),
+            )]),
+        }];
+        let context = crate::Context::builder().variable("use_mocks", false).build();
+
+        let composed = engine
+            .compose_template_with_context("test-app", services.clone(), Some(&context))
+            .await
+            .unwrap();
+        let paths: Vec<_> = composed.files.iter().map(|f| f.path.clone()).collect();
+        assert!(paths.contains(&PathBuf::from("billing.ts")));
+        assert!(!paths.contains(&PathBuf::from("mocks.ts")));
+        assert!(paths.contains(&PathBuf::from("webhooks.ts")));
+
+        // Without the variable in scope the condition refers to an unknown identifier
+        let result = engine.compose_template("test-app", services).await;
+        assert!(matches!(result, Err(EngineError::ConditionError { .. })));
+    }
+
+    #[tokio::test]
+    async fn test_add_service_merges_into_existing_project() {
+        let temp_dir = create_test_structure().await;
+        let base_path = temp_dir.path();
+
+        fs::write(
+            base_path.join("templates/test-app/anvil.yaml"),
+            r#"
+name: "test-app"
+description: "Test application"
+version: "1.0.0"
+services:
+  - name: "auth"
+    category: "auth"
+    prompt: "Choose auth provider"
+    options: ["clerk"]
+  - name: "payments"
+    category: "payments"
+    prompt: "Choose payments provider"
+    options: ["stripe"]
+"#,
+        )
+        .await
+        .unwrap();
+        let stripe_dir = base_path.join("templates/shared/payments/stripe");
+        fs::create_dir_all(stripe_dir.join("lib")).await.unwrap();
+        fs::write(
+            stripe_dir.join("package.json"),
+            r#"{"dependencies": {"stripe": "^14.0.0"}}"#,
+        )
+        .await
+        .unwrap();
+        fs::write(stripe_dir.join("lib/stripe.ts"), "// stripe")
+            .await
+            .unwrap();
+        fs::write(stripe_dir.join(".env.example"), "STRIPE_SECRET_KEY=\n")
+            .await
+            .unwrap();
+        fs::write(stripe_dir.join("README.md"), "## Payments\n")
+            .await
+            .unwrap();
+
+        let project_dir = base_path.join("project");
+        fs::create_dir_all(&project_dir).await.unwrap();
+        fs::write(
+            project_dir.join("package.json"),
+            r#"{"name": "app", "dependencies": {"next": "14.0.0"}}"#,
+        )
+        .await
+        .unwrap();
+        fs::write(project_dir.join(".env.example"), "DATABASE_URL=\n")
+            .await
+            .unwrap();
+        fs::write(project_dir.join("README.md"), "# app\n\n## Payments\n")
+            .await
+            .unwrap();
+        fs::create_dir_all(project_dir.join("lib")).await.unwrap();
+        fs::write(project_dir.join("lib/stripe.ts"), "// stripe")
+            .await
+            .unwrap();
+
+        let engine = CompositionEngine::new(
+            base_path.join("templates"),
+            base_path.join("templates/shared"),
+        );
+        let existing = vec![ServiceSelection {
+            category: ServiceCategory::Auth,
+            provider: "clerk".to_string(),
+            config: HashMap::new(),
+        }];
+        let stripe = ServiceSelection {
+            category: ServiceCategory::Payments,
+            provider: "stripe".to_string(),
+            config: HashMap::new(),
+        };
+
+        let composed = engine
+            .compose_service_addition("test-app", &existing, stripe.clone(), None)
+            .await
+            .unwrap();
+        let mut paths: Vec<_> = composed.files.iter().map(|f| f.path.clone()).collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from(".env.example"),
+                PathBuf::from("README.md"),
+                PathBuf::from("lib/stripe.ts"),
+                PathBuf::from("package.json")
+            ]
+        );
+
+        let rendered = || {
+            composed
+                .files
+                .iter()
+                .map(|file| ProcessedFile {
+                    output_path: file.path.clone(),
+                    content: file.content.clone(),
+                    mode: file.mode,
+                    executable: false,
+                    source: file.source.clone(),
+                    merge_strategy: None,
+                })
+                .collect::<Vec<_>>()
+        };
+        let content = |files: &[ProcessedFile], path: &str| {
+            files
+                .iter()
+                .find(|file| file.output_path == Path::new(path))
+                .map(|file| file.content.as_text().unwrap().to_string())
+        };
+
+        // lib/stripe.ts is already in the project as the service renders it
+        let merged = engine
+            .merge_into_project(&stripe, rendered(), &project_dir, &FileMergingStrategy::Merge)
+            .await
+            .unwrap();
+        let mut paths: Vec<_> = merged.iter().map(|f| f.output_path.clone()).collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from(".env.example"),
+                PathBuf::from("README.md"),
+                PathBuf::from("package.json")
+            ]
+        );
+        // Under merge, files other than JSON keep the service's content for the conflict mode
+        assert_eq!(
+            content(&merged, ".env.example").as_deref(),
+            Some("STRIPE_SECRET_KEY=\n")
+        );
+        assert_eq!(content(&merged, "README.md").as_deref(), Some("## Payments\n"));
+        let json: Value =
+            serde_json::from_str(&content(&merged, "package.json").unwrap()).unwrap();
+        assert_eq!(json["name"], "app");
+        assert_eq!(json["dependencies"]["next"], "14.0.0");
+        assert_eq!(json["dependencies"]["stripe"], "^14.0.0");
+
+        // Other strategies resolve existing files the same way generation does
+        let merged = engine
+            .merge_into_project(&stripe, rendered(), &project_dir, &FileMergingStrategy::Append)
+            .await
+            .unwrap();
+        assert_eq!(
+            content(&merged, ".env.example").as_deref(),
+            Some("DATABASE_URL=\n\nSTRIPE_SECRET_KEY=\n\n")
+        );
+        assert_eq!(merged.len(), 3);
+
+        let merged = engine
+            .merge_into_project(&stripe, rendered(), &project_dir, &FileMergingStrategy::Skip)
+            .await
+            .unwrap();
+        assert!(merged.is_empty());
+
+        // A category the project already has, or the template does not offer, is refused
+        let clerk_again = existing[0].clone();
+        assert!(engine
+            .compose_service_addition("test-app", &existing, clerk_again, None)
+            .await
+            .is_err());
+        let email = ServiceSelection {
+            category: ServiceCategory::Email,
+            provider: "resend".to_string(),
+            config: HashMap::new(),
+        };
+        assert!(engine
+            .compose_service_addition("test-app", &existing, email, None)
+            .await
+            .is_err());
+    }
 }
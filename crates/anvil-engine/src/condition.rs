@@ -0,0 +1,705 @@
+/*
+Module for the condition language shared by conditional files, feature enabled_when
+and hook conditions in anvil.yaml.
+
+Grammar, from lowest to highest precedence:
+- `a || b`, `a or b`
+- `a && b`, `a and b`
+- `!a`, `not a`
+- `a == b`, `a != b`, `a < b`, `a <= b`, `a > b`, `a >= b`, `a in [..]`, `a not in [..]`
+- parentheses, lists `['x', 'y']`, strings, numbers, `true`/`false`/`null`,
+  `always`/`never`, and dotted identifiers such as `services.auth`
+
+Identifiers resolve against the context: template variables (nested with dots),
+`features` (the list of enabled features) and `feature_<name>` (whether a declared
+feature is enabled). A declared feature's bare name reads as `feature_<name>`, which
+the CLI warns about. Any other identifier is an error, so typos never silently pass.
+A missing key under a mapping variable is null rather than an error, since mappings
+such as `service_config` only hold the services and settings that were chosen.
+*/
+
+use std::cmp::Ordering;
+
+use crate::engine::Context;
+use crate::error::{EngineError, EngineResult};
+
+/*
+Parses and evaluates a condition against the context. An empty condition is true.
+*/
+pub fn evaluate_condition(condition: &str, context: &Context) -> EngineResult<bool> {
+    Condition::parse(condition)?.evaluate(context)
+}
+
+#[derive(Debug, Clone)]
+pub struct Condition {
+    source: String,
+    expr: Expr,
+}
+
+impl Condition {
+    pub fn parse(source: &str) -> EngineResult<Self> {
+        let error = |reason: String| EngineError::condition_error(source, reason);
+
+        let tokens = tokenize(source).map_err(error)?;
+        let expr = if tokens.is_empty() {
+            Expr::Literal(Value::Bool(true))
+        } else {
+            let mut parser = Parser { tokens, pos: 0 };
+            let expr = parser.parse_or().map_err(error)?;
+            if let Some(token) = parser.peek() {
+                return Err(error(format!("unexpected {}", token.describe())));
+            }
+            expr
+        };
+
+        Ok(Self {
+            source: source.to_string(),
+            expr,
+        })
+    }
+
+    pub fn evaluate(&self, context: &Context) -> EngineResult<bool> {
+        self.expr
+            .evaluate(context)
+            .map(|value| value.is_truthy())
+            .map_err(|reason| EngineError::condition_error(&self.source, reason))
+    }
+
+    pub fn source(&self) -> &str {
+        &self.source
+    }
+
+    /* The first segment of every identifier the condition mentions, in order */
+    pub fn identifiers(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        self.expr.collect_identifiers(&mut names);
+        names
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Value {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    List(Vec<Value>),
+}
+
+impl Value {
+    fn from_yaml(value: &serde_yaml::Value) -> Self {
+        match value {
+            serde_yaml::Value::Null => Value::Null,
+            serde_yaml::Value::Bool(b) => Value::Bool(*b),
+            serde_yaml::Value::Number(n) => Value::Number(n.as_f64().unwrap_or_default()),
+            serde_yaml::Value::String(s) => Value::String(s.clone()),
+            serde_yaml::Value::Sequence(items) => {
+                Value::List(items.iter().map(Value::from_yaml).collect())
+            }
+            serde_yaml::Value::Mapping(mapping) => Value::Bool(!mapping.is_empty()),
+            serde_yaml::Value::Tagged(tagged) => Value::from_yaml(&tagged.value),
+        }
+    }
+
+    /*
+    "none" is falsy so that `services.auth` reads naturally when no provider is selected.
+    */
+    fn is_truthy(&self) -> bool {
+        match self {
+            Value::Null => false,
+            Value::Bool(b) => *b,
+            Value::Number(n) => *n != 0.0,
+            Value::String(s) => !s.is_empty() && s != "none",
+            Value::List(items) => !items.is_empty(),
+        }
+    }
+
+    fn as_number(&self) -> Option<f64> {
+        match self {
+            Value::Number(n) => Some(*n),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        }
+    }
+
+    fn as_text(&self) -> Option<String> {
+        match self {
+            Value::Bool(b) => Some(b.to_string()),
+            Value::Number(n) => Some(n.to_string()),
+            Value::String(s) => Some(s.clone()),
+            Value::Null | Value::List(_) => None,
+        }
+    }
+
+    /*
+    Values of the same type compare directly. Mixed scalars compare numerically when
+    both sides are numbers, and by their text otherwise, so answers stored as
+    strings ("true", "3") still match boolean and numeric literals.
+    */
+    fn loose_eq(&self, other: &Value) -> bool {
+        match (self, other) {
+            (Value::List(a), Value::List(b)) => {
+                a.len() == b.len() && a.iter().zip(b).all(|(x, y)| x.loose_eq(y))
+            }
+            (Value::Null, Value::Null) => true,
+            (Value::Null, _) | (_, Value::Null) => false,
+            _ => match (self.as_number(), other.as_number()) {
+                (Some(a), Some(b)) => a == b,
+                _ => self.as_text() == other.as_text(),
+            },
+        }
+    }
+
+    fn compare(&self, other: &Value) -> Result<Ordering, String> {
+        if let (Some(a), Some(b)) = (self.as_number(), other.as_number()) {
+            return a
+                .partial_cmp(&b)
+                .ok_or_else(|| "cannot compare NaN".to_string());
+        }
+
+        match (self, other) {
+            (Value::String(a), Value::String(b)) => Ok(a.cmp(b)),
+            _ => Err(format!(
+                "cannot order {} and {}",
+                self.type_name(),
+                other.type_name()
+            )),
+        }
+    }
+
+    fn contains(&self, needle: &Value) -> Result<bool, String> {
+        match self {
+            Value::List(items) => Ok(items.iter().any(|item| item.loose_eq(needle))),
+            Value::String(haystack) => match needle.as_text() {
+                Some(text) => Ok(haystack.contains(&text)),
+                None => Ok(false),
+            },
+            other => Err(format!(
+                "right side of 'in' must be a list or string, found {}",
+                other.type_name()
+            )),
+        }
+    }
+
+    fn type_name(&self) -> &'static str {
+        match self {
+            Value::Null => "null",
+            Value::Bool(_) => "boolean",
+            Value::Number(_) => "number",
+            Value::String(_) => "string",
+            Value::List(_) => "list",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CompareOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    In,
+    NotIn,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Literal(Value),
+    Identifier(Vec<String>),
+    List(Vec<Expr>),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Compare(CompareOp, Box<Expr>, Box<Expr>),
+}
+
+impl Expr {
+    /*
+    Both sides of && and || are always evaluated so unknown identifiers are reported
+    even when the result would not depend on them.
+    */
+    fn evaluate(&self, context: &Context) -> Result<Value, String> {
+        match self {
+            Expr::Literal(value) => Ok(value.clone()),
+            Expr::Identifier(path) => resolve_identifier(path, context),
+            Expr::List(items) => Ok(Value::List(
+                items
+                    .iter()
+                    .map(|item| item.evaluate(context))
+                    .collect::<Result<_, _>>()?,
+            )),
+            Expr::Not(inner) => Ok(Value::Bool(!inner.evaluate(context)?.is_truthy())),
+            Expr::And(left, right) => {
+                let left = left.evaluate(context)?.is_truthy();
+                let right = right.evaluate(context)?.is_truthy();
+                Ok(Value::Bool(left && right))
+            }
+            Expr::Or(left, right) => {
+                let left = left.evaluate(context)?.is_truthy();
+                let right = right.evaluate(context)?.is_truthy();
+                Ok(Value::Bool(left || right))
+            }
+            Expr::Compare(op, left, right) => {
+                let left = left.evaluate(context)?;
+                let right = right.evaluate(context)?;
+                let result = match op {
+                    CompareOp::Eq => left.loose_eq(&right),
+                    CompareOp::Ne => !left.loose_eq(&right),
+                    CompareOp::Lt => left.compare(&right)? == Ordering::Less,
+                    CompareOp::Le => left.compare(&right)? != Ordering::Greater,
+                    CompareOp::Gt => left.compare(&right)? == Ordering::Greater,
+                    CompareOp::Ge => left.compare(&right)? != Ordering::Less,
+                    CompareOp::In => right.contains(&left)?,
+                    CompareOp::NotIn => !right.contains(&left)?,
+                };
+                Ok(Value::Bool(result))
+            }
+        }
+    }
+
+    fn collect_identifiers<'a>(&'a self, names: &mut Vec<&'a str>) {
+        match self {
+            Expr::Literal(_) => {}
+            Expr::Identifier(path) => names.push(&path[0]),
+            Expr::List(items) => items
+                .iter()
+                .for_each(|item| item.collect_identifiers(names)),
+            Expr::Not(inner) => inner.collect_identifiers(names),
+            Expr::And(left, right) | Expr::Or(left, right) | Expr::Compare(_, left, right) => {
+                left.collect_identifiers(names);
+                right.collect_identifiers(names);
+            }
+        }
+    }
+}
+
+fn resolve_identifier(path: &[String], context: &Context) -> Result<Value, String> {
+    let root = &path[0];
+
+    if let Some(value) = context.get_variable(root) {
+        let mut current = value;
+        for segment in &path[1..] {
+            match current {
+                serde_yaml::Value::Mapping(mapping) => match mapping.get(segment.as_str()) {
+                    Some(next) => current = next,
+                    None => return Ok(Value::Null),
+                },
+                _ => {
+                    return Err(format!(
+                        "'{}' has no field '{}'",
+                        path[..path.len() - 1].join("."),
+                        segment
+                    ))
+                }
+            }
+        }
+        return Ok(Value::from_yaml(current));
+    }
+
+    if path.len() == 1 {
+        if root == "features" {
+            return Ok(Value::List(
+                context
+                    .features()
+                    .iter()
+                    .map(|feature| Value::String(feature.clone()))
+                    .collect(),
+            ));
+        }
+
+        if let Some(feature) = root.strip_prefix("feature_") {
+            if context.declares_feature(feature) {
+                return Ok(Value::Bool(context.has_feature(feature)));
+            }
+        }
+
+        if context.declares_feature(root) {
+            return Ok(Value::Bool(context.has_feature(root)));
+        }
+    }
+
+    Err(format!("unknown identifier '{}'", path.join(".")))
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Number(f64),
+    Dot,
+    Comma,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+    Not,
+    And,
+    Or,
+    Op(CompareOp),
+}
+
+impl Token {
+    fn describe(&self) -> String {
+        match self {
+            Token::Ident(name) => format!("'{}'", name),
+            Token::Str(s) => format!("string '{}'", s),
+            Token::Number(n) => format!("number {}", n),
+            Token::Dot => "'.'".to_string(),
+            Token::Comma => "','".to_string(),
+            Token::LParen => "'('".to_string(),
+            Token::RParen => "')'".to_string(),
+            Token::LBracket => "'['".to_string(),
+            Token::RBracket => "']'".to_string(),
+            Token::Not => "'!'".to_string(),
+            Token::And => "'&&'".to_string(),
+            Token::Or => "'||'".to_string(),
+            Token::Op(op) => format!("operator {:?}", op),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let chars: Vec<char> = source.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        let next = chars.get(i + 1).copied();
+
+        match c {
+            c if c.is_whitespace() => i += 1,
+            '.' => {
+                tokens.push(Token::Dot);
+                i += 1;
+            }
+            ',' => {
+                tokens.push(Token::Comma);
+                i += 1;
+            }
+            '(' => {
+                tokens.push(Token::LParen);
+                i += 1;
+            }
+            ')' => {
+                tokens.push(Token::RParen);
+                i += 1;
+            }
+            '[' => {
+                tokens.push(Token::LBracket);
+                i += 1;
+            }
+            ']' => {
+                tokens.push(Token::RBracket);
+                i += 1;
+            }
+            '&' if next == Some('&') => {
+                tokens.push(Token::And);
+                i += 2;
+            }
+            '|' if next == Some('|') => {
+                tokens.push(Token::Or);
+                i += 2;
+            }
+            '=' if next == Some('=') => {
+                tokens.push(Token::Op(CompareOp::Eq));
+                i += 2;
+            }
+            '!' if next == Some('=') => {
+                tokens.push(Token::Op(CompareOp::Ne));
+                i += 2;
+            }
+            '!' => {
+                tokens.push(Token::Not);
+                i += 1;
+            }
+            '<' | '>' => {
+                let inclusive = next == Some('=');
+                let op = match (c, inclusive) {
+                    ('<', false) => CompareOp::Lt,
+                    ('<', true) => CompareOp::Le,
+                    ('>', false) => CompareOp::Gt,
+                    _ => CompareOp::Ge,
+                };
+                tokens.push(Token::Op(op));
+                i += if inclusive { 2 } else { 1 };
+            }
+            '\'' | '"' => {
+                let quote = c;
+                let mut value = String::new();
+                i += 1;
+                loop {
+                    match chars.get(i) {
+                        None => return Err("unterminated string".to_string()),
+                        Some('\\') if i + 1 < chars.len() => {
+                            value.push(chars[i + 1]);
+                            i += 2;
+                        }
+                        Some(&ch) if ch == quote => {
+                            i += 1;
+                            break;
+                        }
+                        Some(&ch) => {
+                            value.push(ch);
+                            i += 1;
+                        }
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            c if c.is_ascii_digit() || (c == '-' && next.is_some_and(|n| n.is_ascii_digit())) => {
+                let start = i;
+                i += 1;
+                while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                    i += 1;
+                }
+                let text: String = chars[start..i].iter().collect();
+                let number = text
+                    .parse()
+                    .map_err(|_| format!("invalid number '{}'", text))?;
+                tokens.push(Token::Number(number));
+            }
+            c if c.is_alphabetic() || c == '_' => {
+                let start = i;
+                while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_') {
+                    i += 1;
+                }
+                let word: String = chars[start..i].iter().collect();
+                tokens.push(match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    "in" => Token::Op(CompareOp::In),
+                    _ => Token::Ident(word),
+                });
+            }
+            other => return Err(format!("unexpected character '{}'", other)),
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, expected: &Token) -> bool {
+        if self.peek() == Some(expected) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, expected: &Token) -> Result<(), String> {
+        match self.next() {
+            Some(ref token) if token == expected => Ok(()),
+            Some(token) => Err(format!(
+                "expected {} but found {}",
+                expected.describe(),
+                token.describe()
+            )),
+            None => Err(format!("expected {} but reached the end", expected.describe())),
+        }
+    }
+
+    fn parse_or(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_and()?;
+        while self.eat(&Token::Or) {
+            let right = self.parse_and()?;
+            left = Expr::Or(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_and(&mut self) -> Result<Expr, String> {
+        let mut left = self.parse_not()?;
+        while self.eat(&Token::And) {
+            let right = self.parse_not()?;
+            left = Expr::And(Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn parse_not(&mut self) -> Result<Expr, String> {
+        if self.eat(&Token::Not) {
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_comparison()
+    }
+
+    fn parse_comparison(&mut self) -> Result<Expr, String> {
+        let left = self.parse_primary()?;
+
+        let op = match self.peek() {
+            Some(Token::Op(op)) => *op,
+            // `not in` is the only place `not` may follow an operand
+            Some(Token::Not)
+                if self.tokens.get(self.pos + 1) == Some(&Token::Op(CompareOp::In)) =>
+            {
+                self.pos += 1;
+                CompareOp::NotIn
+            }
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+
+        let right = self.parse_primary()?;
+        Ok(Expr::Compare(op, Box::new(left), Box::new(right)))
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::LParen) => {
+                let expr = self.parse_or()?;
+                self.expect(&Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::LBracket) => {
+                let mut items = Vec::new();
+                if !self.eat(&Token::RBracket) {
+                    loop {
+                        items.push(self.parse_primary()?);
+                        if self.eat(&Token::RBracket) {
+                            break;
+                        }
+                        self.expect(&Token::Comma)?;
+                    }
+                }
+                Ok(Expr::List(items))
+            }
+            Some(Token::Str(s)) => Ok(Expr::Literal(Value::String(s))),
+            Some(Token::Number(n)) => Ok(Expr::Literal(Value::Number(n))),
+            Some(Token::Ident(name)) => match name.as_str() {
+                "true" | "always" => Ok(Expr::Literal(Value::Bool(true))),
+                "false" | "never" => Ok(Expr::Literal(Value::Bool(false))),
+                "null" => Ok(Expr::Literal(Value::Null)),
+                _ => {
+                    let mut path = vec![name];
+                    while self.eat(&Token::Dot) {
+                        match self.next() {
+                            Some(Token::Ident(segment)) => path.push(segment),
+                            _ => return Err("expected a name after '.'".to_string()),
+                        }
+                    }
+                    Ok(Expr::Identifier(path))
+                }
+            },
+            Some(token) => Err(format!("unexpected {}", token.describe())),
+            None => Err("unexpected end of condition".to_string()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> Context {
+        let mut services = serde_yaml::Mapping::new();
+        services.insert("auth".into(), "clerk".into());
+        services.insert("payments".into(), "none".into());
+
+        let mut service_config = serde_yaml::Mapping::new();
+        service_config.insert("auth".into(), serde_yaml::Mapping::new().into());
+
+        let mut context = Context::builder()
+            .variable("package_manager", "pnpm")
+            .variable("setup_git", true)
+            .variable("port", 8080)
+            .variable("enable_pooling", "true")
+            .variable("services", serde_yaml::Value::Mapping(services))
+            .variable("service_config", serde_yaml::Value::Mapping(service_config))
+            .feature("typescript")
+            .build();
+        context.declare_feature("tailwind".to_string());
+        context
+    }
+
+    fn eval(condition: &str) -> bool {
+        evaluate_condition(condition, &context()).unwrap()
+    }
+
+    #[test]
+    fn test_conditions() {
+        assert!(eval(""));
+        assert!(eval("always"));
+        assert!(!eval("never"));
+        assert!(eval("feature_typescript"));
+        assert!(!eval("feature_tailwind"));
+        assert!(eval("'typescript' in features"));
+        assert!(eval("setup_git"));
+        assert!(!eval("!setup_git"));
+        assert!(eval("package_manager == 'pnpm'"));
+        assert!(eval("package_manager != \"npm\""));
+        assert!(eval("enable_pooling == true"));
+        assert!(eval("port >= 8000 && port < 9000"));
+        assert!(eval("services.auth in ['clerk', 'auth0']"));
+        assert!(eval("services.auth not in ['auth0']"));
+        assert!(!eval("services.payments"));
+        assert!(!eval("services.database"));
+        assert!(eval("service_config.auth.domain == null"));
+        assert!(eval("service_config.database == null"));
+    }
+
+    #[test]
+    fn test_precedence_and_grouping() {
+        assert!(eval("setup_git || never && never"));
+        assert!(!eval("(setup_git || never) && never"));
+        assert!(eval("not (services.auth == 'auth0') and setup_git"));
+        assert!(eval("!!setup_git"));
+    }
+
+    #[test]
+    fn test_errors() {
+        let context = context();
+
+        let err = evaluate_condition("missing_variable", &context).unwrap_err();
+        assert!(err.to_string().contains("unknown identifier 'missing_variable'"));
+
+        // Unknown identifiers are reported even when the other side decides the result
+        assert!(evaluate_condition("setup_git || typo", &context).is_err());
+
+        // Only declared features can be named with the prefix
+        let err = evaluate_condition("feature_x", &context).unwrap_err();
+        assert!(err.to_string().contains("unknown identifier 'feature_x'"));
+
+        assert!(evaluate_condition("port.number", &context).is_err());
+
+        assert!(Condition::parse("services.auth ==").is_err());
+        assert!(Condition::parse("(setup_git").is_err());
+        assert!(Condition::parse("'unterminated").is_err());
+        assert!(Condition::parse("setup_git setup_git").is_err());
+        assert!(evaluate_condition("package_manager < 3", &context).is_err());
+    }
+
+    #[test]
+    fn test_bare_feature_names() {
+        let mut context = context();
+        assert!(evaluate_condition("sass", &context).is_err());
+
+        assert!(evaluate_condition("typescript", &context).unwrap());
+        assert!(!evaluate_condition("tailwind", &context).unwrap());
+        assert!(evaluate_condition("typescript && !tailwind", &context).unwrap());
+
+        // Variables win over features of the same name
+        context.declare_feature("setup_git".to_string());
+        assert!(evaluate_condition("setup_git", &context).unwrap());
+    }
+}
@@ -1,4 +1,7 @@
+use crate::condition::Condition;
 use crate::error::{EngineError, EngineResult};
+use crate::exclude::validate_patterns;
+use crate::variables::variable_order;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
@@ -22,6 +25,9 @@ pub struct TemplateConfig {
     #[serde(default = "default_min_anvil_version")]
     pub min_anvil_version: String,
 
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_anvil_version: Option<String>,
+
     #[serde(default)]
     pub services: Vec<ServiceDefinition>,
 
@@ -30,6 +36,13 @@ pub struct TemplateConfig {
 
     #[serde(default)]
     pub service_combinations: Vec<ServiceCombination>,
+
+    /* Gitignore-style patterns for files in the template directory that are never generated */
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub each: Vec<EachRule>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,9 +52,14 @@ pub struct TemplateVariable {
     pub var_type: VariableType,
     pub prompt: String,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub default: Option<serde_yaml::Value>,
     #[serde(default)]
     pub required: bool,
+    /* Condition on earlier answers; the variable is skipped entirely when it is false */
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub when: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -52,17 +70,44 @@ pub enum VariableType {
         min_length: usize,
         #[serde(skip_serializing_if = "Option::is_none")]
         max_length: Option<usize>,
+        /* Regex the whole value must match */
+        #[serde(skip_serializing_if = "Option::is_none")]
+        pattern: Option<String>,
     },
     Boolean,
     Choice {
         options: Vec<String>,
     },
+    /* Any number of the options, rendered as a list of strings */
+    MultiChoice {
+        options: Vec<String>,
+    },
     Number {
         #[serde(skip_serializing_if = "Option::is_none")]
         min: Option<i64>,
         #[serde(skip_serializing_if = "Option::is_none")]
         max: Option<i64>,
     },
+    Float {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        min: Option<f64>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        max: Option<f64>,
+    },
+    /* Without `items` the elements can be anything, maps included */
+    List {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        items: Option<Box<VariableType>>,
+        #[serde(default)]
+        min_items: usize,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        max_items: Option<usize>,
+    },
+    /* A string that is prompted for without echo and never saved with the answers */
+    Secret {
+        #[serde(default)]
+        min_length: usize,
+    },
 }
 
 impl VariableType {
@@ -71,9 +116,22 @@ impl VariableType {
             VariableType::String { .. } => "string".to_string(),
             VariableType::Boolean => "boolean".to_string(),
             VariableType::Choice { .. } => "choice".to_string(),
+            VariableType::MultiChoice { .. } => "multi_choice".to_string(),
             VariableType::Number { .. } => "number".to_string(),
+            VariableType::Float { .. } => "float".to_string(),
+            VariableType::List {
+                items: Some(items), ..
+            } => {
+                format!("list of {}", items.type_name())
+            }
+            VariableType::List { items: None, .. } => "list".to_string(),
+            VariableType::Secret { .. } => "secret".to_string(),
         }
     }
+
+    pub fn is_secret(&self) -> bool {
+        matches!(self, VariableType::Secret { .. })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -86,7 +144,7 @@ pub struct Feature {
     pub dependencies: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Hooks {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub pre_generate: Option<Vec<HookCommand>>,
@@ -103,6 +161,21 @@ pub struct HookCommand {
     pub condition: Option<String>,
     #[serde(default)]
     pub env: HashMap<String, String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub timeout: Option<u64>,
+    #[serde(default)]
+    pub on_failure: HookFailurePolicy,
+    #[serde(default)]
+    pub capture_output: bool,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HookFailurePolicy {
+    #[default]
+    Abort,
+    Continue,
+    Rollback,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -128,7 +201,7 @@ pub struct ServiceDefinition {
     pub compatibility_rules: Option<Vec<CompatibilityRule>>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq, Hash, PartialOrd, Ord)]
 #[serde(rename_all = "lowercase")]
 pub enum ServiceCategory {
     Auth,
@@ -143,6 +216,22 @@ pub enum ServiceCategory {
     Storage,
 }
 
+impl ServiceCategory {
+    pub fn all() -> &'static [ServiceCategory] {
+        &[
+            ServiceCategory::Auth,
+            ServiceCategory::Payments,
+            ServiceCategory::Database,
+            ServiceCategory::AI,
+            ServiceCategory::Api,
+            ServiceCategory::Deployment,
+            ServiceCategory::Monitoring,
+            ServiceCategory::Email,
+            ServiceCategory::Storage,
+        ]
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServiceConfig {
     pub name: String,
@@ -169,6 +258,12 @@ pub struct ServiceConfig {
     #[serde(default)]
     #[serde(skip_serializing_if = "Option::is_none")]
     pub compatibility_rules: Option<Vec<CompatibilityRule>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hooks: Option<Hooks>,
+
+    /* Gitignore-style patterns for files in the service directory that are never generated */
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub exclude: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -247,18 +342,20 @@ pub struct CompositionConfig {
     pub conditional_files: Vec<ConditionalFile>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum FileMergingStrategy {
     Append,
+    #[default]
     Merge,
     Override,
     Skip,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum DependencyResolution {
+    #[default]
     Auto,
     Manual,
     Strict,
@@ -301,6 +398,45 @@ pub struct ServiceCombination {
     pub tags: Option<Vec<String>>,
 }
 
+/*
+Renders the template files matching `files` once per element of the list variable
+`over`, with the element bound to `as` (and its position to `<as>_index`) in both
+the content and the output path, e.g. `models/{{ item.name | snake_case }}.rs`.
+*/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EachRule {
+    /* Glob matched against the file's path in the template, without `.tera` */
+    pub files: String,
+    pub over: String,
+    #[serde(rename = "as", default = "default_each_binding")]
+    pub binding: String,
+}
+
+impl EachRule {
+    pub fn matcher(&self) -> EngineResult<globset::GlobMatcher> {
+        globset::GlobBuilder::new(&self.files)
+            .literal_separator(true)
+            .build()
+            .map(|glob| glob.compile_matcher())
+            .map_err(|e| {
+                EngineError::invalid_config(format!(
+                    "Invalid 'each' files pattern '{}': {}",
+                    self.files, e
+                ))
+            })
+    }
+
+    pub fn validate(&self) -> EngineResult<()> {
+        if self.over.is_empty() || self.binding.is_empty() {
+            return Err(EngineError::invalid_config(format!(
+                "'each' rule for '{}' needs a list variable and a name to bind items to",
+                self.files
+            )));
+        }
+        self.matcher().map(|_| ())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServiceSpec {
     pub category: ServiceCategory,
@@ -317,6 +453,7 @@ impl TemplateConfig {
 
         let config: TemplateConfig = serde_yaml::from_str(&content)?;
         config.validate()?;
+        config.check_anvil_compatibility(env!("CARGO_PKG_VERSION"))?;
         Ok(config)
     }
 
@@ -334,17 +471,126 @@ impl TemplateConfig {
         semver::Version::parse(&self.version)
             .map_err(|_| EngineError::invalid_config("Invalid version format"))?;
 
-        semver::Version::parse(&self.min_anvil_version)
+        let min_version = semver::Version::parse(&self.min_anvil_version)
             .map_err(|_| EngineError::invalid_config("Invalid min_anvil_version format"))?;
 
+        if let Some(max_anvil_version) = &self.max_anvil_version {
+            let max_version = semver::Version::parse(max_anvil_version)
+                .map_err(|_| EngineError::invalid_config("Invalid max_anvil_version format"))?;
+
+            if max_version < min_version {
+                return Err(EngineError::invalid_config(
+                    "max_anvil_version cannot be lower than min_anvil_version",
+                ));
+            }
+        }
+
         for variable in &self.variables {
             variable.validate()?;
         }
+        variable_order(&self.variables)?;
 
         for feature in &self.features {
             feature.validate()?;
         }
 
+        validate_patterns(&self.exclude)?;
+
+        for rule in &self.each {
+            rule.validate()?;
+        }
+
+        if let Some(composition) = &self.composition {
+            for conditional_file in &composition.conditional_files {
+                Condition::parse(&conditional_file.condition)?;
+            }
+        }
+
+        if let Some(hooks) = &self.hooks {
+            for hook in hooks.pre_generate.iter().chain(&hooks.post_generate).flatten() {
+                if let Some(condition) = &hook.condition {
+                    Condition::parse(condition)?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /*
+    Features that conditions name bare, such as `auth` for `feature_auth`, as
+    (condition, feature) pairs. They still evaluate, but the CLI warns about them.
+    */
+    pub fn bare_feature_conditions(&self) -> Vec<(&str, &str)> {
+        let hook_conditions = self.hooks.iter().flat_map(|hooks| {
+            hooks
+                .pre_generate
+                .iter()
+                .chain(&hooks.post_generate)
+                .flatten()
+                .filter_map(|hook| hook.condition.as_deref())
+        });
+        let conditions = self
+            .composition
+            .iter()
+            .flat_map(|composition| &composition.conditional_files)
+            .map(|conditional_file| conditional_file.condition.as_str())
+            .chain(hook_conditions)
+            .chain(
+                self.features
+                    .iter()
+                    .filter_map(|feature| feature.enabled_when.as_deref()),
+            );
+
+        let mut bare = Vec::new();
+        for condition in conditions {
+            let Ok(parsed) = Condition::parse(condition) else {
+                continue;
+            };
+            for name in parsed.identifiers() {
+                let Some(feature) = self.features.iter().find(|feature| feature.name == name)
+                else {
+                    continue;
+                };
+                let shadowed = self.variables.iter().any(|variable| variable.name == name);
+                if !shadowed && !bare.contains(&(condition, feature.name.as_str())) {
+                    bare.push((condition, feature.name.as_str()));
+                }
+            }
+        }
+        bare
+    }
+
+    /*
+    Checks that the given Anvil version falls within the template's
+    min_anvil_version..=max_anvil_version range.
+    */
+    pub fn check_anvil_compatibility(&self, anvil_version: &str) -> EngineResult<()> {
+        let current = semver::Version::parse(anvil_version)
+            .map_err(|_| EngineError::invalid_config("Invalid Anvil version format"))?;
+
+        let min_version = semver::Version::parse(&self.min_anvil_version)
+            .map_err(|_| EngineError::invalid_config("Invalid min_anvil_version format"))?;
+        if current < min_version {
+            return Err(EngineError::incompatible_anvil_version(
+                &self.name,
+                format!(">= {}", min_version),
+                anvil_version,
+            ));
+        }
+
+        if let Some(max_anvil_version) = &self.max_anvil_version {
+            let max_version = semver::Version::parse(max_anvil_version)
+                .map_err(|_| EngineError::invalid_config("Invalid max_anvil_version format"))?;
+            if current > max_version {
+                return Err(EngineError::incompatible_anvil_version(
+                    &self.name,
+                    format!("<= {}", max_version),
+                    anvil_version,
+                ));
+            }
+        }
+
         Ok(())
     }
 
@@ -370,10 +616,15 @@ impl TemplateVariable {
             )));
         }
 
-        match &self.var_type {
+        self.validate_type(&self.var_type)
+    }
+
+    fn validate_type(&self, var_type: &VariableType) -> EngineResult<()> {
+        match var_type {
             VariableType::String {
                 min_length,
                 max_length,
+                pattern,
             } => {
                 if let Some(max) = max_length {
                     if *min_length > *max {
@@ -383,12 +634,18 @@ impl TemplateVariable {
                         )));
                     }
                 }
+                if let Some(pattern) = pattern {
+                    compile_pattern(pattern).map_err(|e| {
+                        EngineError::invalid_config(format!("Variable '{}': {}", self.name, e))
+                    })?;
+                }
             }
-            VariableType::Choice { options } => {
+            VariableType::Choice { options } | VariableType::MultiChoice { options } => {
                 if options.is_empty() {
                     return Err(EngineError::invalid_config(format!(
-                        "Variable '{}': choice type must have at least one option",
-                        self.name
+                        "Variable '{}': {} type must have at least one option",
+                        self.name,
+                        var_type.type_name()
                     )));
                 }
             }
@@ -402,18 +659,77 @@ impl TemplateVariable {
                     }
                 }
             }
-            VariableType::Boolean => {}
+            VariableType::Float { min, max } => {
+                if let (Some(min_val), Some(max_val)) = (min, max) {
+                    if min_val > max_val {
+                        return Err(EngineError::invalid_config(format!(
+                            "Variable '{}': min cannot be greater than max",
+                            self.name
+                        )));
+                    }
+                }
+            }
+            VariableType::List {
+                items,
+                min_items,
+                max_items,
+            } => {
+                if let Some(max) = max_items {
+                    if *min_items > *max {
+                        return Err(EngineError::invalid_config(format!(
+                            "Variable '{}': min_items cannot be greater than max_items",
+                            self.name
+                        )));
+                    }
+                }
+                if let Some(items) = items {
+                    if items.is_secret() {
+                        return Err(EngineError::invalid_config(format!(
+                            "Variable '{}': list items cannot be secret",
+                            self.name
+                        )));
+                    }
+                    self.validate_type(items)?;
+                }
+            }
+            VariableType::Boolean | VariableType::Secret { .. } => {}
         }
 
         Ok(())
     }
 
+    /*
+    Converts a supplied value (from --var, where everything is a string, or from an
+    answers file) to the variable's declared type and validates it.
+    */
+    pub fn coerce_value(&self, value: serde_yaml::Value) -> EngineResult<serde_yaml::Value> {
+        let mismatch = |value: &serde_yaml::Value| {
+            EngineError::variable_error(
+                &self.name,
+                format!(
+                    "Expected a {} value, got {}",
+                    self.var_type.type_name(),
+                    describe_value(value)
+                ),
+            )
+        };
+
+        let coerced = coerce_to(&self.var_type, value).map_err(|value| mismatch(&value))?;
+        self.validate_value(&coerced)?;
+        Ok(coerced)
+    }
+
     pub fn validate_value(&self, value: &serde_yaml::Value) -> EngineResult<()> {
-        match (&self.var_type, value) {
+        self.check_value(&self.var_type, value)
+    }
+
+    fn check_value(&self, var_type: &VariableType, value: &serde_yaml::Value) -> EngineResult<()> {
+        match (var_type, value) {
             (
                 VariableType::String {
                     min_length,
                     max_length,
+                    pattern,
                 },
                 serde_yaml::Value::String(s),
             ) => {
@@ -431,6 +747,24 @@ impl TemplateVariable {
                         ));
                     }
                 }
+                if let Some(pattern) = pattern {
+                    let regex = compile_pattern(pattern)
+                        .map_err(|e| EngineError::variable_error(&self.name, e))?;
+                    if !regex.is_match(s) {
+                        return Err(EngineError::variable_error(
+                            &self.name,
+                            format!("'{}' does not match the pattern {}", s, pattern),
+                        ));
+                    }
+                }
+            }
+            (VariableType::Secret { min_length }, serde_yaml::Value::String(s)) => {
+                if s.len() < *min_length {
+                    return Err(EngineError::variable_error(
+                        &self.name,
+                        format!("Secret too short (minimum {} characters)", min_length),
+                    ));
+                }
             }
             (VariableType::Boolean, serde_yaml::Value::Bool(_)) => {}
             (VariableType::Number { min, max }, serde_yaml::Value::Number(n)) => {
@@ -453,6 +787,26 @@ impl TemplateVariable {
                     }
                 }
             }
+            (VariableType::Float { min, max }, serde_yaml::Value::Number(n)) => {
+                if let Some(f) = n.as_f64() {
+                    if let Some(min_val) = min {
+                        if f < *min_val {
+                            return Err(EngineError::variable_error(
+                                &self.name,
+                                format!("Number too small (minimum {})", min_val),
+                            ));
+                        }
+                    }
+                    if let Some(max_val) = max {
+                        if f > *max_val {
+                            return Err(EngineError::variable_error(
+                                &self.name,
+                                format!("Number too large (maximum {})", max_val),
+                            ));
+                        }
+                    }
+                }
+            }
             (VariableType::Choice { options }, serde_yaml::Value::String(s)) => {
                 if !options.contains(s) {
                     return Err(EngineError::variable_error(
@@ -465,10 +819,55 @@ impl TemplateVariable {
                     ));
                 }
             }
+            (VariableType::MultiChoice { options }, serde_yaml::Value::Sequence(values)) => {
+                for value in values {
+                    let valid = value
+                        .as_str()
+                        .is_some_and(|s| options.iter().any(|o| o == s));
+                    if !valid {
+                        return Err(EngineError::variable_error(
+                            &self.name,
+                            format!(
+                                "Invalid choice {}. Valid options: {}",
+                                describe_value(value),
+                                options.join(", ")
+                            ),
+                        ));
+                    }
+                }
+            }
+            (
+                VariableType::List {
+                    items,
+                    min_items,
+                    max_items,
+                },
+                serde_yaml::Value::Sequence(values),
+            ) => {
+                if values.len() < *min_items {
+                    return Err(EngineError::variable_error(
+                        &self.name,
+                        format!("List too short (minimum {} items)", min_items),
+                    ));
+                }
+                if let Some(max) = max_items {
+                    if values.len() > *max {
+                        return Err(EngineError::variable_error(
+                            &self.name,
+                            format!("List too long (maximum {} items)", max),
+                        ));
+                    }
+                }
+                if let Some(items) = items {
+                    for value in values {
+                        self.check_value(items, value)?;
+                    }
+                }
+            }
             _ => {
                 return Err(EngineError::variable_error(
                     &self.name,
-                    format!("Value type mismatch for variable type {:?}", self.var_type),
+                    format!("Value type mismatch for variable type {:?}", var_type),
                 ));
             }
         }
@@ -476,6 +875,91 @@ impl TemplateVariable {
     }
 }
 
+/*
+Converts `value` to `var_type` where there is an obvious reading of it, handing the
+value back when there is not. Lists and multi-choices given as a single string
+are split on commas, which is how they arrive from --var.
+*/
+fn coerce_to(
+    var_type: &VariableType,
+    value: serde_yaml::Value,
+) -> Result<serde_yaml::Value, serde_yaml::Value> {
+    let coerced = match (var_type, value) {
+        (VariableType::Boolean, serde_yaml::Value::String(s)) => {
+            match s.trim().to_lowercase().as_str() {
+                "true" | "yes" | "y" | "1" => serde_yaml::Value::Bool(true),
+                "false" | "no" | "n" | "0" => serde_yaml::Value::Bool(false),
+                _ => return Err(serde_yaml::Value::String(s)),
+            }
+        }
+        (VariableType::Number { .. }, serde_yaml::Value::String(s)) => {
+            match s.trim().parse::<i64>() {
+                Ok(n) => serde_yaml::Value::from(n),
+                Err(_) => return Err(serde_yaml::Value::String(s)),
+            }
+        }
+        (VariableType::Float { .. }, serde_yaml::Value::String(s)) => {
+            match s.trim().parse::<f64>() {
+                Ok(f) if f.is_finite() => serde_yaml::Value::from(f),
+                _ => return Err(serde_yaml::Value::String(s)),
+            }
+        }
+        (VariableType::Float { .. }, serde_yaml::Value::Number(n)) => match n.as_f64() {
+            Some(f) if f.is_finite() => serde_yaml::Value::from(f),
+            _ => return Err(serde_yaml::Value::Number(n)),
+        },
+        (
+            VariableType::String { .. } | VariableType::Choice { .. } | VariableType::Secret { .. },
+            serde_yaml::Value::Number(n),
+        ) => serde_yaml::Value::String(n.to_string()),
+        (
+            VariableType::String { .. } | VariableType::Choice { .. } | VariableType::Secret { .. },
+            serde_yaml::Value::Bool(b),
+        ) => serde_yaml::Value::String(b.to_string()),
+        (
+            VariableType::List { .. } | VariableType::MultiChoice { .. },
+            serde_yaml::Value::String(s),
+        ) => {
+            let values = s
+                .split(',')
+                .map(str::trim)
+                .filter(|item| !item.is_empty())
+                .map(|item| serde_yaml::Value::String(item.to_string()))
+                .collect();
+            coerce_to(var_type, serde_yaml::Value::Sequence(values))?
+        }
+        (
+            VariableType::List {
+                items: Some(items), ..
+            },
+            serde_yaml::Value::Sequence(values),
+        ) => serde_yaml::Value::Sequence(
+            values
+                .into_iter()
+                .map(|value| coerce_to(items, value))
+                .collect::<Result<_, _>>()?,
+        ),
+        (_, value) => value,
+    };
+    Ok(coerced)
+}
+
+/* Anchored so a pattern constrains the whole value rather than any part of it */
+fn compile_pattern(pattern: &str) -> Result<regex::Regex, String> {
+    regex::Regex::new(&format!("^(?:{})$", pattern))
+        .map_err(|e| format!("invalid pattern '{}': {}", pattern, e))
+}
+
+fn describe_value(value: &serde_yaml::Value) -> String {
+    match value {
+        serde_yaml::Value::String(s) => format!("'{}'", s),
+        other => serde_yaml::to_string(other)
+            .unwrap_or_default()
+            .trim()
+            .to_string(),
+    }
+}
+
 impl Feature {
     pub fn validate(&self) -> EngineResult<()> {
         if self.name.is_empty() {
@@ -489,24 +973,32 @@ impl Feature {
             )));
         }
 
+        if let Some(enabled_when) = &self.enabled_when {
+            Condition::parse(enabled_when)?;
+        }
+
         Ok(())
     }
 }
 
-fn default_min_anvil_version() -> String {
-    "0.1.0".to_string()
-}
+impl ServiceConfig {
+    pub async fn from_file(path: &std::path::Path) -> EngineResult<Self> {
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| EngineError::file_error(path, e))?;
 
-impl Default for FileMergingStrategy {
-    fn default() -> Self {
-        FileMergingStrategy::Merge
+        let config: ServiceConfig = serde_yaml::from_str(&content)?;
+        validate_patterns(&config.exclude)?;
+        Ok(config)
     }
 }
 
-impl Default for DependencyResolution {
-    fn default() -> Self {
-        DependencyResolution::Auto
-    }
+fn default_min_anvil_version() -> String {
+    "0.1.0".to_string()
+}
+
+fn default_each_binding() -> String {
+    "item".to_string()
 }
 
 #[cfg(test)]
@@ -552,9 +1044,12 @@ features:
             features: vec![],
             hooks: None,
             min_anvil_version: "0.1.0".to_string(),
+            max_anvil_version: None,
             services: vec![],
             composition: None,
             service_combinations: vec![],
+            exclude: vec![],
+            each: vec![],
         };
 
         assert!(config.validate().is_ok());
@@ -565,6 +1060,236 @@ features:
         config.name = "test".to_string();
         config.version = "invalid-version".to_string();
         assert!(config.validate().is_err());
+
+        config.version = "1.0.0".to_string();
+        config.max_anvil_version = Some("0.0.9".to_string());
+        assert!(config.validate().is_err());
+    }
+
+    #[test]
+    fn test_anvil_version_compatibility() {
+        let mut config: TemplateConfig = serde_yaml::from_str(
+            r#"
+name: "test"
+description: "Test template"
+version: "1.0.0"
+min_anvil_version: "0.2.0"
+max_anvil_version: "0.4.0"
+"#,
+        )
+        .unwrap();
+
+        assert!(config.check_anvil_compatibility("0.2.0").is_ok());
+        assert!(config.check_anvil_compatibility("0.4.0").is_ok());
+        assert!(matches!(
+            config.check_anvil_compatibility("0.1.9"),
+            Err(EngineError::IncompatibleAnvilVersion { ref requirement, .. })
+                if requirement == ">= 0.2.0"
+        ));
+        assert!(matches!(
+            config.check_anvil_compatibility("0.5.0"),
+            Err(EngineError::IncompatibleAnvilVersion { ref requirement, .. })
+                if requirement == "<= 0.4.0"
+        ));
+
+        config.max_anvil_version = None;
+        assert!(config.check_anvil_compatibility("9.0.0").is_ok());
+    }
+
+    #[test]
+    fn test_bare_feature_conditions() {
+        let config: TemplateConfig = serde_yaml::from_str(
+            r#"
+name: "test"
+description: "Test template"
+version: "1.0.0"
+variables:
+  - name: "docker"
+    type: { type: "boolean" }
+    prompt: "Docker?"
+features:
+  - name: "auth"
+    description: "Authentication"
+  - name: "docker"
+    description: "Docker support"
+  - name: "billing"
+    description: "Billing"
+    enabled_when: "auth"
+hooks:
+  post_generate:
+    - command: "docker build ."
+      condition: "docker && feature_auth"
+composition:
+  conditional_files:
+    - path: "middleware.ts"
+      condition: "auth && services.auth != 'none'"
+"#,
+        )
+        .unwrap();
+
+        // `docker` is also a variable, so it is not read as the feature
+        assert_eq!(
+            config.bare_feature_conditions(),
+            vec![
+                ("auth && services.auth != 'none'", "auth"),
+                ("auth", "auth"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_coerce_variable_values() {
+        let variable = |var_type: VariableType| TemplateVariable {
+            name: "test_var".to_string(),
+            var_type,
+            prompt: "Test variable?".to_string(),
+            description: None,
+            default: None,
+            required: false,
+            when: None,
+        };
+
+        let boolean = variable(VariableType::Boolean);
+        assert_eq!(
+            boolean.coerce_value("yes".into()).unwrap(),
+            serde_yaml::Value::Bool(true)
+        );
+        assert!(boolean.coerce_value("maybe".into()).is_err());
+
+        let number = variable(VariableType::Number {
+            min: Some(1),
+            max: Some(10),
+        });
+        assert_eq!(
+            number.coerce_value("7".into()).unwrap(),
+            serde_yaml::Value::from(7)
+        );
+        assert!(number.coerce_value("11".into()).is_err());
+        assert!(number.coerce_value("seven".into()).is_err());
+
+        let choice = variable(VariableType::Choice {
+            options: vec!["npm".to_string(), "pnpm".to_string()],
+        });
+        assert!(choice.coerce_value("pnpm".into()).is_ok());
+        assert!(choice.coerce_value("yarn".into()).is_err());
+
+        let string = variable(VariableType::String {
+            min_length: 0,
+            max_length: None,
+            pattern: None,
+        });
+        assert_eq!(
+            string.coerce_value(serde_yaml::Value::from(1.5)).unwrap(),
+            serde_yaml::Value::from("1.5")
+        );
+    }
+
+    #[test]
+    fn test_extended_variable_types() {
+        let variable = |var_type: VariableType| TemplateVariable {
+            name: "test_var".to_string(),
+            var_type,
+            prompt: "Test variable?".to_string(),
+            description: None,
+            default: None,
+            required: false,
+            when: None,
+        };
+        let strings = |items: &[&str]| {
+            serde_yaml::Value::Sequence(items.iter().map(|&item| item.into()).collect())
+        };
+
+        let slug = variable(VariableType::String {
+            min_length: 0,
+            max_length: None,
+            pattern: Some("[a-z][a-z0-9-]*".to_string()),
+        });
+        assert!(slug.validate().is_ok());
+        assert!(slug.coerce_value("my-app".into()).is_ok());
+        assert!(slug.coerce_value("My App".into()).is_err());
+        assert!(slug.coerce_value("x my-app".into()).is_err());
+        let broken = variable(VariableType::String {
+            min_length: 0,
+            max_length: None,
+            pattern: Some("[a-z".to_string()),
+        });
+        assert!(broken.validate().is_err());
+
+        let ratio = variable(VariableType::Float {
+            min: Some(0.0),
+            max: Some(1.0),
+        });
+        assert_eq!(
+            ratio.coerce_value("0.25".into()).unwrap(),
+            serde_yaml::Value::from(0.25)
+        );
+        assert_eq!(
+            ratio.coerce_value(serde_yaml::Value::from(1)).unwrap(),
+            serde_yaml::Value::from(1.0)
+        );
+        assert!(ratio.coerce_value("1.5".into()).is_err());
+        assert!(ratio.coerce_value("NaN".into()).is_err());
+        let scale = variable(VariableType::Float {
+            min: None,
+            max: None,
+        });
+        assert!(scale.coerce_value(serde_yaml::from_str(".nan").unwrap()).is_err());
+        assert!(scale.coerce_value(serde_yaml::from_str("-.inf").unwrap()).is_err());
+
+        let ports = variable(VariableType::List {
+            items: Some(Box::new(VariableType::Number {
+                min: Some(1),
+                max: Some(65535),
+            })),
+            min_items: 1,
+            max_items: None,
+        });
+        assert_eq!(ports.var_type.type_name(), "list of number");
+        assert_eq!(
+            ports.coerce_value("80, 443".into()).unwrap(),
+            serde_yaml::Value::Sequence(vec![80.into(), 443.into()])
+        );
+        assert!(ports.coerce_value("80, http".into()).is_err());
+        assert!(ports.coerce_value("70000".into()).is_err());
+        assert!(ports.coerce_value("".into()).is_err());
+
+        let entities: serde_yaml::Value =
+            serde_yaml::from_str("[{name: user}, {name: order}]").unwrap();
+        let any_list = variable(VariableType::List {
+            items: None,
+            min_items: 0,
+            max_items: Some(2),
+        });
+        assert!(any_list.coerce_value(entities.clone()).is_ok());
+        assert!(any_list.coerce_value("a, b, c".into()).is_err());
+
+        let features = variable(VariableType::MultiChoice {
+            options: vec!["auth".to_string(), "billing".to_string()],
+        });
+        assert_eq!(
+            features.coerce_value("auth,billing".into()).unwrap(),
+            strings(&["auth", "billing"])
+        );
+        assert!(features.coerce_value(strings(&[])).is_ok());
+        assert!(features.coerce_value("auth, search".into()).is_err());
+        assert!(
+            variable(VariableType::MultiChoice { options: vec![] })
+                .validate()
+                .is_err()
+        );
+
+        let token = variable(VariableType::Secret { min_length: 8 });
+        assert!(token.var_type.is_secret());
+        assert!(token.coerce_value("s3cr3t-token".into()).is_ok());
+        assert!(token.coerce_value("short".into()).is_err());
+
+        let parsed: TemplateVariable = serde_yaml::from_str(
+            "name: tags\ntype: { type: list, items: { type: string, pattern: \"[a-z]+\" } }\nprompt: Tags?\n",
+        )
+        .unwrap();
+        assert!(parsed.validate().is_ok());
+        assert!(parsed.coerce_value("web, api".into()).is_ok());
+        assert!(parsed.coerce_value("web, API".into()).is_err());
     }
 
     #[test]
@@ -574,10 +1299,13 @@ features:
             var_type: VariableType::String {
                 min_length: 1,
                 max_length: Some(10),
+                pattern: None,
             },
             prompt: "Test variable?".to_string(),
+            description: None,
             default: None,
             required: true,
+            when: None,
         };
 
         assert!(variable.validate().is_ok());
@@ -599,14 +1327,3 @@ features:
         );
     }
 }
-
-impl ServiceConfig {
-    pub async fn from_file(path: &std::path::Path) -> EngineResult<Self> {
-        let content = tokio::fs::read_to_string(path)
-            .await
-            .map_err(|e| EngineError::file_error(path, e))?;
-
-        let config: ServiceConfig = serde_yaml::from_str(&content)?;
-        Ok(config)
-    }
-}
@@ -0,0 +1,277 @@
+/*
+Module for comparing a rendered template with a directory on disk, both to preview
+what generating into an existing directory would change and to find where a
+generated project has drifted from its template.
+*/
+
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+use crate::engine::{FileContent, ProcessedTemplate};
+use crate::error::{EngineError, EngineResult};
+
+/* Anvil's metadata and version control are never part of the comparison */
+const IGNORED_DIRECTORIES: [&str; 2] = [".git", ".anvil"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileDiffKind {
+    /* The template produces the file but it is not on disk */
+    Added,
+    /* `patch` is a unified diff from the copy on disk to the template's output */
+    Changed { patch: String },
+    Unchanged,
+    /* On disk but not produced by the template */
+    Extra,
+    /* A directory none of the template's files live in, reported once */
+    ExtraDirectory,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct FileDiff {
+    pub path: PathBuf,
+    pub kind: FileDiffKind,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ProjectDiff {
+    pub files: Vec<FileDiff>,
+}
+
+impl ProjectDiff {
+    pub fn is_clean(&self) -> bool {
+        self.files
+            .iter()
+            .all(|file| file.kind == FileDiffKind::Unchanged)
+    }
+
+    pub fn added(&self) -> impl Iterator<Item = &FileDiff> {
+        self.files
+            .iter()
+            .filter(|file| file.kind == FileDiffKind::Added)
+    }
+
+    pub fn changed(&self) -> impl Iterator<Item = &FileDiff> {
+        self.files
+            .iter()
+            .filter(|file| matches!(file.kind, FileDiffKind::Changed { .. }))
+    }
+
+    pub fn extra(&self) -> impl Iterator<Item = &FileDiff> {
+        self.files.iter().filter(|file| {
+            matches!(
+                file.kind,
+                FileDiffKind::Extra | FileDiffKind::ExtraDirectory
+            )
+        })
+    }
+}
+
+/*
+Compares every file the template renders with the same path under `dir`, then
+lists what else is in `dir`. A missing directory simply shows every file as added.
+*/
+pub async fn diff_directory(template: &ProcessedTemplate, dir: &Path) -> EngineResult<ProjectDiff> {
+    let mut files = Vec::new();
+
+    for file in &template.files {
+        if is_ignored(&file.output_path) {
+            continue;
+        }
+
+        let full_path = dir.join(&file.output_path);
+        let kind = if !full_path.is_file() && !full_path.is_symlink() {
+            FileDiffKind::Added
+        } else {
+            let existing = FileContent::load(&full_path)
+                .await
+                .map_err(|e| EngineError::file_error(&full_path, e))?;
+            if existing == file.content {
+                FileDiffKind::Unchanged
+            } else {
+                FileDiffKind::Changed {
+                    patch: unified_diff(&file.output_path, &existing, &file.content),
+                }
+            }
+        };
+        files.push(FileDiff {
+            path: file.output_path.clone(),
+            kind,
+        });
+    }
+
+    if dir.is_dir() {
+        let rendered: HashSet<&Path> = template
+            .files
+            .iter()
+            .map(|file| file.output_path.as_path())
+            .collect();
+        collect_extra_files(dir, &rendered, &mut files).await?;
+    }
+
+    files.sort_by(|a, b| a.path.cmp(&b.path));
+    Ok(ProjectDiff { files })
+}
+
+fn is_ignored(path: &Path) -> bool {
+    path.components().next().is_some_and(|first| {
+        IGNORED_DIRECTORIES
+            .iter()
+            .any(|dir| first.as_os_str() == *dir)
+    })
+}
+
+fn unified_diff(path: &Path, existing: &FileContent, rendered: &FileContent) -> String {
+    if existing.is_symlink() || rendered.is_symlink() {
+        return format!("Symbolic links a/{0} and b/{0} differ\n", path.display());
+    }
+    let (Some(existing), Some(rendered)) = (existing.as_text(), rendered.as_text()) else {
+        return format!("Binary files a/{0} and b/{0} differ\n", path.display());
+    };
+
+    diffy::DiffOptions::new()
+        .set_original_filename(format!("a/{}", path.display()))
+        .set_modified_filename(format!("b/{}", path.display()))
+        .create_patch(existing, rendered)
+        .to_string()
+}
+
+/*
+Walks `dir` for files the template does not produce. Directories that hold none
+of the template's files are reported as a whole rather than walked, which keeps
+things like node_modules down to a single line.
+*/
+async fn collect_extra_files(
+    dir: &Path,
+    rendered: &HashSet<&Path>,
+    files: &mut Vec<FileDiff>,
+) -> EngineResult<()> {
+    let mut pending = vec![PathBuf::new()];
+
+    while let Some(relative_dir) = pending.pop() {
+        let full_dir = dir.join(&relative_dir);
+        let mut entries = fs::read_dir(&full_dir)
+            .await
+            .map_err(|e| EngineError::file_error(&full_dir, e))?;
+
+        while let Some(entry) = entries
+            .next_entry()
+            .await
+            .map_err(|e| EngineError::file_error(&full_dir, e))?
+        {
+            let path = relative_dir.join(entry.file_name());
+            if is_ignored(&path) {
+                continue;
+            }
+
+            let file_type = entry
+                .file_type()
+                .await
+                .map_err(|e| EngineError::file_error(entry.path(), e))?;
+            if file_type.is_dir() {
+                if rendered.iter().any(|file| file.starts_with(&path)) {
+                    pending.push(path);
+                } else {
+                    files.push(FileDiff {
+                        path,
+                        kind: FileDiffKind::ExtraDirectory,
+                    });
+                }
+            } else if !rendered.contains(path.as_path()) {
+                files.push(FileDiff {
+                    path,
+                    kind: FileDiffKind::Extra,
+                });
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::composition::FileSource;
+    use crate::config::Hooks;
+    use crate::engine::ProcessedFile;
+    use tempfile::TempDir;
+
+    fn file(path: &str, content: &str) -> ProcessedFile {
+        ProcessedFile {
+            output_path: PathBuf::from(path),
+            content: content.into(),
+            mode: None,
+            executable: false,
+            source: FileSource::BaseTemplate,
+            merge_strategy: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_diff_directory() {
+        let project = TempDir::new().unwrap();
+        let root = project.path();
+        fs::create_dir_all(root.join("src")).await.unwrap();
+        fs::create_dir_all(root.join("node_modules/react"))
+            .await
+            .unwrap();
+        fs::create_dir_all(root.join(".git")).await.unwrap();
+        fs::write(root.join("README.md"), "# app\n").await.unwrap();
+        fs::write(root.join("src/main.rs"), "fn main() {}\n")
+            .await
+            .unwrap();
+        fs::write(root.join("src/local.rs"), "// mine\n")
+            .await
+            .unwrap();
+        fs::write(root.join("node_modules/react/index.js"), "")
+            .await
+            .unwrap();
+
+        let template = ProcessedTemplate {
+            files: vec![
+                file("README.md", "# app\n"),
+                file("src/main.rs", "fn main() {\n    run();\n}\n"),
+                file("src/lib.rs", "pub fn run() {}\n"),
+                file(".anvil/answers.yaml", "template: app\n"),
+            ],
+            hooks: Hooks::default(),
+        };
+
+        let diff = diff_directory(&template, root).await.unwrap();
+        let summary: Vec<_> = diff
+            .files
+            .iter()
+            .map(|file| match &file.kind {
+                FileDiffKind::Changed { .. } => (file.path.clone(), "changed"),
+                FileDiffKind::Added => (file.path.clone(), "added"),
+                FileDiffKind::Unchanged => (file.path.clone(), "unchanged"),
+                FileDiffKind::Extra => (file.path.clone(), "extra"),
+                FileDiffKind::ExtraDirectory => (file.path.clone(), "extra directory"),
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (PathBuf::from("README.md"), "unchanged"),
+                (PathBuf::from("node_modules"), "extra directory"),
+                (PathBuf::from("src/lib.rs"), "added"),
+                (PathBuf::from("src/local.rs"), "extra"),
+                (PathBuf::from("src/main.rs"), "changed"),
+            ]
+        );
+        assert!(!diff.is_clean());
+
+        let patch = match &diff.changed().next().unwrap().kind {
+            FileDiffKind::Changed { patch } => patch.clone(),
+            _ => unreachable!(),
+        };
+        assert!(patch.starts_with("--- a/src/main.rs\n+++ b/src/main.rs\n"));
+        assert!(patch.contains("-fn main() {}\n+fn main() {\n+    run();\n+}\n"));
+
+        let missing = diff_directory(&template, &root.join("missing"))
+            .await
+            .unwrap();
+        assert_eq!(missing.added().count(), 3);
+    }
+}
@@ -1,17 +1,22 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::path::{Path, PathBuf};
 use tera::Tera;
 use walkdir::WalkDir;
 use serde_yaml::Value;
 use chrono::{DateTime, Utc};
 
-use crate::config::TemplateConfig;
+use crate::composition::FileSource;
+use crate::config::{EachRule, FileMergingStrategy, HookCommand, Hooks, TemplateConfig};
 use crate::error::{EngineError, EngineResult};
+use crate::exclude::ExcludeRules;
+use crate::variables::{variable_applies, variable_order};
 
 #[derive(Debug, Clone)]
 pub struct Context {
     variables: HashMap<String, Value>,
     features: Vec<String>,
+    /* Every feature the template declares, enabled or not */
+    declared_features: Vec<String>,
 }
 
 impl Context {
@@ -19,6 +24,7 @@ impl Context {
         Self {
             variables: HashMap::new(),
             features: Vec::new(),
+            declared_features: Vec::new(),
         }
     }
 
@@ -34,7 +40,9 @@ impl Context {
         self.variables.get(name)
     }
 
+    /* Enabling a feature also declares it */
     pub fn add_feature(&mut self, feature: String) {
+        self.declare_feature(feature.clone());
         if !self.features.contains(&feature) {
             self.features.push(feature);
         }
@@ -44,6 +52,16 @@ impl Context {
         self.features.contains(&feature.to_string())
     }
 
+    pub fn declare_feature(&mut self, feature: String) {
+        if !self.declared_features.contains(&feature) {
+            self.declared_features.push(feature);
+        }
+    }
+
+    pub fn declares_feature(&self, feature: &str) -> bool {
+        self.declared_features.iter().any(|declared| declared == feature)
+    }
+
     pub fn variables(&self) -> &HashMap<String, Value> {
         &self.variables
     }
@@ -61,7 +79,7 @@ impl Context {
         
         tera_context.insert("features", &self.features);
         for feature in &self.features {
-            tera_context.insert(&format!("feature_{}", feature), &true);
+            tera_context.insert(format!("feature_{}", feature), &true);
         }
         
         tera_context
@@ -74,7 +92,7 @@ impl Default for Context {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Default)]
 pub struct ContextBuilder {
     context: Context,
 }
@@ -101,24 +119,143 @@ impl ContextBuilder {
     }
 }
 
+/* How many leading bytes are checked for NUL when deciding a file is binary */
+const BINARY_SNIFF_LEN: usize = 8000;
+
+/*
+The contents of a template file. Binary files such as images and fonts are carried
+as raw bytes so they are never rendered and are written out byte-for-byte, and
+symbolic links keep their target so they are recreated rather than followed.
+*/
+#[derive(Debug, Clone)]
+pub enum FileContent {
+    Text(String),
+    Binary(Vec<u8>),
+    Symlink(PathBuf),
+}
+
+impl FileContent {
+    /* Treats content as binary if it has a NUL byte near the start or is not UTF-8 */
+    pub fn from_bytes(bytes: Vec<u8>) -> Self {
+        if bytes.iter().take(BINARY_SNIFF_LEN).any(|&b| b == 0) {
+            return Self::Binary(bytes);
+        }
+        match String::from_utf8(bytes) {
+            Ok(text) => Self::Text(text),
+            Err(e) => Self::Binary(e.into_bytes()),
+        }
+    }
+
+    /* Reads a file, or the target of a symbolic link without following it */
+    pub fn read(path: &Path) -> EngineResult<Self> {
+        let metadata =
+            std::fs::symlink_metadata(path).map_err(|e| EngineError::file_error(path, e))?;
+        if metadata.is_symlink() {
+            return std::fs::read_link(path)
+                .map(Self::Symlink)
+                .map_err(|e| EngineError::file_error(path, e));
+        }
+        std::fs::read(path)
+            .map(Self::from_bytes)
+            .map_err(|e| EngineError::file_error(path, e))
+    }
+
+    /* Like `read`, for what is already on disk where a generated file will go */
+    pub async fn load(path: &Path) -> std::io::Result<Self> {
+        if tokio::fs::symlink_metadata(path).await?.is_symlink() {
+            return tokio::fs::read_link(path).await.map(Self::Symlink);
+        }
+        tokio::fs::read(path).await.map(Self::from_bytes)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        match self {
+            Self::Text(text) => text.as_bytes(),
+            Self::Binary(bytes) => bytes,
+            Self::Symlink(target) => target.as_os_str().as_encoded_bytes(),
+        }
+    }
+
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            Self::Text(text) => Some(text),
+            Self::Binary(_) | Self::Symlink(_) => None,
+        }
+    }
+
+    pub fn is_binary(&self) -> bool {
+        matches!(self, Self::Binary(_))
+    }
+
+    pub fn is_symlink(&self) -> bool {
+        matches!(self, Self::Symlink(_))
+    }
+
+    pub fn len(&self) -> usize {
+        self.as_bytes().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/* Text and binary content compare by bytes; a link only ever equals the same link */
+impl PartialEq for FileContent {
+    fn eq(&self, other: &Self) -> bool {
+        self.is_symlink() == other.is_symlink() && self.as_bytes() == other.as_bytes()
+    }
+}
+
+impl From<String> for FileContent {
+    fn from(text: String) -> Self {
+        Self::Text(text)
+    }
+}
+
+impl From<&str> for FileContent {
+    fn from(text: &str) -> Self {
+        Self::Text(text.to_string())
+    }
+}
+
+/* The permission bits of a template file, so generated files keep them */
+#[cfg(unix)]
+pub(crate) fn file_mode(metadata: &std::fs::Metadata) -> Option<u32> {
+    use std::os::unix::fs::PermissionsExt;
+    Some(metadata.permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+pub(crate) fn file_mode(_metadata: &std::fs::Metadata) -> Option<u32> {
+    None
+}
+
 #[derive(Debug, Clone)]
 pub struct TemplateFile {
     pub source_path: PathBuf,
     pub relative_path: PathBuf,
     pub output_path: PathBuf,
-    pub content: String,
+    pub content: FileContent,
+    pub mode: Option<u32>,
 }
 
 #[derive(Debug)]
 pub struct ProcessedTemplate {
     pub files: Vec<ProcessedFile>,
+    pub hooks: Hooks,
 }
 
 #[derive(Debug)]
 pub struct ProcessedFile {
     pub output_path: PathBuf,
-    pub content: String,
+    pub content: FileContent,
+    /* Unix permission bits carried over from the template, when known */
+    pub mode: Option<u32>,
     pub executable: bool,
+    pub source: FileSource,
+    /* How the file was combined when several sources provided it */
+    pub merge_strategy: Option<FileMergingStrategy>,
 }
 
 pub struct TemplateEngine {
@@ -151,13 +288,21 @@ impl TemplateEngine {
     pub fn discover_template_files(
         &self,
         template_dir: &Path,
+        exclude: &[String],
     ) -> EngineResult<Vec<TemplateFile>> {
         let mut files = Vec::new();
+        let rules = ExcludeRules::new(template_dir, exclude)?;
         
         for entry in WalkDir::new(template_dir)
             .into_iter()
+            .filter_entry(|e| {
+                e.depth() == 0
+                    || e.path().strip_prefix(template_dir).map_or(true, |relative| {
+                        !rules.is_excluded(relative, e.file_type().is_dir())
+                    })
+            })
             .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file())
+            .filter(|e| !e.file_type().is_dir())
         {
             let source_path = entry.path().to_path_buf();
             
@@ -180,14 +325,19 @@ impl TemplateEngine {
                 relative_path.clone()
             };
             
-            let content = std::fs::read_to_string(&source_path)
-                .map_err(|e| EngineError::file_error(&source_path, e))?;
+            let content = FileContent::read(&source_path)?;
+            let mode = entry
+                .metadata()
+                .ok()
+                .filter(|metadata| metadata.is_file())
+                .and_then(|metadata| file_mode(&metadata));
             
             files.push(TemplateFile {
                 source_path,
                 relative_path,
                 output_path,
                 content,
+                mode,
             });
         }
         
@@ -197,32 +347,44 @@ impl TemplateEngine {
     pub async fn process_template(
         &mut self,
         template_dir: &Path,
+        config: &TemplateConfig,
         context: &Context,
     ) -> EngineResult<ProcessedTemplate> {
-        let template_files = self.discover_template_files(template_dir)?;
+        let template_files = self.discover_template_files(template_dir, &config.exclude)?;
         let tera_context = context.to_tera_context();
+        let each_rules = Self::compile_each_rules(&config.each)?;
         
         let mut processed_files = Vec::new();
+        let mut output_paths = HashSet::new();
         
         for template_file in template_files {
-            let processed_content = if template_file.source_path.extension().and_then(|e| e.to_str()) == Some("tera") {
-                self.tera.render_str(&template_file.content, &tera_context)
-                    .map_err(EngineError::ProcessingError)?
-            } else {
-                template_file.content
-            };
+            let is_template = template_file.source_path.extension().and_then(|e| e.to_str()) == Some("tera");
+            let rendered = self.render_file(
+                &template_file.output_path,
+                template_file.content,
+                is_template,
+                &each_rules,
+                &tera_context,
+            )?;
             
-            let executable = self.should_be_executable(&template_file.output_path);
-            
-            processed_files.push(ProcessedFile {
-                output_path: template_file.output_path,
-                content: processed_content,
-                executable,
-            });
+            for (output_path, processed_content) in rendered {
+                Self::claim_output_path(&mut output_paths, &template_file.relative_path, &output_path)?;
+                let executable = self.is_executable(&output_path, &processed_content, template_file.mode);
+                
+                processed_files.push(ProcessedFile {
+                    output_path,
+                    content: processed_content,
+                    mode: template_file.mode,
+                    executable,
+                    source: FileSource::BaseTemplate,
+                    merge_strategy: None,
+                });
+            }
         }
         
         Ok(ProcessedTemplate {
             files: processed_files,
+            hooks: Hooks::default(),
         })
     }
 
@@ -238,27 +400,249 @@ impl TemplateEngine {
         // Build comprehensive shared context
         let tera_context = self.build_shared_context(context, &composed)?;
         
+        let hooks = self.render_hooks_with_context(&composed.hooks, &tera_context)?;
+        let each_rules = Self::compile_each_rules(&composed.base_config.each)?;
+        
         let mut processed_files = Vec::new();
+        let mut output_paths = HashSet::new();
         
         for composed_file in composed.files {
-            let processed_content = if composed_file.is_template {
-                self.tera.render_str(&composed_file.content, &tera_context)
-                    .map_err(EngineError::ProcessingError)?
-            } else {
-                composed_file.content
-            };
-            
-            let executable = self.should_be_executable(&composed_file.path);
+            let rendered = self.render_file(
+                &composed_file.path,
+                composed_file.content,
+                composed_file.is_template,
+                &each_rules,
+                &tera_context,
+            )?;
+            let merge_strategy = matches!(composed_file.source, FileSource::Merged { .. })
+                .then_some(composed_file.merge_strategy);
             
-            processed_files.push(ProcessedFile {
-                output_path: composed_file.path,
-                content: processed_content,
-                executable,
-            });
+            for (output_path, processed_content) in rendered {
+                Self::claim_output_path(&mut output_paths, &composed_file.path, &output_path)?;
+                let executable = self.is_executable(&output_path, &processed_content, composed_file.mode);
+                
+                processed_files.push(ProcessedFile {
+                    output_path,
+                    content: processed_content,
+                    mode: composed_file.mode,
+                    executable,
+                    source: composed_file.source.clone(),
+                    merge_strategy: merge_strategy.clone(),
+                });
+            }
         }
         
         Ok(ProcessedTemplate {
             files: processed_files,
+            hooks,
+        })
+    }
+
+    /*
+    Records that `source` renders to `output_path`, failing if another template file
+    already renders there, since one of the two would silently overwrite the other.
+    */
+    fn claim_output_path(
+        output_paths: &mut HashSet<PathBuf>,
+        source: &Path,
+        output_path: &Path,
+    ) -> EngineResult<()> {
+        if !output_paths.insert(output_path.to_path_buf()) {
+            return Err(EngineError::invalid_config(format!(
+                "{} renders to {}, which another template file already renders to",
+                source.display(),
+                output_path.display()
+            )));
+        }
+        Ok(())
+    }
+
+    fn compile_each_rules(rules: &[EachRule]) -> EngineResult<Vec<(globset::GlobMatcher, &EachRule)>> {
+        rules
+            .iter()
+            .map(|rule| Ok((rule.matcher()?, rule)))
+            .collect()
+    }
+
+    /*
+    Renders a file's path and, for `.tera` files, its content. A file matched by an
+    `each:` rule is rendered once per element of the rule's list instead, and every
+    copy must end up at a different path.
+    */
+    fn render_file(
+        &mut self,
+        path: &Path,
+        content: FileContent,
+        is_template: bool,
+        each_rules: &[(globset::GlobMatcher, &EachRule)],
+        tera_context: &tera::Context,
+    ) -> EngineResult<Vec<(PathBuf, FileContent)>> {
+        let Some((_, rule)) = each_rules.iter().find(|(matcher, _)| matcher.is_match(path)) else {
+            return Ok(self
+                .render_copy(path, content, is_template, tera_context)?
+                .into_iter()
+                .collect());
+        };
+        
+        let items = match tera_context.get(&rule.over) {
+            Some(tera::Value::Array(items)) => items.clone(),
+            None | Some(tera::Value::Null) => Vec::new(),
+            Some(_) => {
+                return Err(EngineError::invalid_config(format!(
+                    "'each' rule for {} needs '{}' to be a list",
+                    path.display(),
+                    rule.over
+                )))
+            }
+        };
+        
+        let mut rendered: Vec<(PathBuf, FileContent)> = Vec::new();
+        for (index, item) in items.iter().enumerate() {
+            let mut item_context = tera_context.clone();
+            item_context.insert(&rule.binding, item);
+            item_context.insert(format!("{}_index", rule.binding), &index);
+            
+            let Some(copy) = self.render_copy(path, content.clone(), is_template, &item_context)? else {
+                continue;
+            };
+            if rendered.iter().any(|(existing, _)| *existing == copy.0) {
+                return Err(EngineError::invalid_config(format!(
+                    "'each' rule for {} renders more than one item of '{}' to {}",
+                    path.display(),
+                    rule.over,
+                    copy.0.display()
+                )));
+            }
+            rendered.push(copy);
+        }
+        
+        Ok(rendered)
+    }
+
+    fn render_copy(
+        &mut self,
+        path: &Path,
+        content: FileContent,
+        is_template: bool,
+        tera_context: &tera::Context,
+    ) -> EngineResult<Option<(PathBuf, FileContent)>> {
+        let Some(output_path) = self.render_output_path(path, tera_context)? else {
+            return Ok(None);
+        };
+        let content = match content {
+            FileContent::Text(text) if is_template => self.tera.render_str(&text, tera_context)
+                .map_err(EngineError::ProcessingError)?
+                .into(),
+            FileContent::Binary(_) if is_template => {
+                return Err(EngineError::invalid_config(format!(
+                    "Template for '{}' is not valid UTF-8, so it cannot be rendered",
+                    path.display()
+                )));
+            }
+            content => content,
+        };
+        
+        Ok(Some((output_path, content)))
+    }
+
+    /*
+    Renders Tera expressions in an output path one segment at a time, so files and
+    directories can be named after the user's answers, e.g.
+    `cmd/{{ project_name }}/main.go`. A segment that renders to nothing leaves the
+    file out, which is how a whole directory is made optional.
+    */
+    fn render_output_path(
+        &mut self,
+        path: &Path,
+        tera_context: &tera::Context,
+    ) -> EngineResult<Option<PathBuf>> {
+        let mut rendered = PathBuf::new();
+        for component in path.components() {
+            let segment = match component.as_os_str().to_str() {
+                Some(segment) if segment.contains("{{") || segment.contains("{%") => segment,
+                _ => {
+                    rendered.push(component);
+                    continue;
+                }
+            };
+            
+            let value = self.tera.render_str(segment, tera_context)
+                .map_err(EngineError::ProcessingError)?;
+            let value = value.trim();
+            if value.is_empty() {
+                return Ok(None);
+            }
+            if value.contains(['/', '\\']) || value == "." || value == ".." {
+                return Err(EngineError::invalid_config(format!(
+                    "Path segment '{}' in {} rendered to '{}', which is not a single file or directory name",
+                    segment,
+                    path.display(),
+                    value
+                )));
+            }
+            rendered.push(value);
+        }
+        
+        Ok(Some(rendered))
+    }
+
+    /*
+    Renders hook commands, working directories and env values through Tera so a hook
+    can follow the user's answers, e.g. "{{ package_manager }} install".
+    */
+    pub fn render_hooks(&mut self, hooks: &Hooks, context: &Context) -> EngineResult<Hooks> {
+        let tera_context = context.to_tera_context();
+        self.render_hooks_with_context(hooks, &tera_context)
+    }
+
+    fn render_hooks_with_context(
+        &mut self,
+        hooks: &Hooks,
+        tera_context: &tera::Context,
+    ) -> EngineResult<Hooks> {
+        Ok(Hooks {
+            pre_generate: self.render_hook_stage(&hooks.pre_generate, tera_context)?,
+            post_generate: self.render_hook_stage(&hooks.post_generate, tera_context)?,
+        })
+    }
+
+    fn render_hook_stage(
+        &mut self,
+        stage: &Option<Vec<HookCommand>>,
+        tera_context: &tera::Context,
+    ) -> EngineResult<Option<Vec<HookCommand>>> {
+        match stage {
+            Some(commands) => commands
+                .iter()
+                .map(|hook| self.render_hook_command(hook, tera_context))
+                .collect::<EngineResult<Vec<_>>>()
+                .map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn render_hook_command(
+        &mut self,
+        hook: &HookCommand,
+        tera_context: &tera::Context,
+    ) -> EngineResult<HookCommand> {
+        let command = self.tera.render_str(&hook.command, tera_context)?;
+        
+        let working_dir = match &hook.working_dir {
+            Some(dir) => Some(self.tera.render_str(dir, tera_context)?),
+            None => None,
+        };
+        
+        let mut env = HashMap::new();
+        for (key, value) in &hook.env {
+            env.insert(key.clone(), self.tera.render_str(value, tera_context)?);
+        }
+        
+        Ok(HookCommand {
+            command,
+            working_dir,
+            env,
+            ..hook.clone()
         })
     }
 
@@ -273,14 +657,16 @@ impl TemplateEngine {
         context: &Context,
         config: &TemplateConfig,
     ) -> EngineResult<()> {
-        for variable in &config.variables {
-            if variable.required {
-                if !context.variables.contains_key(&variable.name) {
-                    return Err(EngineError::variable_error(
-                        &variable.name,
-                        "Required variable not provided",
-                    ));
-                }
+        for variable in variable_order(&config.variables)? {
+            if !variable_applies(variable, &config.variables, context)? {
+                continue;
+            }
+
+            if variable.required && !context.variables.contains_key(&variable.name) {
+                return Err(EngineError::variable_error(
+                    &variable.name,
+                    "Required variable not provided",
+                ));
             }
             
             if let Some(value) = context.get_variable(&variable.name) {
@@ -291,6 +677,18 @@ impl TemplateEngine {
         Ok(())
     }
 
+    /*
+    A file is executable when its template source is, going by the source's Unix
+    mode. The name-based guess is only used where no mode is known.
+    */
+    fn is_executable(&self, path: &Path, content: &FileContent, mode: Option<u32>) -> bool {
+        match mode {
+            _ if content.is_symlink() => false,
+            Some(mode) => mode & 0o111 != 0,
+            None => self.should_be_executable(path),
+        }
+    }
+
     fn should_be_executable(&self, path: &Path) -> bool {
         if let Some(extension) = path.extension().and_then(|e| e.to_str()) {
             matches!(extension, "sh" | "py" | "rb" | "pl")
@@ -317,8 +715,7 @@ impl TemplateEngine {
                 }
             })
             .collect::<String>()
-            .replace(' ', "_")
-            .replace('-', "_");
+            .replace([' ', '-'], "_");
         Ok(tera::Value::String(snake_case))
     }
 
@@ -371,8 +768,7 @@ impl TemplateEngine {
                 }
             })
             .collect::<String>()
-            .replace(' ', "-")
-            .replace('_', "-");
+            .replace([' ', '_'], "-");
         Ok(tera::Value::String(kebab_case))
     }
 
@@ -408,9 +804,9 @@ impl TemplateEngine {
         tera_context.insert("environment_variables", &composed.environment_variables);
         
         for (service_name, service_info) in &composed.service_context.services {
-            tera_context.insert(&format!("service_{}", service_name.to_lowercase()), &service_info.provider);
+            tera_context.insert(format!("service_{}", service_name.to_lowercase()), &service_info.provider);
             for (export_key, export_value) in &service_info.exports {
-                tera_context.insert(&format!("{}_{}", service_name.to_lowercase(), export_key), export_value);
+                tera_context.insert(format!("{}_{}", service_name.to_lowercase(), export_key), export_value);
             }
         }
         
@@ -449,10 +845,9 @@ impl TemplateEngine {
                 }
             })
             .collect::<String>()
-            .replace(' ', "_")
-            .replace('-', "_");
+            .replace([' ', '-'], "_");
         
-        let module_name = if module_name.chars().next().map_or(false, |c| c.is_numeric()) {
+        let module_name = if module_name.chars().next().is_some_and(|c| c.is_numeric()) {
             format!("_{}", module_name)
         } else {
             module_name
@@ -471,9 +866,14 @@ impl Default for TemplateEngine {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::config::HookFailurePolicy;
     use tempfile::TempDir;
     use std::fs;
 
+    fn template_config(extra: &str) -> TemplateConfig {
+        serde_yaml::from_str(&format!("name: test\ndescription: Test\nversion: 1.0.0\n{}", extra)).unwrap()
+    }
+
     #[test]
     fn test_context_builder() {
         let context = Context::builder()
@@ -526,6 +926,38 @@ mod tests {
         assert_eq!(result, "my_awesome_project");
     }
 
+    #[test]
+    fn test_render_hooks() {
+        let mut engine = TemplateEngine::new_for_testing().unwrap();
+        let context = Context::builder()
+            .variable("package_manager", "pnpm")
+            .variable("project_name", "MyApp")
+            .build();
+        
+        let mut env = HashMap::new();
+        env.insert("APP_NAME".to_string(), "{{ project_name | kebab_case }}".to_string());
+        let hooks = Hooks {
+            pre_generate: None,
+            post_generate: Some(vec![HookCommand {
+                command: "{{ package_manager }} install".to_string(),
+                working_dir: Some("{{ project_name | snake_case }}".to_string()),
+                condition: Some("always".to_string()),
+                env,
+                timeout: None,
+                on_failure: HookFailurePolicy::default(),
+                capture_output: false,
+            }]),
+        };
+        
+        let rendered = engine.render_hooks(&hooks, &context).unwrap();
+        let hook = &rendered.post_generate.unwrap()[0];
+        assert_eq!(hook.command, "pnpm install");
+        assert_eq!(hook.working_dir.as_deref(), Some("my_app"));
+        assert_eq!(hook.env.get("APP_NAME").unwrap(), "my-app");
+        assert_eq!(hook.condition.as_deref(), Some("always"));
+        assert!(rendered.pre_generate.is_none());
+    }
+
     #[tokio::test]
     async fn test_template_file_discovery() {
         let temp_dir = TempDir::new().unwrap();
@@ -534,9 +966,14 @@ mod tests {
         
         fs::write(template_dir.join("file.txt.tera"), "Hello {{ name }}").unwrap();
         fs::write(template_dir.join("static.md"), "# README").unwrap();
+        fs::write(template_dir.join(".DS_Store"), "").unwrap();
+        fs::create_dir_all(template_dir.join("node_modules/react")).unwrap();
+        fs::write(template_dir.join("node_modules/react/index.js"), "").unwrap();
+        fs::write(template_dir.join(".anvilignore"), ".DS_Store\n").unwrap();
         
         let engine = TemplateEngine::new_for_testing().unwrap();
-        let files = engine.discover_template_files(&template_dir).unwrap();
+        let files = engine.discover_template_files(&template_dir, &["node_modules/".to_string()]).unwrap();
+        assert_eq!(files.len(), 2);
         
         
         let template_file = files.iter().find(|f| f.relative_path.to_str().unwrap() == "file.txt.tera").unwrap();
@@ -561,15 +998,210 @@ mod tests {
         ).unwrap();
         
         let mut engine = TemplateEngine::new_for_testing().unwrap();
+        let config = template_config("");
         let context = Context::builder()
             .variable("project_name", "my-project")
             .build();
         
-        let result = engine.process_template(&template_dir, &context).await.unwrap();
+        let result = engine.process_template(&template_dir, &config, &context).await.unwrap();
         
         assert_eq!(result.files.len(), 1);
         let file = &result.files[0];
         assert_eq!(file.output_path, PathBuf::from("main.rs"));
-        assert!(file.content.contains("Hello from MyProject!"));
+        assert!(file.content.as_text().unwrap().contains("Hello from MyProject!"));
+    }
+
+    #[tokio::test]
+    async fn test_binary_files_are_copied_verbatim() {
+        let temp_dir = TempDir::new().unwrap();
+        let template_dir = temp_dir.path().join("template");
+        std::fs::create_dir_all(&template_dir).unwrap();
+        
+        let png = [0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0x1a, 0x0a, 0x00, 0x00, b'{', b'{'];
+        let latin1 = b"caf\xe9 {{ name }}".to_vec();
+        std::fs::write(template_dir.join("favicon.png"), png).unwrap();
+        std::fs::write(template_dir.join("notes.txt.tera"), &latin1).unwrap();
+        
+        let mut engine = TemplateEngine::new_for_testing().unwrap();
+        let config = template_config("");
+        let error = engine.process_template(&template_dir, &config, &Context::new()).await.unwrap_err();
+        assert!(error.to_string().contains("notes.txt"));
+        
+        std::fs::remove_file(template_dir.join("notes.txt.tera")).unwrap();
+        let result = engine.process_template(&template_dir, &config, &Context::new()).await.unwrap();
+        let favicon = result.files.iter().find(|f| f.output_path == Path::new("favicon.png")).unwrap();
+        assert_eq!(favicon.content, FileContent::Binary(png.to_vec()));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_source_modes_and_symlinks() {
+        use crate::generator::{ConflictMode, FileGenerator, FileStatus};
+        use std::os::unix::fs::PermissionsExt;
+        
+        let temp_dir = TempDir::new().unwrap();
+        let template_dir = temp_dir.path().join("template");
+        let output_dir = temp_dir.path().join("output");
+        std::fs::create_dir_all(template_dir.join("bin")).unwrap();
+        
+        let write = |path: &str, mode: u32| {
+            let path = template_dir.join(path);
+            std::fs::write(&path, "#!/bin/sh\n").unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(mode)).unwrap();
+        };
+        write("bin/run", 0o755);
+        write("tool.py", 0o644);
+        write("secrets.env", 0o600);
+        std::os::unix::fs::symlink("bin/run", template_dir.join("run")).unwrap();
+        
+        let mut engine = TemplateEngine::new_for_testing().unwrap();
+        let config = template_config("");
+        let context = Context::new();
+        let template = engine.process_template(&template_dir, &config, &context).await.unwrap();
+        let run = template.files.iter().find(|f| f.output_path == Path::new("bin/run")).unwrap();
+        assert!(run.executable);
+        let tool = template.files.iter().find(|f| f.output_path == Path::new("tool.py")).unwrap();
+        assert!(!tool.executable);
+        
+        let generator = FileGenerator::new(&output_dir);
+        generator.generate_files(template, None).await.unwrap();
+        
+        let mode = |path: &str| {
+            std::fs::metadata(output_dir.join(path)).unwrap().permissions().mode() & 0o777
+        };
+        assert_eq!(mode("bin/run"), 0o755);
+        assert_eq!(mode("tool.py"), 0o644);
+        assert_eq!(mode("secrets.env"), 0o600);
+        assert_eq!(std::fs::read_link(output_dir.join("run")).unwrap(), Path::new("bin/run"));
+        
+        // An identical link already in place is not a conflict
+        let template = engine.process_template(&template_dir, &config, &context).await.unwrap();
+        let result = FileGenerator::new(&output_dir)
+            .with_conflict_mode(ConflictMode::Skip)
+            .generate_files(template, None)
+            .await
+            .unwrap();
+        let link = result.files.iter().find(|f| f.path == Path::new("run")).unwrap();
+        assert_eq!(link.status, FileStatus::Unchanged);
+    }
+
+    #[tokio::test]
+    async fn test_templated_output_paths() {
+        let temp_dir = TempDir::new().unwrap();
+        let template_dir = temp_dir.path().join("template");
+        std::fs::create_dir_all(template_dir.join("cmd/{{ project_name }}")).unwrap();
+        std::fs::create_dir_all(template_dir.join("src/{{ project_name | rust_module_name }}")).unwrap();
+        std::fs::create_dir_all(template_dir.join("{% if with_docs %}docs{% endif %}")).unwrap();
+        
+        std::fs::write(template_dir.join("cmd/{{ project_name }}/main.go.tera"), "package main").unwrap();
+        std::fs::write(template_dir.join("src/{{ project_name | rust_module_name }}/mod.rs"), "").unwrap();
+        std::fs::write(template_dir.join("{% if with_docs %}docs{% endif %}/guide.md"), "").unwrap();
+        std::fs::write(template_dir.join("{{ license_file }}"), "MIT").unwrap();
+        
+        let mut engine = TemplateEngine::new_for_testing().unwrap();
+        let config = template_config("");
+        let context = Context::builder()
+            .variable("project_name", "my-app")
+            .variable("with_docs", false)
+            .variable("license_file", "")
+            .build();
+        
+        let result = engine.process_template(&template_dir, &config, &context).await.unwrap();
+        let mut paths: Vec<_> = result.files.iter().map(|f| f.output_path.clone()).collect();
+        paths.sort();
+        assert_eq!(
+            paths,
+            vec![PathBuf::from("cmd/my-app/main.go"), PathBuf::from("src/my_app/mod.rs")]
+        );
+        
+        let context = Context::builder()
+            .variable("project_name", "../escape")
+            .variable("with_docs", true)
+            .variable("license_file", "LICENSE")
+            .build();
+        assert!(engine.process_template(&template_dir, &config, &context).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_each_rule_fans_out_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let template_dir = temp_dir.path().join("template");
+        std::fs::create_dir_all(template_dir.join("models")).unwrap();
+        
+        std::fs::write(
+            template_dir.join("models/{{ item.name | snake_case }}.rs.tera"),
+            "// {{ item_index }}\npub struct {{ item.name }};\n",
+        ).unwrap();
+        std::fs::write(template_dir.join("models.rs.tera"), "{% for e in entities %}{{ e.name }} {% endfor %}").unwrap();
+        
+        let config = template_config("each:\n  - files: \"models/*.rs\"\n    over: entities\n");
+        let entities: Value = serde_yaml::from_str("[{name: User}, {name: BlogPost}]").unwrap();
+        let mut engine = TemplateEngine::new_for_testing().unwrap();
+        let context = Context::builder().variable("entities", entities).build();
+        let result = engine.process_template(&template_dir, &config, &context).await.unwrap();
+        
+        let mut files: Vec<_> = result
+            .files
+            .iter()
+            .map(|f| (f.output_path.clone(), f.content.as_text().unwrap().to_string()))
+            .collect();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                (PathBuf::from("models/blog_post.rs"), "// 1\npub struct BlogPost;\n".to_string()),
+                (PathBuf::from("models/user.rs"), "// 0\npub struct User;\n".to_string()),
+                (PathBuf::from("models.rs"), "User BlogPost ".to_string()),
+            ]
+        );
+        
+        let context = Context::builder().variable("entities", "User").build();
+        assert!(engine.process_template(&template_dir, &config, &context).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_files_cannot_share_a_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let template_dir = temp_dir.path().join("template");
+        std::fs::create_dir_all(template_dir.join("{{ a }}")).unwrap();
+        std::fs::create_dir_all(template_dir.join("{{ b }}")).unwrap();
+        std::fs::write(template_dir.join("{{ a }}/x"), "a").unwrap();
+        std::fs::write(template_dir.join("{{ b }}/x"), "b").unwrap();
+        
+        let config = template_config("");
+        let mut engine = TemplateEngine::new_for_testing().unwrap();
+        
+        let context = Context::builder().variable("a", "one").variable("b", "two").build();
+        let result = engine.process_template(&template_dir, &config, &context).await.unwrap();
+        assert_eq!(result.files.len(), 2);
+        
+        let context = Context::builder().variable("a", "same").variable("b", "same").build();
+        let error = engine.process_template(&template_dir, &config, &context).await.unwrap_err();
+        assert!(error.to_string().contains("same/x"));
+    }
+
+    #[tokio::test]
+    async fn test_composed_files_cannot_share_a_path() {
+        let temp_dir = TempDir::new().unwrap();
+        let template_dir = temp_dir.path().join("templates/app");
+        std::fs::create_dir_all(&template_dir).unwrap();
+        std::fs::write(template_dir.join("anvil.yaml"), "name: app\ndescription: App\nversion: 1.0.0\n").unwrap();
+        std::fs::write(template_dir.join("{{ config_file }}"), "a").unwrap();
+        std::fs::write(template_dir.join("settings.toml"), "b").unwrap();
+        
+        let composition = crate::composition::CompositionEngine::new(
+            temp_dir.path().join("templates"),
+            temp_dir.path().join("templates/shared"),
+        );
+        let mut engine = TemplateEngine::new_for_testing().unwrap();
+        
+        let context = Context::builder().variable("config_file", "config.toml").build();
+        let composed = composition.compose_template("app", Vec::new()).await.unwrap();
+        assert_eq!(engine.process_composed_template(composed, &context).await.unwrap().files.len(), 2);
+        
+        let context = Context::builder().variable("config_file", "settings.toml").build();
+        let composed = composition.compose_template("app", Vec::new()).await.unwrap();
+        let error = engine.process_composed_template(composed, &context).await.unwrap_err();
+        assert!(error.to_string().contains("settings.toml"));
     }
 }
\ No newline at end of file
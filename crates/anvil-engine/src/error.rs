@@ -30,6 +30,22 @@ pub enum EngineError {
 
     #[error("Template composition failed: {reason}")]
     CompositionError { reason: String },
+
+    #[error("Hook '{command}' failed: {reason}")]
+    HookError { command: String, reason: String },
+
+    #[error("Invalid condition '{condition}': {reason}")]
+    ConditionError { condition: String, reason: String },
+
+    #[error("Generation cancelled: {reason}")]
+    Cancelled { reason: String },
+
+    #[error("Template '{template}' requires Anvil {requirement}, but this is Anvil {current}")]
+    IncompatibleAnvilVersion {
+        template: String,
+        requirement: String,
+        current: String,
+    },
 }
 
 pub type EngineResult<T> = Result<T, EngineError>;
@@ -74,4 +90,36 @@ impl EngineError {
             reason: reason.into(),
         }
     }
+
+    pub fn hook_error(command: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self::HookError {
+            command: command.into(),
+            reason: reason.into(),
+        }
+    }
+
+    pub fn condition_error(condition: impl Into<String>, reason: impl Into<String>) -> Self {
+        Self::ConditionError {
+            condition: condition.into(),
+            reason: reason.into(),
+        }
+    }
+
+    pub fn cancelled(reason: impl Into<String>) -> Self {
+        Self::Cancelled {
+            reason: reason.into(),
+        }
+    }
+
+    pub fn incompatible_anvil_version(
+        template: impl Into<String>,
+        requirement: impl Into<String>,
+        current: impl Into<String>,
+    ) -> Self {
+        Self::IncompatibleAnvilVersion {
+            template: template.into(),
+            requirement: requirement.into(),
+            current: current.into(),
+        }
+    }
 }
@@ -0,0 +1,99 @@
+/*
+Module for the rules that keep files in a template or service directory out of
+generated projects: gitignore-style lines from the directory's `.anvilignore`, plus
+the `exclude:` globs in its anvil.yaml. Patterns match paths relative to the
+directory, as they are in the template (so `.tera` suffixes included).
+*/
+
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::path::Path;
+
+use crate::error::{EngineError, EngineResult};
+
+pub const IGNORE_FILE: &str = ".anvilignore";
+
+#[derive(Debug, Clone)]
+pub struct ExcludeRules {
+    matcher: Gitignore,
+}
+
+impl ExcludeRules {
+    /* Rules for the template or service rooted at `dir`, given its `exclude:` globs */
+    pub fn new(dir: &Path, patterns: &[String]) -> EngineResult<Self> {
+        let mut builder = GitignoreBuilder::new(dir);
+
+        let ignore_path = dir.join(IGNORE_FILE);
+        if ignore_path.is_file() {
+            if let Some(e) = builder.add(&ignore_path) {
+                return Err(EngineError::invalid_config(format!(
+                    "Invalid {}: {}",
+                    ignore_path.display(),
+                    e
+                )));
+            }
+        }
+
+        add_patterns(&mut builder, patterns)?;
+
+        let matcher = builder
+            .build()
+            .map_err(|e| EngineError::invalid_config(format!("Invalid exclude patterns: {}", e)))?;
+        Ok(Self { matcher })
+    }
+
+    /* Whether the entry at `relative_path` should be left out, along with everything under it */
+    pub fn is_excluded(&self, relative_path: &Path, is_dir: bool) -> bool {
+        relative_path == Path::new(IGNORE_FILE)
+            || self.matcher.matched(relative_path, is_dir).is_ignore()
+    }
+}
+
+/* Checks `exclude:` globs when a config is loaded, so mistakes show up early */
+pub fn validate_patterns(patterns: &[String]) -> EngineResult<()> {
+    add_patterns(&mut GitignoreBuilder::new(""), patterns)
+}
+
+fn add_patterns(builder: &mut GitignoreBuilder, patterns: &[String]) -> EngineResult<()> {
+    for pattern in patterns {
+        builder.add_line(None, pattern).map_err(|e| {
+            EngineError::invalid_config(format!("Invalid exclude pattern '{}': {}", pattern, e))
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[test]
+    fn test_exclude_rules() {
+        let temp_dir = TempDir::new().unwrap();
+        let dir = temp_dir.path();
+        std::fs::write(
+            dir.join(IGNORE_FILE),
+            "# editor and OS clutter\n.DS_Store\n*.swp\nnode_modules/\n*.log\n!keep.log\n",
+        )
+        .unwrap();
+
+        let exclude = vec!["docs/drafts/**".to_string(), "*.bak.tera".to_string()];
+        let rules = ExcludeRules::new(dir, &exclude).unwrap();
+        let excluded = |path: &str, is_dir: bool| rules.is_excluded(Path::new(path), is_dir);
+
+        assert!(excluded(".anvilignore", false));
+        assert!(excluded(".DS_Store", false));
+        assert!(excluded("src/.DS_Store", false));
+        assert!(excluded("src/.main.rs.swp", false));
+        assert!(excluded("node_modules", true));
+        assert!(!excluded("node_modules", false));
+        assert!(excluded("debug.log", false));
+        assert!(!excluded("keep.log", false));
+        assert!(excluded("docs/drafts/plan.md", false));
+        assert!(excluded("config.bak.tera", false));
+        assert!(!excluded("docs/guide.md", false));
+        assert!(!excluded("src/main.rs.tera", false));
+
+        assert!(validate_patterns(&["src/[".to_string()]).is_err());
+    }
+}
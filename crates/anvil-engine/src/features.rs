@@ -0,0 +1,187 @@
+/*
+Module for computing the active feature set of a template.
+Evaluates each feature's enabled_when condition, applies explicit user choices,
+and checks that every active feature has its dependencies enabled.
+*/
+
+use crate::condition::evaluate_condition;
+use crate::config::Feature;
+use crate::engine::Context;
+use crate::error::{EngineError, EngineResult};
+
+#[derive(Debug, Clone, Default)]
+pub struct FeatureSelection {
+    pub enabled: Vec<String>,
+    pub disabled: Vec<String>,
+}
+
+impl FeatureSelection {
+    pub fn new(enabled: Vec<String>, disabled: Vec<String>) -> Self {
+        Self { enabled, disabled }
+    }
+
+    /*
+    Rejects feature names the template does not declare and features that were
+    both enabled and disabled.
+    */
+    pub fn validate(&self, features: &[Feature]) -> EngineResult<()> {
+        for name in self.enabled.iter().chain(&self.disabled) {
+            if !features.iter().any(|feature| &feature.name == name) {
+                let available: Vec<&str> = features.iter().map(|f| f.name.as_str()).collect();
+                return Err(EngineError::invalid_config(format!(
+                    "Unknown feature '{}'. Available features: {}",
+                    name,
+                    if available.is_empty() {
+                        "none".to_string()
+                    } else {
+                        available.join(", ")
+                    }
+                )));
+            }
+        }
+
+        if let Some(name) = self
+            .enabled
+            .iter()
+            .find(|name| self.disabled.contains(name))
+        {
+            return Err(EngineError::invalid_config(format!(
+                "Feature '{}' cannot be both enabled and disabled",
+                name
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/*
+Computes which features are on, in declaration order, without checking dependencies.
+Explicit selections win; otherwise a feature is on when it has no enabled_when or
+its condition holds. Conditions see the features enabled before them.
+*/
+pub fn evaluate_features(
+    features: &[Feature],
+    context: &Context,
+    selection: &FeatureSelection,
+) -> EngineResult<Vec<String>> {
+    selection.validate(features)?;
+
+    let mut working_context = context.clone();
+    for feature in features {
+        working_context.declare_feature(feature.name.clone());
+    }
+    let mut active = Vec::new();
+
+    for feature in features {
+        let enabled = if selection.disabled.contains(&feature.name) {
+            false
+        } else if selection.enabled.contains(&feature.name) {
+            true
+        } else {
+            match &feature.enabled_when {
+                Some(condition) => evaluate_condition(condition, &working_context)?,
+                None => true,
+            }
+        };
+
+        if enabled {
+            working_context.add_feature(feature.name.clone());
+            active.push(feature.name.clone());
+        }
+    }
+
+    Ok(active)
+}
+
+/*
+Verifies that every active feature's dependencies are also active.
+*/
+pub fn check_feature_dependencies(features: &[Feature], active: &[String]) -> EngineResult<()> {
+    for feature in features.iter().filter(|f| active.contains(&f.name)) {
+        for dependency in &feature.dependencies {
+            if !active.contains(dependency) {
+                return Err(EngineError::feature_dependency_error(
+                    &feature.name,
+                    dependency,
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/*
+Computes the active feature set and checks its dependencies.
+*/
+pub fn resolve_features(
+    features: &[Feature],
+    context: &Context,
+    selection: &FeatureSelection,
+) -> EngineResult<Vec<String>> {
+    let active = evaluate_features(features, context, selection)?;
+    check_feature_dependencies(features, &active)?;
+    Ok(active)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feature(name: &str, enabled_when: Option<&str>, dependencies: &[&str]) -> Feature {
+        Feature {
+            name: name.to_string(),
+            description: format!("{} feature", name),
+            enabled_when: enabled_when.map(|c| c.to_string()),
+            dependencies: dependencies.iter().map(|d| d.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_enabled_when_and_selection() {
+        let features = vec![
+            feature("typescript", None, &[]),
+            feature("pooling", Some("enable_pooling == true"), &[]),
+            feature("auth_tables", Some("services.auth != 'none'"), &[]),
+            feature("demo", Some("feature_pooling"), &[]),
+        ];
+
+        let mut services = serde_yaml::Mapping::new();
+        services.insert("auth".into(), "clerk".into());
+        let context = Context::builder()
+            .variable("enable_pooling", true)
+            .variable("services", serde_yaml::Value::Mapping(services))
+            .build();
+
+        let active = resolve_features(&features, &context, &FeatureSelection::default()).unwrap();
+        assert_eq!(active, vec!["typescript", "pooling", "auth_tables", "demo"]);
+
+        let selection = FeatureSelection::new(vec![], vec!["pooling".to_string()]);
+        let active = resolve_features(&features, &context, &selection).unwrap();
+        assert_eq!(active, vec!["typescript", "auth_tables"]);
+
+        let selection = FeatureSelection::new(vec!["missing".to_string()], vec![]);
+        assert!(resolve_features(&features, &context, &selection).is_err());
+    }
+
+    #[test]
+    fn test_missing_dependency() {
+        let features = vec![
+            feature("database", Some("use_database"), &[]),
+            feature("migrations", None, &["database"]),
+        ];
+        let context = Context::builder().variable("use_database", false).build();
+
+        let err = resolve_features(&features, &context, &FeatureSelection::default()).unwrap_err();
+        assert!(matches!(
+            err,
+            EngineError::FeatureDependencyError { ref feature, ref dependency }
+                if feature == "migrations" && dependency == "database"
+        ));
+
+        let selection = FeatureSelection::new(vec!["database".to_string()], vec![]);
+        let active = resolve_features(&features, &context, &selection).unwrap();
+        assert_eq!(active, vec!["database", "migrations"]);
+    }
+}
@@ -1,25 +1,190 @@
+use serde::Serialize;
+use std::fmt;
 use std::path::{Path, PathBuf};
+use std::str::FromStr;
 #[cfg(unix)]
 use std::os::unix::fs::PermissionsExt;
 use tokio::fs;
 use tokio::io::AsyncWriteExt;
 
-use crate::engine::{ProcessedTemplate, ProcessedFile};
+use crate::composition::FileSource;
+use crate::config::FileMergingStrategy;
+use crate::engine::{file_mode, FileContent, ProcessedTemplate, ProcessedFile};
 use crate::error::{EngineError, EngineResult};
+use crate::manifest::{Manifest, MANIFEST_FILE};
+use crate::update::{merge_existing, MergeOutcome};
 
 pub struct FileGenerator {
     output_dir: PathBuf,
     dry_run: bool,
+    write_manifest: bool,
+    conflict_mode: ConflictMode,
+    conflict_prompt: Option<ConflictPrompt>,
 }
 
 pub type ProgressCallback = Box<dyn Fn(usize, usize, &str) + Send + Sync>;
 
-#[derive(Debug)]
+/*
+Asked which mode to apply to one conflicting file when the mode is `Prompt`. An error
+(e.g. the user cancelling) aborts the run before anything reaches the output directory.
+*/
+pub type ConflictPrompt = Box<dyn Fn(&Path) -> EngineResult<ConflictMode> + Send + Sync>;
+
+/*
+What to do when a generated file would replace a file with different content that
+is already on disk. Files the template does not produce are never touched.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ConflictMode {
+    #[default]
+    Overwrite,
+    Skip,
+    Prompt,
+    Merge,
+    Backup,
+}
+
+impl ConflictMode {
+    pub fn all() -> &'static [ConflictMode] {
+        &[
+            ConflictMode::Overwrite,
+            ConflictMode::Skip,
+            ConflictMode::Prompt,
+            ConflictMode::Merge,
+            ConflictMode::Backup,
+        ]
+    }
+}
+
+impl fmt::Display for ConflictMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ConflictMode::Overwrite => "overwrite",
+            ConflictMode::Skip => "skip",
+            ConflictMode::Prompt => "prompt",
+            ConflictMode::Merge => "merge",
+            ConflictMode::Backup => "backup",
+        };
+        f.write_str(name)
+    }
+}
+
+impl FromStr for ConflictMode {
+    type Err = String;
+
+    fn from_str(input: &str) -> Result<Self, Self::Err> {
+        Self::all()
+            .iter()
+            .find(|mode| mode.to_string().eq_ignore_ascii_case(input))
+            .copied()
+            .ok_or_else(|| {
+                let names: Vec<String> = Self::all().iter().map(|m| m.to_string()).collect();
+                format!(
+                    "unknown conflict mode '{}'. Expected one of: {}",
+                    input,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct GenerationResult {
     pub files_created: usize,
     pub directories_created: usize,
     pub bytes_written: u64,
     pub output_directory: PathBuf,
+    pub written_files: Vec<PathBuf>,
+    pub created_directories: Vec<PathBuf>,
+    /* Conflicting files, relative to the output directory, by how they were handled */
+    pub overwritten_files: Vec<PathBuf>,
+    pub skipped_files: Vec<PathBuf>,
+    /* Dry runs only: files a real run would ask about */
+    pub pending_files: Vec<PathBuf>,
+    pub merged_files: Vec<PathBuf>,
+    pub conflicted_files: Vec<PathBuf>,
+    pub backup_files: Vec<PathBuf>,
+    /* What the run replaced, kept so that a rollback can put it back */
+    #[serde(skip)]
+    pub replaced_files: Vec<ReplacedFile>,
+    /* Every file the template produced, in generation order */
+    pub files: Vec<FileReport>,
+}
+
+/* What generation did (or, for a dry run, would do) with one file */
+#[derive(Debug, Clone, Serialize)]
+pub struct FileReport {
+    pub path: PathBuf,
+    pub size: u64,
+    pub source: FileSource,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub merge_strategy: Option<FileMergingStrategy>,
+    pub executable: bool,
+    pub status: FileStatus,
+}
+
+/* A file that was on disk before a generation run wrote over it or backed it up */
+#[derive(Debug, Clone)]
+pub struct ReplacedFile {
+    pub path: PathBuf,
+    pub content: FileContent,
+    pub mode: Option<u32>,
+    pub backup: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FileStatus {
+    Create,
+    /* Already on disk with the same content */
+    Unchanged,
+    Overwrite,
+    Skip,
+    /* Would be asked about; only reported by dry runs */
+    Pending,
+    Merge,
+    /* Merged, but with conflict markers left to resolve */
+    Conflict,
+    Backup,
+}
+
+const STAGED_FILES_DIR: &str = "files";
+const DISPLACED_FILES_DIR: &str = "displaced";
+
+/* A file written to the staging directory, waiting to be moved into place */
+struct StagedFile {
+    path: PathBuf,
+    backup: Option<PathBuf>,
+}
+
+/* One reversible step of moving staged files into an existing output directory */
+enum CommitStep {
+    CreatedDirectory(PathBuf),
+    Moved { from: PathBuf, to: PathBuf },
+}
+
+/* How one generated file will be written, once any conflict has been resolved */
+enum FileAction {
+    Create,
+    Write,
+    Overwrite,
+    Skip,
+    Pending,
+    Merge(MergeOutcome),
+    Backup(PathBuf),
+}
+
+impl FileReport {
+    fn new(file: &ProcessedFile, status: FileStatus) -> Self {
+        Self {
+            path: file.output_path.clone(),
+            size: file.content.len() as u64,
+            source: file.source.clone(),
+            merge_strategy: file.merge_strategy.clone(),
+            executable: file.executable,
+            status,
+        }
+    }
 }
 
 impl FileGenerator {
@@ -27,6 +192,9 @@ impl FileGenerator {
         Self {
             output_dir: output_dir.into(),
             dry_run: false,
+            write_manifest: false,
+            conflict_mode: ConflictMode::default(),
+            conflict_prompt: None,
         }
     }
 
@@ -34,91 +202,592 @@ impl FileGenerator {
         Self {
             output_dir: output_dir.into(),
             dry_run: true,
+            write_manifest: false,
+            conflict_mode: ConflictMode::default(),
+            conflict_prompt: None,
         }
     }
 
+    /*
+    Records every written file's provenance and hash in `.anvil/manifest.json`,
+    updating the manifest already in the output directory if there is one.
+    */
+    pub fn with_manifest(mut self) -> Self {
+        self.write_manifest = true;
+        self
+    }
+
+    pub fn with_conflict_mode(mut self, mode: ConflictMode) -> Self {
+        self.conflict_mode = mode;
+        self
+    }
+
+    /* Without a prompt, conflicts in `Prompt` mode are skipped */
+    pub fn with_conflict_prompt(mut self, prompt: ConflictPrompt) -> Self {
+        self.conflict_prompt = Some(prompt);
+        self
+    }
+
+    /*
+    Writes the files into a staging directory next to the output directory and only
+    moves them into place once every file has been written. If anything fails first
+    (or the run is interrupted and `discard_staging` is called), the output directory
+    is left exactly as it was.
+    */
     pub async fn generate_files(
         &self,
         template: ProcessedTemplate,
         progress_callback: Option<ProgressCallback>,
     ) -> EngineResult<GenerationResult> {
+        if self.dry_run {
+            let (result, _) = self
+                .stage_files(template, progress_callback, &self.output_dir)
+                .await?;
+            return Ok(result);
+        }
+
+        // Anything left here was abandoned by an interrupted run
+        self.discard_staging().await?;
+        let staging_dir = self.staging_directory()?;
+        let staged_root = staging_dir.join(STAGED_FILES_DIR);
+        fs::create_dir_all(&staged_root)
+            .await
+            .map_err(|e| EngineError::file_error(&staged_root, e))?;
+
+        let staged = self
+            .stage_files(template, progress_callback, &staged_root)
+            .await;
+
+        // No awaits from here on, so an interrupted run cannot stop halfway through the swap
+        let committed = staged.and_then(|(result, staged_files)| {
+            self.commit_staged(&staging_dir, &staged_files)?;
+            Ok(result)
+        });
+        match std::fs::remove_dir_all(&staging_dir) {
+            Ok(()) => {}
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+            Err(e) if committed.is_ok() => return Err(EngineError::file_error(&staging_dir, e)),
+            Err(_) => {}
+        }
+        committed
+    }
+
+    /* Removes the staging directory of an unfinished run, if there is one */
+    pub async fn discard_staging(&self) -> EngineResult<()> {
+        let staging_dir = self.staging_directory()?;
+        match fs::remove_dir_all(&staging_dir).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(EngineError::file_error(&staging_dir, e)),
+        }
+    }
+
+    /*
+    The staging directory sits beside the output directory so that moving files into
+    place is a rename on the same filesystem rather than a copy.
+    */
+    fn staging_directory(&self) -> EngineResult<PathBuf> {
+        let output_dir = std::path::absolute(&self.output_dir)
+            .map_err(|e| EngineError::file_error(&self.output_dir, e))?;
+        match (output_dir.parent(), output_dir.file_name()) {
+            (Some(parent), Some(name)) => {
+                let mut staging_name = std::ffi::OsString::from(".");
+                staging_name.push(name);
+                staging_name.push(".anvil-staging");
+                Ok(parent.join(staging_name))
+            }
+            _ => Err(EngineError::invalid_config(format!(
+                "Cannot generate into '{}': it has no parent directory to stage files in",
+                self.output_dir.display()
+            ))),
+        }
+    }
+
+    /*
+    Resolves conflicts and writes every file under `root` (nothing is written for a
+    dry run). The result describes what committing the staged files will do to the
+    output directory.
+    */
+    async fn stage_files(
+        &self,
+        template: ProcessedTemplate,
+        progress_callback: Option<ProgressCallback>,
+        root: &Path,
+    ) -> EngineResult<(GenerationResult, Vec<StagedFile>)> {
         let total_files = template.files.len();
         let mut files_created = 0;
-        let mut directories_created = 0;
         let mut bytes_written = 0u64;
+        let mut written_files = Vec::new();
+        let mut created_directories = Vec::new();
+        let mut overwritten_files = Vec::new();
+        let mut skipped_files = Vec::new();
+        let mut pending_files = Vec::new();
+        let mut merged_files = Vec::new();
+        let mut conflicted_files = Vec::new();
+        let mut backup_files = Vec::new();
+        let mut replaced_files = Vec::new();
+        let mut staged_files = Vec::new();
+        let mut reports = Vec::new();
 
-        if !self.dry_run {
-            fs::create_dir_all(&self.output_dir)
-                .await
-                .map_err(|e| EngineError::file_error(&self.output_dir, e))?;
+        if !self.output_dir.exists() {
+            created_directories.push(self.output_dir.clone());
         }
 
-        for (index, file) in template.files.into_iter().enumerate() {
-            let (file_created, dirs_created, bytes) = Self::write_single_file(&self.output_dir, file, self.dry_run).await?;
-            
-            if file_created {
-                files_created += 1;
+        let manifest_path = self.output_dir.join(MANIFEST_FILE);
+        let mut manifest = if self.write_manifest && manifest_path.exists() {
+            Some(Manifest::from_file(&manifest_path).await?)
+        } else if self.write_manifest {
+            Some(Manifest::default())
+        } else {
+            None
+        };
+
+        for (index, mut file) in template.files.into_iter().enumerate() {
+            let full_path = self.output_dir.join(&file.output_path);
+            let action = self.resolve_conflict(&full_path, &file).await?;
+            let newly_created = matches!(action, FileAction::Create);
+            let mut backup = None;
+            let status = match &action {
+                FileAction::Create => FileStatus::Create,
+                FileAction::Write => FileStatus::Unchanged,
+                FileAction::Overwrite => FileStatus::Overwrite,
+                FileAction::Skip => FileStatus::Skip,
+                FileAction::Pending => FileStatus::Pending,
+                FileAction::Merge(MergeOutcome::Clean(_)) => FileStatus::Merge,
+                FileAction::Merge(MergeOutcome::Conflicted { .. }) => FileStatus::Conflict,
+                FileAction::Backup(_) => FileStatus::Backup,
+            };
+
+            match action {
+                FileAction::Create | FileAction::Write => {}
+                FileAction::Overwrite => overwritten_files.push(file.output_path.clone()),
+                FileAction::Skip | FileAction::Pending => {
+                    match status {
+                        FileStatus::Pending => pending_files.push(file.output_path.clone()),
+                        _ => skipped_files.push(file.output_path.clone()),
+                    }
+                    reports.push(FileReport::new(&file, status));
+                    if let Some(callback) = &progress_callback {
+                        callback(index + 1, total_files, "Processing files");
+                    }
+                    continue;
+                }
+                FileAction::Merge(outcome) => {
+                    file.content = match outcome {
+                        MergeOutcome::Clean(content) => content.into(),
+                        MergeOutcome::Conflicted { content, .. } => {
+                            conflicted_files.push(file.output_path.clone());
+                            content.into()
+                        }
+                    };
+                    merged_files.push(file.output_path.clone());
+                }
+                FileAction::Backup(backup_path) => {
+                    backup_files.push(
+                        backup_path
+                            .strip_prefix(&self.output_dir)
+                            .unwrap_or(&backup_path)
+                            .to_path_buf(),
+                    );
+                    backup = Some(backup_path);
+                }
+            }
+
+            if let Some(manifest) = manifest.as_mut() {
+                manifest.record(
+                    &file.output_path,
+                    &file.source,
+                    file.merge_strategy.as_ref(),
+                    file.content.as_bytes(),
+                );
             }
-            directories_created += dirs_created;
-            bytes_written += bytes;
-            
+
+            if !self.dry_run && !newly_created {
+                replaced_files
+                    .extend(Self::keep_original(&full_path, &file, backup.as_deref()).await?);
+            }
+
+            reports.push(FileReport::new(&file, status));
+            Self::note_new_directories(&self.output_dir, &full_path, &mut created_directories);
+            bytes_written += Self::write_single_file(root, &file, self.dry_run).await?;
+            files_created += 1;
+            if !self.dry_run && newly_created {
+                written_files.push(full_path);
+            }
+            staged_files.push(StagedFile {
+                path: file.output_path,
+                backup,
+            });
+
             if let Some(callback) = &progress_callback {
                 callback(index + 1, total_files, "Processing files");
             }
         }
 
-        Ok(GenerationResult {
+        if let Some(manifest) = manifest {
+            let manifest_file = manifest.to_file()?;
+            if !self.dry_run && manifest_path.exists() {
+                replaced_files
+                    .extend(Self::keep_original(&manifest_path, &manifest_file, None).await?);
+            }
+            Self::note_new_directories(&self.output_dir, &manifest_path, &mut created_directories);
+            Self::write_single_file(root, &manifest_file, self.dry_run).await?;
+            if !self.dry_run && !manifest_path.exists() {
+                written_files.push(manifest_path);
+            }
+            staged_files.push(StagedFile {
+                path: manifest_file.output_path,
+                backup: None,
+            });
+        }
+
+        let directories_created = created_directories
+            .iter()
+            .filter(|dir| **dir != self.output_dir)
+            .count();
+
+        let result = GenerationResult {
             files_created,
             directories_created,
             bytes_written,
             output_directory: self.output_dir.clone(),
-        })
+            written_files,
+            created_directories,
+            overwritten_files,
+            skipped_files,
+            pending_files,
+            merged_files,
+            conflicted_files,
+            backup_files,
+            replaced_files,
+            files: reports,
+        };
+        Ok((result, staged_files))
     }
 
-    async fn write_single_file(
+    /* Reads what is at `path` before `file` replaces it, unless the content is the same */
+    async fn keep_original(
+        path: &Path,
+        file: &ProcessedFile,
+        backup: Option<&Path>,
+    ) -> EngineResult<Option<ReplacedFile>> {
+        let content = FileContent::load(path)
+            .await
+            .map_err(|e| EngineError::file_error(path, e))?;
+        if content == file.content && backup.is_none() {
+            return Ok(None);
+        }
+        let metadata = fs::symlink_metadata(path)
+            .await
+            .map_err(|e| EngineError::file_error(path, e))?;
+        Ok(Some(ReplacedFile {
+            path: path.to_path_buf(),
+            content,
+            mode: file_mode(&metadata),
+            backup: backup.map(Path::to_path_buf),
+        }))
+    }
+
+    /* Records, parents first, the directories writing `path` will add to the output */
+    fn note_new_directories(
         output_dir: &Path,
-        file: ProcessedFile,
-        dry_run: bool,
-    ) -> EngineResult<(bool, usize, u64)> {
-        let full_path = output_dir.join(&file.output_path);
-        let mut directories_created = 0;
-
-        if let Some(parent) = full_path.parent() {
-            if !dry_run {
-                if !parent.exists() {
-                    fs::create_dir_all(parent)
-                        .await
-                        .map_err(|e| EngineError::file_error(parent, e))?;
-                    directories_created = Self::count_directories_in_path(parent, output_dir);
+        path: &Path,
+        created_directories: &mut Vec<PathBuf>,
+    ) {
+        let Some(parent) = path.parent() else {
+            return;
+        };
+        let mut new_directories: Vec<PathBuf> = parent
+            .ancestors()
+            .take_while(|dir| *dir != output_dir && !dir.exists())
+            .map(Path::to_path_buf)
+            .collect();
+        new_directories.reverse();
+        for dir in new_directories {
+            if !created_directories.contains(&dir) {
+                created_directories.push(dir);
+            }
+        }
+    }
+
+    /*
+    Moves the staged files into the output directory. A missing or empty output
+    directory is replaced by the staging directory in a single rename. Otherwise
+    each file is moved into place, with any file it replaces moved aside first, and
+    every step is undone if one of them fails.
+    */
+    fn commit_staged(&self, staging_dir: &Path, staged_files: &[StagedFile]) -> EngineResult<()> {
+        let staged_root = staging_dir.join(STAGED_FILES_DIR);
+
+        let is_empty = match std::fs::read_dir(&self.output_dir) {
+            Ok(mut entries) => entries.next().is_none(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => true,
+            Err(e) => return Err(EngineError::file_error(&self.output_dir, e)),
+        };
+        if is_empty {
+            let existed = self.output_dir.exists();
+            if existed {
+                std::fs::remove_dir(&self.output_dir)
+                    .map_err(|e| EngineError::file_error(&self.output_dir, e))?;
+            }
+            return std::fs::rename(&staged_root, &self.output_dir).map_err(|e| {
+                if existed {
+                    let _ = std::fs::create_dir(&self.output_dir);
+                }
+                EngineError::file_error(&self.output_dir, e)
+            });
+        }
+
+        let displaced_root = staging_dir.join(DISPLACED_FILES_DIR);
+        let mut journal = Vec::new();
+        for file in staged_files {
+            let target = self.output_dir.join(&file.path);
+            let aside = match &file.backup {
+                Some(backup) => Some(backup.clone()),
+                None if target.is_file() || target.is_symlink() => {
+                    Some(displaced_root.join(&file.path))
+                }
+                None => None,
+            };
+
+            let committed = Self::create_parent_directories(&target, &mut journal)
+                .and_then(|()| match &aside {
+                    Some(aside) => std::fs::create_dir_all(aside.parent().unwrap_or(staging_dir))
+                        .map_err(|e| EngineError::file_error(aside, e))
+                        .and_then(|()| Self::move_file(&target, aside, &mut journal)),
+                    None => Ok(()),
+                })
+                .and_then(|()| {
+                    Self::move_file(&staged_root.join(&file.path), &target, &mut journal)
+                });
+
+            if let Err(e) = committed {
+                Self::undo_commit(journal);
+                return Err(e);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn create_parent_directories(path: &Path, journal: &mut Vec<CommitStep>) -> EngineResult<()> {
+        let Some(parent) = path.parent() else {
+            return Ok(());
+        };
+        let mut missing: Vec<PathBuf> = parent
+            .ancestors()
+            .take_while(|dir| !dir.exists())
+            .map(Path::to_path_buf)
+            .collect();
+        missing.reverse();
+        for dir in missing {
+            std::fs::create_dir(&dir).map_err(|e| EngineError::file_error(&dir, e))?;
+            journal.push(CommitStep::CreatedDirectory(dir));
+        }
+        Ok(())
+    }
+
+    fn move_file(from: &Path, to: &Path, journal: &mut Vec<CommitStep>) -> EngineResult<()> {
+        std::fs::rename(from, to).map_err(|e| EngineError::file_error(to, e))?;
+        journal.push(CommitStep::Moved {
+            from: from.to_path_buf(),
+            to: to.to_path_buf(),
+        });
+        Ok(())
+    }
+
+    fn undo_commit(journal: Vec<CommitStep>) {
+        for step in journal.into_iter().rev() {
+            match step {
+                CommitStep::Moved { from, to } => {
+                    let _ = std::fs::rename(&to, &from);
+                }
+                CommitStep::CreatedDirectory(dir) => {
+                    let _ = std::fs::remove_dir(&dir);
+                }
+            }
+        }
+    }
+
+    /*
+    Decides how to write a file given what is already at its path. Identical content
+    is not a conflict; anything else is handled by the configured conflict mode.
+    */
+    async fn resolve_conflict(
+        &self,
+        full_path: &Path,
+        file: &ProcessedFile,
+    ) -> EngineResult<FileAction> {
+        if !full_path.is_file() && !full_path.is_symlink() {
+            return Ok(FileAction::Create);
+        }
+        // Anvil's own metadata always reflects the latest run
+        if file.output_path.starts_with(".anvil") {
+            return Ok(FileAction::Write);
+        }
+
+        let existing = FileContent::load(full_path)
+            .await
+            .map_err(|e| EngineError::file_error(full_path, e))?;
+        if existing == file.content {
+            return Ok(FileAction::Write);
+        }
+
+        let mode = match (self.conflict_mode, &self.conflict_prompt) {
+            // A dry run reports what would be asked rather than asking
+            (ConflictMode::Prompt, _) if self.dry_run => return Ok(FileAction::Pending),
+            (ConflictMode::Prompt, Some(prompt)) => prompt(&file.output_path)?,
+            (ConflictMode::Prompt, None) => ConflictMode::Skip,
+            (mode, _) => mode,
+        };
+
+        Ok(match mode {
+            ConflictMode::Overwrite => FileAction::Overwrite,
+            ConflictMode::Skip | ConflictMode::Prompt => FileAction::Skip,
+            // Files that are not text cannot be merged line by line, so keep a copy instead
+            ConflictMode::Merge => match (existing.as_text(), file.content.as_text()) {
+                (Some(existing), Some(generated)) => {
+                    FileAction::Merge(merge_existing(existing, generated))
                 }
+                _ => FileAction::Backup(Self::backup_path(full_path)),
+            },
+            ConflictMode::Backup => FileAction::Backup(Self::backup_path(full_path)),
+        })
+    }
+
+    /* The first of `name.bak`, `name.bak.1`, ... that does not exist yet */
+    fn backup_path(path: &Path) -> PathBuf {
+        let mut name = path.as_os_str().to_os_string();
+        name.push(".bak");
+        let mut candidate = PathBuf::from(&name);
+        let mut counter = 1;
+        while candidate.exists() {
+            let mut numbered = name.clone();
+            numbered.push(format!(".{}", counter));
+            candidate = PathBuf::from(numbered);
+            counter += 1;
+        }
+        candidate
+    }
+
+    /*
+    Undoes a generation run: removes the files it created, puts back the files it
+    overwrote, merged into or backed up, then removes the directories it created
+    (deepest first). Anything the run did not touch is left alone.
+    */
+    pub async fn rollback(result: &GenerationResult) -> EngineResult<()> {
+        for path in &result.written_files {
+            Self::remove_if_present(path).await?;
+        }
+
+        for original in &result.replaced_files {
+            Self::remove_if_present(&original.path).await?;
+            Self::write_content(&original.path, &original.content, original.mode, false).await?;
+            if let Some(backup) = &original.backup {
+                Self::remove_if_present(backup).await?;
+            }
+        }
+
+        for dir in result.created_directories.iter().rev() {
+            // Hooks may have added files (e.g. node_modules), so remove the whole tree
+            match fs::remove_dir_all(dir).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(EngineError::file_error(dir, e)),
             }
         }
 
+        Ok(())
+    }
+
+    async fn remove_if_present(path: &Path) -> EngineResult<()> {
+        match fs::remove_file(path).await {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(EngineError::file_error(path, e)),
+        }
+    }
+
+    async fn write_single_file(
+        root: &Path,
+        file: &ProcessedFile,
+        dry_run: bool,
+    ) -> EngineResult<u64> {
+        let full_path = root.join(&file.output_path);
         let bytes_written = file.content.len() as u64;
 
         if !dry_run {
-            let mut file_handle = fs::File::create(&full_path)
-                .await
-                .map_err(|e| EngineError::file_error(&full_path, e))?;
-            
-            file_handle.write_all(file.content.as_bytes())
-                .await
-                .map_err(|e| EngineError::file_error(&full_path, e))?;
-            
-            file_handle.flush()
-                .await
-                .map_err(|e| EngineError::file_error(&full_path, e))?;
+            if let Some(parent) = full_path.parent() {
+                fs::create_dir_all(parent)
+                    .await
+                    .map_err(|e| EngineError::file_error(parent, e))?;
+            }
+
+            Self::write_content(&full_path, &file.content, file.mode, file.executable).await?;
+        }
 
-            if file.executable {
-                Self::make_executable(&full_path).await?;
+        Ok(bytes_written)
+    }
+
+    /*
+    Writes a file's content to `path`, replacing whatever is there. Symbolic links are
+    recreated, and regular files get the template's mode, or are made executable
+    when no mode is known but the file looks like it should be.
+    */
+    pub(crate) async fn write_content(
+        path: &Path,
+        content: &FileContent,
+        mode: Option<u32>,
+        executable: bool,
+    ) -> EngineResult<()> {
+        if let FileContent::Symlink(target) = content {
+            match fs::remove_file(path).await {
+                Ok(()) => {}
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => return Err(EngineError::file_error(path, e)),
             }
+            return Self::create_symlink(target, path).await;
         }
 
-        Ok((true, directories_created, bytes_written))
+        let mut file_handle = fs::File::create(path)
+            .await
+            .map_err(|e| EngineError::file_error(path, e))?;
+        
+        file_handle.write_all(content.as_bytes())
+            .await
+            .map_err(|e| EngineError::file_error(path, e))?;
+        
+        file_handle.flush()
+            .await
+            .map_err(|e| EngineError::file_error(path, e))?;
+
+        match mode {
+            #[cfg(unix)]
+            Some(mode) => fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+                .await
+                .map_err(|e| EngineError::file_error(path, e)),
+            _ if executable => Self::make_executable(path).await,
+            _ => Ok(()),
+        }
+    }
+
+    #[cfg(unix)]
+    async fn create_symlink(target: &Path, path: &Path) -> EngineResult<()> {
+        fs::symlink(target, path)
+            .await
+            .map_err(|e| EngineError::file_error(path, e))
+    }
+
+    /* Without Unix symlinks the link is written as a file holding its target, like git does */
+    #[cfg(not(unix))]
+    async fn create_symlink(target: &Path, path: &Path) -> EngineResult<()> {
+        fs::write(path, target.as_os_str().as_encoded_bytes())
+            .await
+            .map_err(|e| EngineError::file_error(path, e))
     }
 
     #[cfg(unix)]
-    async fn make_executable(path: &Path) -> EngineResult<()> {
+    pub(crate) async fn make_executable(path: &Path) -> EngineResult<()> {
         let metadata = fs::metadata(path)
             .await
             .map_err(|e| EngineError::file_error(path, e))?;
@@ -136,17 +805,10 @@ impl FileGenerator {
     }
 
     #[cfg(not(unix))]
-    async fn make_executable(_path: &Path) -> EngineResult<()> {
+    pub(crate) async fn make_executable(_path: &Path) -> EngineResult<()> {
         Ok(())
     }
 
-    fn count_directories_in_path(created_path: &Path, base_path: &Path) -> usize {
-        created_path
-            .strip_prefix(base_path)
-            .map(|relative| relative.components().count())
-            .unwrap_or(0)
-    }
-
     pub async fn check_output_directory(&self) -> EngineResult<DirectoryStatus> {
         if !self.output_dir.exists() {
             return Ok(DirectoryStatus::DoesNotExist);
@@ -165,24 +827,6 @@ impl FileGenerator {
         }
     }
 
-    pub async fn clean_output_directory(&self) -> EngineResult<()> {
-        if self.dry_run {
-            return Ok(());
-        }
-
-        if self.output_dir.exists() {
-            fs::remove_dir_all(&self.output_dir)
-                .await
-                .map_err(|e| EngineError::file_error(&self.output_dir, e))?;
-        }
-
-        fs::create_dir_all(&self.output_dir)
-            .await
-            .map_err(|e| EngineError::file_error(&self.output_dir, e))?;
-
-        Ok(())
-    }
-
     pub fn output_directory(&self) -> &Path {
         &self.output_dir
     }
@@ -202,29 +846,41 @@ pub enum DirectoryStatus {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::composition::FileSource;
+    use crate::config::{FileMergingStrategy, Hooks, ServiceCategory};
     use crate::engine::{ProcessedTemplate, ProcessedFile};
     use tempfile::TempDir;
-    use std::path::PathBuf;
+    use std::path::{Path, PathBuf};
 
     fn create_test_processed_template() -> ProcessedTemplate {
         ProcessedTemplate {
             files: vec![
                 ProcessedFile {
                     output_path: PathBuf::from("main.rs"),
-                    content: "fn main() { println!(\"Hello, world!\"); }".to_string(),
+                    content: "fn main() { println!(\"Hello, world!\"); }".into(),
+                    mode: None,
                     executable: false,
+                    source: FileSource::BaseTemplate,
+                    merge_strategy: None,
                 },
                 ProcessedFile {
                     output_path: PathBuf::from("src/lib.rs"),
-                    content: "// Library code".to_string(),
+                    content: "// Library code".into(),
+                    mode: None,
                     executable: false,
+                    source: FileSource::BaseTemplate,
+                    merge_strategy: None,
                 },
                 ProcessedFile {
                     output_path: PathBuf::from("scripts/build.sh"),
-                    content: "#!/bin/bash\necho 'Building...'".to_string(),
+                    content: "#!/bin/bash\necho 'Building...'".into(),
+                    mode: None,
                     executable: true,
+                    source: FileSource::BaseTemplate,
+                    merge_strategy: None,
                 },
             ],
+            hooks: Hooks::default(),
         }
     }
 
@@ -250,6 +906,143 @@ mod tests {
         assert!(main_content.contains("Hello, world!"));
     }
 
+    #[tokio::test]
+    async fn test_generation_manifest() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path().join("output");
+
+        let mut template = create_test_processed_template();
+        template.files[0].source = FileSource::Merged {
+            sources: vec![
+                FileSource::BaseTemplate,
+                FileSource::Service {
+                    category: ServiceCategory::Auth,
+                    provider: "clerk".to_string(),
+                },
+            ],
+        };
+        template.files[0].merge_strategy = Some(FileMergingStrategy::Append);
+
+        let generator = FileGenerator::new(&output_dir).with_manifest();
+        let result = generator.generate_files(template, None).await.unwrap();
+        assert_eq!(result.files_created, 3);
+
+        let manifest = Manifest::from_file(&output_dir.join(MANIFEST_FILE))
+            .await
+            .unwrap();
+        assert_eq!(manifest.files.len(), 3);
+
+        let main = manifest.entry(Path::new("main.rs")).unwrap();
+        assert_eq!(main.sources.len(), 2);
+        assert!(main.is_from_service(&ServiceCategory::Auth));
+        assert_eq!(main.merge_strategy, Some(FileMergingStrategy::Append));
+        let main_content = fs::read_to_string(output_dir.join("main.rs"))
+            .await
+            .unwrap();
+        assert!(main.is_unmodified(main_content.as_bytes()));
+    }
+
+    #[tokio::test]
+    async fn test_failed_generation_leaves_output_untouched() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path().join("output");
+
+        let broken_template = || {
+            let mut template = create_test_processed_template();
+            // Cannot be written: main.rs is a file, not a directory
+            template.files.push(ProcessedFile {
+                output_path: PathBuf::from("main.rs/nested.rs"),
+                content: "// unreachable".into(),
+                mode: None,
+                executable: false,
+                source: FileSource::BaseTemplate,
+                merge_strategy: None,
+            });
+            template
+        };
+
+        let generator = FileGenerator::new(&output_dir);
+        assert!(generator.generate_files(broken_template(), None).await.is_err());
+        assert!(!output_dir.exists());
+
+        fs::create_dir_all(&output_dir).await.unwrap();
+        fs::write(output_dir.join("main.rs"), "fn main() {}\n")
+            .await
+            .unwrap();
+        assert!(generator.generate_files(broken_template(), None).await.is_err());
+
+        let main = fs::read_to_string(output_dir.join("main.rs")).await.unwrap();
+        assert_eq!(main, "fn main() {}\n");
+        assert!(!output_dir.join("src").exists());
+        let mut entries = fs::read_dir(temp_dir.path()).await.unwrap();
+        let mut names = Vec::new();
+        while let Some(entry) = entries.next_entry().await.unwrap() {
+            names.push(entry.file_name());
+        }
+        assert_eq!(names, vec![std::ffi::OsString::from("output")]);
+    }
+
+    #[tokio::test]
+    async fn test_commit_into_empty_directory_renames_staging() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path().join("output");
+        fs::create_dir_all(&output_dir).await.unwrap();
+
+        let generator = FileGenerator::new(&output_dir);
+        let result = generator
+            .generate_files(create_test_processed_template(), None)
+            .await
+            .unwrap();
+        assert_eq!(result.files_created, 3);
+        assert!(!result.created_directories.contains(&output_dir));
+
+        let lib = fs::read_to_string(output_dir.join("src/lib.rs"))
+            .await
+            .unwrap();
+        assert_eq!(lib, "// Library code");
+        assert!(!generator.staging_directory().unwrap().exists());
+    }
+
+    #[tokio::test]
+    async fn test_failed_commit_restores_displaced_files() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path().join("output");
+        fs::create_dir_all(&output_dir).await.unwrap();
+        fs::write(output_dir.join("main.rs"), "// mine").await.unwrap();
+
+        let generator = FileGenerator::new(&output_dir);
+        let staging_dir = generator.staging_directory().unwrap();
+        let staged_root = staging_dir.join(STAGED_FILES_DIR);
+        fs::create_dir_all(&staged_root).await.unwrap();
+        fs::write(staged_root.join("main.rs"), "// generated")
+            .await
+            .unwrap();
+
+        // The second file was never staged, so moving it fails after main.rs is in place
+        let staged_files = vec![
+            StagedFile {
+                path: PathBuf::from("main.rs"),
+                backup: None,
+            },
+            StagedFile {
+                path: PathBuf::from("src/lib.rs"),
+                backup: None,
+            },
+        ];
+        assert!(
+            generator
+                .commit_staged(&staging_dir, &staged_files)
+                .is_err()
+        );
+
+        let main = fs::read_to_string(output_dir.join("main.rs")).await.unwrap();
+        assert_eq!(main, "// mine");
+        assert!(!output_dir.join("src").exists());
+        let staged = fs::read_to_string(staged_root.join("main.rs")).await.unwrap();
+        assert_eq!(staged, "// generated");
+        assert!(!staging_dir.join(DISPLACED_FILES_DIR).join("main.rs").exists());
+    }
+
     #[tokio::test]
     async fn test_dry_run() {
         let temp_dir = TempDir::new().unwrap();
@@ -266,6 +1059,40 @@ mod tests {
         assert!(!output_dir.exists());
     }
 
+    #[tokio::test]
+    async fn test_dry_run_report() {
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path().join("output");
+
+        let mut template = create_test_processed_template();
+        template.files[1].source = FileSource::Merged {
+            sources: vec![
+                FileSource::BaseTemplate,
+                FileSource::Service {
+                    category: ServiceCategory::AI,
+                    provider: "openai".to_string(),
+                },
+            ],
+        };
+        template.files[1].merge_strategy = Some(FileMergingStrategy::Append);
+
+        let generator = FileGenerator::new_dry_run(&output_dir);
+        let result = generator.generate_files(template, None).await.unwrap();
+        assert_eq!(result.directories_created, 2);
+
+        let build = &result.files[2];
+        assert_eq!(build.path, PathBuf::from("scripts/build.sh"));
+        assert!(build.executable);
+        assert_eq!(build.status, FileStatus::Create);
+        assert_eq!(build.size, "#!/bin/bash\necho 'Building...'".len() as u64);
+        assert_eq!(result.files[1].source.to_string(), "merged(base, ai:openai)");
+
+        let json = serde_json::to_value(&result).unwrap();
+        assert_eq!(json["files"][1]["merge_strategy"], "append");
+        assert_eq!(json["files"][1]["source"]["type"], "merged");
+        assert_eq!(json["files"][0]["status"], "create");
+    }
+
     #[tokio::test]
     async fn test_directory_status_check() {
         let temp_dir = TempDir::new().unwrap();
@@ -286,22 +1113,99 @@ mod tests {
     }
 
     #[tokio::test]
-    async fn test_clean_output_directory() {
+    async fn test_conflict_modes() {
         let temp_dir = TempDir::new().unwrap();
         let output_dir = temp_dir.path().join("output");
-        
-        fs::create_dir_all(&output_dir).await.unwrap();
-        fs::write(output_dir.join("test.txt"), "content").await.unwrap();
-        
-        let generator = FileGenerator::new(&output_dir);
-        
-        let status = generator.check_output_directory().await.unwrap();
-        assert_eq!(status, DirectoryStatus::ExistsWithContent);
-        
-        generator.clean_output_directory().await.unwrap();
-        
-        let status = generator.check_output_directory().await.unwrap();
-        assert_eq!(status, DirectoryStatus::ExistsEmpty);
+
+        for mode in ConflictMode::all() {
+            let project_dir = output_dir.join(mode.to_string());
+            fs::create_dir_all(project_dir.join(".git")).await.unwrap();
+            fs::write(project_dir.join(".git/HEAD"), "ref: refs/heads/main\n")
+                .await
+                .unwrap();
+            fs::write(project_dir.join("main.rs"), "fn main() {}\n")
+                .await
+                .unwrap();
+
+            let generator = FileGenerator::new(&project_dir).with_conflict_mode(*mode);
+            let result = generator
+                .generate_files(create_test_processed_template(), None)
+                .await
+                .unwrap();
+
+            // Files the template does not produce are left alone in every mode
+            assert!(project_dir.join(".git/HEAD").exists());
+            assert!(!result.written_files.contains(&project_dir.join("main.rs")));
+
+            let main = fs::read_to_string(project_dir.join("main.rs")).await.unwrap();
+            match mode {
+                ConflictMode::Overwrite => {
+                    assert_eq!(result.overwritten_files, vec![PathBuf::from("main.rs")]);
+                    assert!(main.contains("Hello, world!"));
+                }
+                ConflictMode::Skip | ConflictMode::Prompt => {
+                    assert_eq!(result.skipped_files, vec![PathBuf::from("main.rs")]);
+                    assert_eq!(result.files_created, 2);
+                    assert_eq!(main, "fn main() {}\n");
+                }
+                ConflictMode::Merge => {
+                    assert_eq!(result.merged_files, vec![PathBuf::from("main.rs")]);
+                    assert_eq!(result.conflicted_files, vec![PathBuf::from("main.rs")]);
+                    assert!(main.contains("<<<<<<< ours\nfn main() {}\n"));
+                }
+                ConflictMode::Backup => {
+                    assert_eq!(result.backup_files, vec![PathBuf::from("main.rs.bak")]);
+                    assert!(main.contains("Hello, world!"));
+                    let backup = fs::read_to_string(project_dir.join("main.rs.bak"))
+                        .await
+                        .unwrap();
+                    assert_eq!(backup, "fn main() {}\n");
+                }
+            }
+        }
+
+        let project_dir = output_dir.join("prompted");
+        fs::create_dir_all(&project_dir).await.unwrap();
+        fs::write(project_dir.join("main.rs"), "fn main() {}\n")
+            .await
+            .unwrap();
+        let generator = FileGenerator::new(&project_dir)
+            .with_conflict_mode(ConflictMode::Prompt)
+            .with_conflict_prompt(Box::new(|_| Ok(ConflictMode::Overwrite)));
+        let result = generator
+            .generate_files(create_test_processed_template(), None)
+            .await
+            .unwrap();
+        assert_eq!(result.overwritten_files, vec![PathBuf::from("main.rs")]);
+
+        // Cancelling the prompt aborts the run without touching the output directory
+        fs::write(project_dir.join("main.rs"), "fn main() {}\n")
+            .await
+            .unwrap();
+        fs::remove_dir_all(project_dir.join("src")).await.unwrap();
+        let generator = FileGenerator::new(&project_dir)
+            .with_conflict_mode(ConflictMode::Prompt)
+            .with_conflict_prompt(Box::new(|_| Err(EngineError::cancelled("no answer"))));
+        assert!(
+            generator
+                .generate_files(create_test_processed_template(), None)
+                .await
+                .is_err()
+        );
+        let main = fs::read_to_string(project_dir.join("main.rs")).await.unwrap();
+        assert_eq!(main, "fn main() {}\n");
+        assert!(!project_dir.join("src").exists());
+
+        // Dry runs report the conflict as pending instead of asking
+        let generator = FileGenerator::new_dry_run(&project_dir)
+            .with_conflict_mode(ConflictMode::Prompt)
+            .with_conflict_prompt(Box::new(|_| panic!("dry runs must not prompt")));
+        let result = generator
+            .generate_files(create_test_processed_template(), None)
+            .await
+            .unwrap();
+        assert_eq!(result.pending_files, vec![PathBuf::from("main.rs")]);
+        assert_eq!(result.files[0].status, FileStatus::Pending);
     }
 
     #[tokio::test]
@@ -0,0 +1,502 @@
+/*
+Module for running the lifecycle hooks declared in a template's anvil.yaml.
+Handles conditions, environment setup, working directory resolution,
+timeouts, and the per-hook failure policy.
+*/
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::time::Duration;
+use tokio::process::Command;
+
+use crate::condition::evaluate_condition;
+use crate::config::{HookCommand, HookFailurePolicy};
+use crate::engine::Context;
+use crate::error::{EngineError, EngineResult};
+use crate::generator::{FileGenerator, GenerationResult, ProgressCallback};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HookStage {
+    PreGenerate,
+    PostGenerate,
+}
+
+impl HookStage {
+    pub fn name(&self) -> &'static str {
+        match self {
+            HookStage::PreGenerate => "pre_generate",
+            HookStage::PostGenerate => "post_generate",
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct HookExecution {
+    pub command: String,
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct HookFailure {
+    pub command: String,
+    pub reason: String,
+    pub output: Option<String>,
+}
+
+#[derive(Debug, Default)]
+pub struct HookReport {
+    pub executed: Vec<HookExecution>,
+    pub skipped: Vec<String>,
+    pub failed: Vec<HookFailure>,
+}
+
+impl HookFailure {
+    fn into_error(self) -> EngineError {
+        let reason = match &self.output {
+            Some(output) if !output.trim().is_empty() => {
+                format!("{}\n{}", self.reason, output.trim_end())
+            }
+            _ => self.reason,
+        };
+        EngineError::hook_error(self.command, reason)
+    }
+}
+
+pub struct HookRunner {
+    output_dir: PathBuf,
+    generation: Option<GenerationResult>,
+}
+
+impl HookRunner {
+    pub fn new(output_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            output_dir: output_dir.into(),
+            generation: None,
+        }
+    }
+
+    /*
+    Attaches the result of the generation run these hooks follow, so that a hook
+    with `on_failure: rollback` can remove the files that run wrote.
+    */
+    pub fn with_generation(mut self, generation: GenerationResult) -> Self {
+        self.generation = Some(generation);
+        self
+    }
+
+    /*
+    Runs every hook of a stage in declaration order. Hooks whose condition does not
+    hold are skipped. A failing hook is handled according to its on_failure policy:
+    abort stops with a HookError, continue records the failure in the report, and
+    rollback removes the generated files before stopping with a HookError.
+    */
+    pub async fn run_hooks(
+        &self,
+        stage: HookStage,
+        hooks: &[HookCommand],
+        context: &Context,
+        progress_callback: Option<ProgressCallback>,
+    ) -> EngineResult<HookReport> {
+        let mut report = HookReport::default();
+        let total_hooks = hooks.len();
+
+        for (index, hook) in hooks.iter().enumerate() {
+            if !self.should_run(hook, context)? {
+                report.skipped.push(hook.command.clone());
+                continue;
+            }
+
+            if let Some(callback) = &progress_callback {
+                callback(index + 1, total_hooks, &hook.command);
+            }
+
+            let failure = match self.execute(stage, hook).await? {
+                Ok(execution) => {
+                    report.executed.push(execution);
+                    continue;
+                }
+                Err(failure) => failure,
+            };
+
+            match hook.on_failure {
+                HookFailurePolicy::Continue => report.failed.push(failure),
+                HookFailurePolicy::Abort => return Err(failure.into_error()),
+                HookFailurePolicy::Rollback => {
+                    if let Some(generation) = &self.generation {
+                        FileGenerator::rollback(generation).await?;
+                    }
+                    let mut error = failure;
+                    error.reason = format!("{} (generated files were rolled back)", error.reason);
+                    return Err(error.into_error());
+                }
+            }
+        }
+
+        Ok(report)
+    }
+
+    /*
+    Evaluates the hook's condition against the generation context.
+    A missing condition is treated the same as "always".
+    */
+    pub fn should_run(&self, hook: &HookCommand, context: &Context) -> EngineResult<bool> {
+        match &hook.condition {
+            Some(condition) => evaluate_condition(condition, context),
+            None => Ok(true),
+        }
+    }
+
+    /*
+    Runs a single hook through the platform shell, ignoring its on_failure policy.
+    */
+    pub async fn run_hook(
+        &self,
+        stage: HookStage,
+        hook: &HookCommand,
+    ) -> EngineResult<HookExecution> {
+        self.execute(stage, hook)
+            .await?
+            .map_err(HookFailure::into_error)
+    }
+
+    /*
+    Spawns the hook and waits for it, honoring its timeout. Output is streamed straight
+    to the parent process unless capture_output is set. The outer error is reserved for
+    problems resolving the hook or an interrupted run; the inner one describes how the
+    command itself failed.
+    */
+    async fn execute(
+        &self,
+        stage: HookStage,
+        hook: &HookCommand,
+    ) -> EngineResult<Result<HookExecution, HookFailure>> {
+        let working_dir = self.resolve_working_dir(hook)?;
+        let failure = |reason: String, output: Option<String>| HookFailure {
+            command: hook.command.clone(),
+            reason,
+            output,
+        };
+
+        let (stdout, stderr) = if hook.capture_output {
+            (Stdio::piped(), Stdio::piped())
+        } else {
+            (Stdio::inherit(), Stdio::inherit())
+        };
+
+        let mut command = Self::shell_command(&hook.command);
+        command
+            .current_dir(&working_dir)
+            .envs(&hook.env)
+            .stdin(Stdio::null())
+            .stdout(stdout)
+            .stderr(stderr)
+            .kill_on_drop(true);
+        // Its own process group, so that stopping the hook also stops what it started
+        #[cfg(unix)]
+        command.process_group(0);
+
+        let child = match command.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                return Ok(Err(failure(
+                    format!("{} hook could not be started: {}", stage.name(), e),
+                    None,
+                )))
+            }
+        };
+
+        // Returning before the hook exits kills its process group
+        let mut group = ProcessGroupGuard(child.id());
+        let wait = child.wait_with_output();
+        let timeout = async {
+            match hook.timeout {
+                Some(seconds) => tokio::time::sleep(Duration::from_secs(seconds)).await,
+                None => std::future::pending().await,
+            }
+        };
+        let result = tokio::select! {
+            result = wait => result,
+            _ = timeout => {
+                return Ok(Err(failure(
+                    format!(
+                        "{} hook timed out after {}s",
+                        stage.name(),
+                        hook.timeout.unwrap_or_default()
+                    ),
+                    None,
+                )))
+            }
+            _ = tokio::signal::ctrl_c() => {
+                return Err(EngineError::hook_error(
+                    &hook.command,
+                    format!("{} hook was interrupted", stage.name()),
+                ))
+            }
+        };
+        group.disarm();
+
+        let output = match result {
+            Ok(output) => output,
+            Err(e) => {
+                return Ok(Err(failure(
+                    format!("{} hook could not be awaited: {}", stage.name(), e),
+                    None,
+                )))
+            }
+        };
+
+        let captured = if hook.capture_output {
+            let mut text = String::from_utf8_lossy(&output.stdout).into_owned();
+            text.push_str(&String::from_utf8_lossy(&output.stderr));
+            Some(text)
+        } else {
+            None
+        };
+
+        if !output.status.success() {
+            let reason = match output.status.code() {
+                Some(code) => format!("{} hook exited with status {}", stage.name(), code),
+                None => format!("{} hook was terminated by a signal", stage.name()),
+            };
+            return Ok(Err(failure(reason, captured)));
+        }
+
+        Ok(Ok(HookExecution {
+            command: hook.command.clone(),
+            output: captured,
+        }))
+    }
+
+    fn resolve_working_dir(&self, hook: &HookCommand) -> EngineResult<PathBuf> {
+        let working_dir = match &hook.working_dir {
+            Some(dir) => self.output_dir.join(dir),
+            None => self.output_dir.clone(),
+        };
+
+        if !working_dir.is_dir() {
+            return Err(EngineError::hook_error(
+                &hook.command,
+                format!(
+                    "Working directory does not exist: {}",
+                    working_dir.display()
+                ),
+            ));
+        }
+
+        Ok(working_dir)
+    }
+
+    #[cfg(windows)]
+    fn shell_command(command_line: &str) -> Command {
+        let mut command = Command::new("cmd");
+        command.arg("/C").arg(command_line);
+        command
+    }
+
+    #[cfg(not(windows))]
+    fn shell_command(command_line: &str) -> Command {
+        let mut command = Command::new("sh");
+        command.arg("-c").arg(command_line);
+        command
+    }
+
+    pub fn output_directory(&self) -> &Path {
+        &self.output_dir
+    }
+}
+
+/* Kills a running hook's process group when dropped, unless the hook has exited */
+struct ProcessGroupGuard(Option<u32>);
+
+impl ProcessGroupGuard {
+    fn disarm(&mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for ProcessGroupGuard {
+    fn drop(&mut self) {
+        #[cfg(unix)]
+        if let Some(group) = self.0 {
+            // SAFETY: killpg only sends a signal to the group created for this hook
+            unsafe {
+                libc::killpg(group as libc::pid_t, libc::SIGKILL);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+    use tempfile::TempDir;
+
+    fn hook(command: &str, condition: Option<&str>) -> HookCommand {
+        HookCommand {
+            command: command.to_string(),
+            working_dir: None,
+            condition: condition.map(|c| c.to_string()),
+            env: HashMap::new(),
+            timeout: None,
+            on_failure: HookFailurePolicy::Abort,
+            capture_output: false,
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_run_hooks() {
+        let temp_dir = TempDir::new().unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("sub")).unwrap();
+
+        let mut env_hook = hook("echo \"$GREETING\" > greeting.txt", None);
+        env_hook.working_dir = Some("sub".to_string());
+        env_hook
+            .env
+            .insert("GREETING".to_string(), "hello".to_string());
+
+        let hooks = vec![env_hook, hook("touch skipped.txt", Some("never"))];
+
+        let runner = HookRunner::new(temp_dir.path());
+        let report = runner
+            .run_hooks(HookStage::PostGenerate, &hooks, &Context::new(), None)
+            .await
+            .unwrap();
+
+        assert_eq!(report.executed.len(), 1);
+        assert_eq!(report.skipped.len(), 1);
+        let greeting = std::fs::read_to_string(temp_dir.path().join("sub/greeting.txt")).unwrap();
+        assert_eq!(greeting.trim(), "hello");
+        assert!(!temp_dir.path().join("skipped.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_failing_hook_reports_error() {
+        let temp_dir = TempDir::new().unwrap();
+        let runner = HookRunner::new(temp_dir.path());
+
+        let result = runner
+            .run_hooks(
+                HookStage::PostGenerate,
+                &[hook("exit 3", None)],
+                &Context::new(),
+                None,
+            )
+            .await;
+
+        match result {
+            Err(EngineError::HookError { command, reason }) => {
+                assert_eq!(command, "exit 3");
+                assert!(reason.contains("status 3"));
+            }
+            other => panic!("Expected hook error, got {:?}", other),
+        }
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_continue_policy_and_captured_output() {
+        let temp_dir = TempDir::new().unwrap();
+        let runner = HookRunner::new(temp_dir.path());
+
+        let mut failing = hook("echo broken; exit 1", None);
+        failing.on_failure = HookFailurePolicy::Continue;
+        failing.capture_output = true;
+
+        let report = runner
+            .run_hooks(
+                HookStage::PostGenerate,
+                &[failing, hook("touch after.txt", None)],
+                &Context::new(),
+                None,
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(
+            report.failed[0].output.as_deref().map(str::trim),
+            Some("broken")
+        );
+        assert_eq!(report.executed.len(), 1);
+        assert!(temp_dir.path().join("after.txt").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_hook_timeout() {
+        let temp_dir = TempDir::new().unwrap();
+        let runner = HookRunner::new(temp_dir.path());
+
+        // The background job must be stopped along with the shell that started it
+        let mut slow = hook("(sleep 2 && touch late) & sleep 5", None);
+        slow.timeout = Some(1);
+
+        let start = std::time::Instant::now();
+        let error = runner
+            .run_hook(HookStage::PostGenerate, &slow)
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("timed out after 1s"));
+        assert!(start.elapsed() < Duration::from_secs(5));
+
+        tokio::time::sleep(Duration::from_millis(2500)).await;
+        assert!(!temp_dir.path().join("late").exists());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_rollback_policy_removes_generated_files() {
+        use crate::composition::FileSource;
+        use crate::engine::{ProcessedFile, ProcessedTemplate};
+
+        let temp_dir = TempDir::new().unwrap();
+        let output_dir = temp_dir.path().join("project");
+        std::fs::create_dir_all(&output_dir).unwrap();
+        std::fs::write(output_dir.join("keep.txt"), "existing").unwrap();
+
+        let file = |path: &str, content: &str| ProcessedFile {
+            output_path: PathBuf::from(path),
+            content: content.into(),
+            mode: None,
+            executable: false,
+            source: FileSource::BaseTemplate,
+            merge_strategy: None,
+        };
+        let template = ProcessedTemplate {
+            files: vec![
+                file("src/main.rs", "fn main() {}"),
+                file("keep.txt", "generated"),
+            ],
+            hooks: Default::default(),
+        };
+        let generation = FileGenerator::new(&output_dir)
+            .generate_files(template, None)
+            .await
+            .unwrap();
+        assert!(output_dir.join("src/main.rs").exists());
+        assert_eq!(
+            std::fs::read_to_string(output_dir.join("keep.txt")).unwrap(),
+            "generated"
+        );
+
+        let mut failing = hook("exit 1", None);
+        failing.on_failure = HookFailurePolicy::Rollback;
+
+        let runner = HookRunner::new(&output_dir).with_generation(generation);
+        let error = runner
+            .run_hooks(HookStage::PostGenerate, &[failing], &Context::new(), None)
+            .await
+            .unwrap_err();
+
+        assert!(error.to_string().contains("rolled back"));
+        assert!(!output_dir.join("src").exists());
+        assert_eq!(
+            std::fs::read_to_string(output_dir.join("keep.txt")).unwrap(),
+            "existing"
+        );
+    }
+}
@@ -1,16 +1,34 @@
+pub mod answers;
+pub mod condition;
 pub mod config;
 pub mod composition;
+pub mod diff;
 pub mod engine;
 pub mod error;
+pub mod exclude;
+pub mod features;
 pub mod generator;
+pub mod hooks;
+pub mod manifest;
+pub mod update;
+pub mod variables;
 
 pub use config::{
-    TemplateConfig, TemplateVariable, VariableType, ServiceDefinition, 
+    TemplateConfig, TemplateVariable, VariableType, Feature, ServiceDefinition, 
     ServiceCategory, CompositionConfig, FileMergingStrategy, DependencyResolution, ConditionalFile,
     ServiceConfig, ServiceDependencies, EnvironmentVariable, ServiceFile,
-    ServiceCombination, ServicePromptType, ServicePrompt
+    ServiceCombination, ServicePromptType, ServicePrompt, Hooks, HookCommand,
+    HookFailurePolicy, EachRule
 };
 pub use composition::{CompositionEngine, ServiceSelection, ComposedTemplate, ComposedFile, FileSource};
+pub use diff::{diff_directory, FileDiff, FileDiffKind, ProjectDiff};
 pub use engine::{TemplateEngine, Context};
+pub use answers::{load_answers, ProjectAnswers, ServiceAnswer, ANSWERS_FILE};
 pub use error::{EngineError, EngineResult};
-pub use generator::FileGenerator;
\ No newline at end of file
+pub use exclude::{ExcludeRules, IGNORE_FILE};
+pub use features::{FeatureSelection, resolve_features, evaluate_features};
+pub use generator::{ConflictMode, FileGenerator, FileReport, FileStatus, GenerationResult, ReplacedFile};
+pub use hooks::{HookRunner, HookStage, HookReport, HookExecution, HookFailure};
+pub use manifest::{content_hash, Manifest, ManifestEntry, MANIFEST_FILE};
+pub use update::{merge_file, FileUpdate, FileUpdateKind, MergeOutcome, ProjectUpdater, UpdatePlan};
+pub use variables::{variable_applies, variable_order};
@@ -0,0 +1,200 @@
+/*
+Module for the generation manifest, `.anvil/manifest.json`, which records where each
+generated file came from and a hash of the content Anvil wrote. Commands that change
+an existing project use it to tell Anvil's output apart from the user's edits.
+*/
+
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+use crate::composition::FileSource;
+use crate::config::{FileMergingStrategy, ServiceCategory};
+use crate::engine::ProcessedFile;
+use crate::error::{EngineError, EngineResult};
+
+pub const MANIFEST_FILE: &str = ".anvil/manifest.json";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Manifest {
+    pub files: Vec<ManifestEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub path: PathBuf,
+    pub sources: Vec<FileSource>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub merge_strategy: Option<FileMergingStrategy>,
+    pub hash: String,
+}
+
+impl ManifestEntry {
+    pub fn is_unmodified(&self, content: &[u8]) -> bool {
+        self.hash == content_hash(content)
+    }
+
+    pub fn is_from_service(&self, category: &ServiceCategory) -> bool {
+        self.sources
+            .iter()
+            .any(|source| source.is_service(category))
+    }
+}
+
+impl Manifest {
+    /* Builds a manifest for freshly generated files, leaving out Anvil's own metadata */
+    pub fn from_files(files: &[ProcessedFile]) -> Self {
+        let mut manifest = Self::default();
+        for file in files {
+            manifest.record(
+                &file.output_path,
+                &file.source,
+                file.merge_strategy.as_ref(),
+                file.content.as_bytes(),
+            );
+        }
+        manifest
+    }
+
+    pub async fn from_file(path: &Path) -> EngineResult<Self> {
+        let content = tokio::fs::read_to_string(path)
+            .await
+            .map_err(|e| EngineError::file_error(path, e))?;
+        serde_json::from_str(&content).map_err(|e| {
+            EngineError::invalid_config(format!("Invalid manifest {}: {}", path.display(), e))
+        })
+    }
+
+    pub fn to_json(&self) -> EngineResult<String> {
+        serde_json::to_string_pretty(self).map_err(|e| {
+            EngineError::invalid_config(format!("Failed to serialize manifest: {}", e))
+        })
+    }
+
+    pub fn to_file(&self) -> EngineResult<ProcessedFile> {
+        Ok(ProcessedFile {
+            output_path: PathBuf::from(MANIFEST_FILE),
+            content: self.to_json()?.into(),
+            mode: None,
+            executable: false,
+            source: FileSource::BaseTemplate,
+            merge_strategy: None,
+        })
+    }
+
+    pub fn entry(&self, path: &Path) -> Option<&ManifestEntry> {
+        self.files.iter().find(|entry| entry.path == path)
+    }
+
+    /* Records (or replaces) the entry for a file Anvil has just written */
+    pub fn record(
+        &mut self,
+        path: &Path,
+        source: &FileSource,
+        merge_strategy: Option<&FileMergingStrategy>,
+        content: &[u8],
+    ) {
+        if path.starts_with(".anvil") {
+            return;
+        }
+
+        let entry = ManifestEntry {
+            path: path.to_path_buf(),
+            sources: source.contributors().into_iter().cloned().collect(),
+            merge_strategy: merge_strategy.cloned(),
+            hash: content_hash(content),
+        };
+        match self.files.iter_mut().find(|existing| existing.path == path) {
+            Some(existing) => *existing = entry,
+            None => {
+                self.files.push(entry);
+                self.files.sort_by(|a, b| a.path.cmp(&b.path));
+            }
+        }
+    }
+
+    pub fn forget(&mut self, path: &Path) {
+        self.files.retain(|entry| entry.path != path);
+    }
+
+    pub fn service_files<'a>(
+        &'a self,
+        category: &'a ServiceCategory,
+    ) -> impl Iterator<Item = &'a ManifestEntry> {
+        self.files
+            .iter()
+            .filter(move |entry| entry.is_from_service(category))
+    }
+}
+
+pub fn content_hash(content: &[u8]) -> String {
+    format!("sha256:{:x}", Sha256::digest(content))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str, content: &str, source: FileSource) -> ProcessedFile {
+        ProcessedFile {
+            output_path: PathBuf::from(path),
+            content: content.into(),
+            mode: None,
+            executable: false,
+            source,
+            merge_strategy: None,
+        }
+    }
+
+    #[test]
+    fn test_manifest_records_contributors() {
+        let clerk = FileSource::Service {
+            category: ServiceCategory::Auth,
+            provider: "clerk".to_string(),
+        };
+        let merged = FileSource::Merged {
+            sources: vec![FileSource::BaseTemplate, clerk.clone()],
+        };
+        let manifest = Manifest::from_files(&[
+            file("src/middleware.ts", "// clerk", clerk.clone()),
+            file("package.json", "{}", merged),
+            file("README.md", "# app", FileSource::BaseTemplate),
+            file(
+                ".anvil/answers.yaml",
+                "template: app",
+                FileSource::BaseTemplate,
+            ),
+        ]);
+
+        let paths: Vec<_> = manifest.files.iter().map(|e| e.path.clone()).collect();
+        assert_eq!(
+            paths,
+            vec![
+                PathBuf::from("README.md"),
+                PathBuf::from("package.json"),
+                PathBuf::from("src/middleware.ts")
+            ]
+        );
+
+        let package = manifest.entry(Path::new("package.json")).unwrap();
+        assert_eq!(package.sources, vec![FileSource::BaseTemplate, clerk]);
+        assert!(package.is_unmodified(b"{}"));
+        assert!(!package.is_unmodified(b"{\"edited\": true}"));
+
+        let auth_files: Vec<_> = manifest
+            .service_files(&ServiceCategory::Auth)
+            .map(|e| e.path.clone())
+            .collect();
+        assert_eq!(
+            auth_files,
+            vec![
+                PathBuf::from("package.json"),
+                PathBuf::from("src/middleware.ts")
+            ]
+        );
+
+        let json = manifest.to_json().unwrap();
+        assert!(json.contains("\"type\": \"service\""));
+        assert_eq!(serde_json::from_str::<Manifest>(&json).unwrap(), manifest);
+    }
+}
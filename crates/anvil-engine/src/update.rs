@@ -0,0 +1,697 @@
+/*
+Module for bringing an existing project up to date with a newer template version.
+The template is rendered twice in memory, once as the project was generated (base)
+and once as it is now (target), and each file's template changes are three-way
+merged into the project's copy so local edits survive.
+*/
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tokio::fs;
+
+use crate::composition::FileSource;
+use crate::config::{FileMergingStrategy, ServiceCategory};
+use crate::engine::{FileContent, ProcessedFile, ProcessedTemplate};
+use crate::error::{EngineError, EngineResult};
+use crate::generator::FileGenerator;
+use crate::manifest::Manifest;
+
+const CONFLICT_MARKER: &str = "<<<<<<< ours";
+const DEPENDENCY_SECTIONS: [&str; 2] = ["dependencies", "devDependencies"];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FileUpdateKind {
+    /* The template added a file the project does not have yet */
+    Added,
+    /* The template changed a file the project had not modified */
+    Updated,
+    /* Template and project changes to the file merged cleanly */
+    Merged,
+    /* Template and project changed the same lines; conflict markers are written */
+    Conflicted { conflicts: usize },
+    /* The template dropped a file the project had not modified */
+    Removed,
+    /* The template change could not be applied safely and the project copy is left alone */
+    Skipped { reason: String },
+}
+
+#[derive(Debug, Clone)]
+pub struct FileUpdate {
+    pub path: PathBuf,
+    pub kind: FileUpdateKind,
+    pub content: Option<FileContent>,
+    pub mode: Option<u32>,
+    pub executable: bool,
+    pub source: FileSource,
+    pub merge_strategy: Option<FileMergingStrategy>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct UpdatePlan {
+    pub files: Vec<FileUpdate>,
+}
+
+impl UpdatePlan {
+    pub fn is_empty(&self) -> bool {
+        self.files.is_empty()
+    }
+
+    pub fn conflicts(&self) -> impl Iterator<Item = &FileUpdate> {
+        self.files
+            .iter()
+            .filter(|file| matches!(file.kind, FileUpdateKind::Conflicted { .. }))
+    }
+
+    pub fn has_conflicts(&self) -> bool {
+        self.conflicts().next().is_some()
+    }
+
+    /* Brings the manifest in line with the project after the plan has been applied */
+    pub fn update_manifest(&self, manifest: &mut Manifest) {
+        for update in &self.files {
+            match (&update.kind, &update.content) {
+                (FileUpdateKind::Removed, _) => manifest.forget(&update.path),
+                (FileUpdateKind::Skipped { .. }, _) | (_, None) => {}
+                (_, Some(content)) => manifest.record(
+                    &update.path,
+                    &update.source,
+                    update.merge_strategy.as_ref(),
+                    content.as_bytes(),
+                ),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum MergeOutcome {
+    Clean(String),
+    Conflicted { content: String, conflicts: usize },
+}
+
+/*
+Three-way merges one file. `ours` is the project's copy, `theirs` the new template
+output, and `base` what the template produced when the project was generated.
+*/
+pub fn merge_file(base: &str, ours: &str, theirs: &str) -> MergeOutcome {
+    match diffy::merge(base, ours, theirs) {
+        Ok(content) => MergeOutcome::Clean(content),
+        Err(content) => {
+            let conflicts = content
+                .lines()
+                .filter(|line| line.starts_with(CONFLICT_MARKER))
+                .count();
+            MergeOutcome::Conflicted { content, conflicts }
+        }
+    }
+}
+
+/*
+Merges a generated file into an unrelated copy already on disk, for when there is no
+record of what Anvil originally wrote. The lines both versions share stand in for
+the common ancestor, so additions on either side are kept and only lines the two
+versions disagree on become conflicts.
+*/
+pub fn merge_existing(existing: &str, generated: &str) -> MergeOutcome {
+    let mut options = diffy::DiffOptions::new();
+    options.set_context_len(usize::MAX);
+    let patch = options.create_patch(existing, generated);
+
+    let common: String = patch
+        .hunks()
+        .iter()
+        .flat_map(|hunk| hunk.lines())
+        .filter_map(|line| match line {
+            diffy::Line::Context(text) => Some(*text),
+            _ => None,
+        })
+        .collect();
+
+    merge_file(&common, existing, generated)
+}
+
+pub struct ProjectUpdater {
+    project_dir: PathBuf,
+}
+
+impl ProjectUpdater {
+    pub fn new(project_dir: impl Into<PathBuf>) -> Self {
+        Self {
+            project_dir: project_dir.into(),
+        }
+    }
+
+    /*
+    Compares the base and target renders against the project on disk and decides
+    what to do with every file the template touched. Nothing is written.
+    */
+    pub async fn plan(
+        &self,
+        base: &ProcessedTemplate,
+        target: &ProcessedTemplate,
+    ) -> EngineResult<UpdatePlan> {
+        let base_files = index_files(&base.files);
+        let target_files = index_files(&target.files);
+
+        let mut paths: Vec<&PathBuf> = base_files.keys().chain(target_files.keys()).collect();
+        paths.sort();
+        paths.dedup();
+
+        let mut plan = UpdatePlan::default();
+        for path in paths {
+            let base_file = base_files.get(path);
+            let target_file = target_files.get(path);
+            let current = self.read_project_file(path).await?;
+
+            if let Some(update) = plan_file(path, base_file, target_file, current) {
+                plan.files.push(update);
+            }
+        }
+
+        Ok(plan)
+    }
+
+    /* Writes the planned changes, including conflicted files with their markers */
+    pub async fn apply(&self, plan: &UpdatePlan) -> EngineResult<()> {
+        for update in &plan.files {
+            let full_path = self.project_dir.join(&update.path);
+
+            match (&update.kind, &update.content) {
+                (FileUpdateKind::Removed, _) => {
+                    fs::remove_file(&full_path)
+                        .await
+                        .map_err(|e| EngineError::file_error(&full_path, e))?;
+                    self.remove_empty_parents(&full_path).await;
+                }
+                (FileUpdateKind::Skipped { .. }, _) | (_, None) => {}
+                (_, Some(content)) => {
+                    if let Some(parent) = full_path.parent() {
+                        fs::create_dir_all(parent)
+                            .await
+                            .map_err(|e| EngineError::file_error(parent, e))?;
+                    }
+                    FileGenerator::write_content(
+                        &full_path,
+                        content,
+                        update.mode,
+                        update.executable,
+                    )
+                    .await?;
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /*
+    Plans backing a service out of the project. Only files the manifest attributes to
+    the service are touched: unmodified files are deleted or reset to what the
+    remaining sources produce, the service's dependencies are dropped from edited JSON
+    files, and other edited files are reported instead of overwritten.
+    */
+    pub async fn plan_service_removal(
+        &self,
+        manifest: &Manifest,
+        category: &ServiceCategory,
+        service_files: &[ProcessedFile],
+        remaining: &ProcessedTemplate,
+    ) -> EngineResult<UpdatePlan> {
+        let service_files = index_files(service_files);
+        let remaining_files = index_files(&remaining.files);
+
+        let mut plan = UpdatePlan::default();
+        for entry in manifest.service_files(category) {
+            let Some(current) = self.read_project_file(&entry.path).await? else {
+                continue;
+            };
+
+            let other_sources: Vec<FileSource> = entry
+                .sources
+                .iter()
+                .filter(|source| !source.is_service(category))
+                .cloned()
+                .collect();
+            let source = match other_sources.as_slice() {
+                [single] => single.clone(),
+                _ => FileSource::Merged {
+                    sources: other_sources.clone(),
+                },
+            };
+            let remaining_file = remaining_files.get(&entry.path);
+            let update = |kind: FileUpdateKind, content: Option<FileContent>| FileUpdate {
+                path: entry.path.clone(),
+                kind,
+                content,
+                mode: remaining_file.and_then(|file| file.mode),
+                executable: remaining_file.map(|file| file.executable).unwrap_or(false),
+                source: source.clone(),
+                merge_strategy: if other_sources.len() > 1 {
+                    entry.merge_strategy.clone()
+                } else {
+                    None
+                },
+            };
+            let skipped = |reason: &str| {
+                update(
+                    FileUpdateKind::Skipped {
+                        reason: reason.to_string(),
+                    },
+                    None,
+                )
+            };
+
+            if entry.is_unmodified(current.as_bytes()) {
+                match remaining_file {
+                    Some(file) if !other_sources.is_empty() => {
+                        if file.content != current {
+                            plan.files
+                                .push(update(FileUpdateKind::Updated, Some(file.content.clone())));
+                        }
+                    }
+                    _ => plan.files.push(update(FileUpdateKind::Removed, None)),
+                }
+            } else if other_sources.is_empty() {
+                plan.files.push(skipped("modified since it was generated"));
+            } else if entry.path.extension().and_then(|ext| ext.to_str()) == Some("json") {
+                let service_content = service_files
+                    .get(&entry.path)
+                    .and_then(|f| f.content.as_text());
+                let remaining_content = remaining_file.and_then(|f| f.content.as_text());
+                let stripped = current
+                    .as_text()
+                    .and_then(|text| strip_dependencies(text, service_content, remaining_content));
+                match stripped {
+                    Some(content) if current.as_text() != Some(content.as_str()) => {
+                        plan.files
+                            .push(update(FileUpdateKind::Merged, Some(content.into())));
+                    }
+                    Some(_) => {}
+                    None => plan.files.push(skipped("modified and not valid JSON")),
+                }
+            } else {
+                plan.files.push(skipped(
+                    "merged with other sources and modified since it was generated",
+                ));
+            }
+        }
+
+        Ok(plan)
+    }
+
+    /* Removes directories left empty by a deleted file, up to the project root */
+    async fn remove_empty_parents(&self, path: &Path) {
+        for dir in path.ancestors().skip(1) {
+            if dir == self.project_dir || fs::remove_dir(dir).await.is_err() {
+                break;
+            }
+        }
+    }
+
+    async fn read_project_file(&self, path: &Path) -> EngineResult<Option<FileContent>> {
+        let full_path = self.project_dir.join(path);
+        match FileContent::load(&full_path).await {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(EngineError::file_error(full_path, e)),
+        }
+    }
+}
+
+fn index_files(files: &[ProcessedFile]) -> BTreeMap<PathBuf, &ProcessedFile> {
+    files
+        .iter()
+        .map(|file| (file.output_path.clone(), file))
+        .collect()
+}
+
+fn plan_file(
+    path: &Path,
+    base: Option<&&ProcessedFile>,
+    target: Option<&&ProcessedFile>,
+    current: Option<FileContent>,
+) -> Option<FileUpdate> {
+    let update = |kind: FileUpdateKind, content: Option<FileContent>| FileUpdate {
+        path: path.to_path_buf(),
+        kind,
+        content,
+        mode: target.and_then(|file| file.mode),
+        executable: target.map(|file| file.executable).unwrap_or(false),
+        source: target
+            .map(|file| file.source.clone())
+            .unwrap_or(FileSource::BaseTemplate),
+        merge_strategy: target.and_then(|file| file.merge_strategy.clone()),
+    };
+    let skipped = |reason: &str| {
+        update(
+            FileUpdateKind::Skipped {
+                reason: reason.to_string(),
+            },
+            None,
+        )
+    };
+
+    match (base, target, current) {
+        (Some(base), Some(target), _) if base.content == target.content => None,
+        (Some(_), Some(_), None) => Some(skipped("deleted in the project")),
+        (_, Some(target), Some(current)) if current == target.content => None,
+        (Some(base), Some(target), Some(current)) if current == base.content => Some(update(
+            FileUpdateKind::Updated,
+            Some(target.content.clone()),
+        )),
+        (Some(base), Some(target), Some(current)) => {
+            match merge_text(&base.content, &current, &target.content) {
+                Some(outcome) => Some(merged(update, outcome)),
+                None => Some(skipped("binary file modified in the project")),
+            }
+        }
+        (None, Some(target), None) => {
+            Some(update(FileUpdateKind::Added, Some(target.content.clone())))
+        }
+        // The project already has its own file where the template now adds one
+        (None, Some(target), Some(current)) => {
+            match merge_text(&FileContent::from(""), &current, &target.content) {
+                Some(outcome) => Some(merged(update, outcome)),
+                None => Some(skipped("binary file already exists in the project")),
+            }
+        }
+        (Some(base), None, Some(current)) if current == base.content => {
+            Some(update(FileUpdateKind::Removed, None))
+        }
+        (Some(_), None, Some(_)) => Some(skipped(
+            "removed from the template but modified in the project",
+        )),
+        (_, None, None) | (None, None, _) => None,
+    }
+}
+
+/*
+Removes the dependencies a service's JSON file declares from the project's copy,
+keeping any that the remaining sources still declare. Returns None when the project
+copy is not valid JSON.
+*/
+fn strip_dependencies(
+    current: &str,
+    service: Option<&str>,
+    remaining: Option<&str>,
+) -> Option<String> {
+    let mut project: serde_json::Value = serde_json::from_str(current).ok()?;
+    let parse = |content: Option<&str>| -> serde_json::Value {
+        content
+            .and_then(|content| serde_json::from_str(content).ok())
+            .unwrap_or_default()
+    };
+    let service = parse(service);
+    let remaining = parse(remaining);
+
+    for section in DEPENDENCY_SECTIONS {
+        let Some(service_deps) = service.get(section).and_then(|deps| deps.as_object()) else {
+            continue;
+        };
+        let Some(project_deps) = project
+            .get_mut(section)
+            .and_then(|deps| deps.as_object_mut())
+        else {
+            continue;
+        };
+        for name in service_deps.keys() {
+            let still_needed = remaining
+                .get(section)
+                .and_then(|deps| deps.get(name))
+                .is_some();
+            if !still_needed {
+                project_deps.remove(name);
+            }
+        }
+    }
+
+    serde_json::to_string_pretty(&project).ok()
+}
+
+/* Three-way merges text content; binary files cannot be merged and give None */
+fn merge_text(base: &FileContent, ours: &FileContent, theirs: &FileContent) -> Option<MergeOutcome> {
+    Some(merge_file(base.as_text()?, ours.as_text()?, theirs.as_text()?))
+}
+
+fn merged(
+    update: impl Fn(FileUpdateKind, Option<FileContent>) -> FileUpdate,
+    outcome: MergeOutcome,
+) -> FileUpdate {
+    match outcome {
+        MergeOutcome::Clean(content) => update(FileUpdateKind::Merged, Some(content.into())),
+        MergeOutcome::Conflicted { content, conflicts } => {
+            update(FileUpdateKind::Conflicted { conflicts }, Some(content.into()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Hooks;
+    use tempfile::TempDir;
+
+    fn template(files: &[(&str, &str)]) -> ProcessedTemplate {
+        ProcessedTemplate {
+            files: files
+                .iter()
+                .map(|(path, content)| ProcessedFile {
+                    output_path: PathBuf::from(path),
+                    content: (*content).into(),
+                    mode: None,
+                    executable: false,
+                    source: FileSource::BaseTemplate,
+                    merge_strategy: None,
+                })
+                .collect(),
+            hooks: Hooks::default(),
+        }
+    }
+
+    #[test]
+    fn test_merge_file() {
+        let base = "one\ntwo\nthree\n";
+
+        let outcome = merge_file(base, "one\ntwo\nthree\nlocal\n", "ONE\ntwo\nthree\n");
+        assert_eq!(
+            outcome,
+            MergeOutcome::Clean("ONE\ntwo\nthree\nlocal\n".to_string())
+        );
+
+        let outcome = merge_file(base, "one\nmine\nthree\n", "one\ntheirs\nthree\n");
+        match outcome {
+            MergeOutcome::Conflicted { content, conflicts } => {
+                assert_eq!(conflicts, 1);
+                assert!(content.contains("<<<<<<< ours\nmine\n"));
+                assert!(content.contains(">>>>>>> theirs\n"));
+            }
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_merge_existing() {
+        let outcome = merge_existing("node_modules\n.env.local\n", "dist\nnode_modules\n");
+        assert_eq!(
+            outcome,
+            MergeOutcome::Clean("dist\nnode_modules\n.env.local\n".to_string())
+        );
+
+        let outcome = merge_existing("port: 3000\nname: app\n", "port: 8080\nname: app\n");
+        match outcome {
+            MergeOutcome::Conflicted { content, conflicts } => {
+                assert_eq!(conflicts, 1);
+                assert!(content.contains("<<<<<<< ours\nport: 3000\n"));
+                assert!(content.ends_with(">>>>>>> theirs\nname: app\n"));
+            }
+            other => panic!("expected a conflict, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_plan_and_apply_update() {
+        let project = TempDir::new().unwrap();
+        let root = project.path();
+        fs::write(root.join("untouched.txt"), "v1\n").await.unwrap();
+        fs::write(root.join("edited.txt"), "a\nb\nc\nlocal\n")
+            .await
+            .unwrap();
+        fs::write(root.join("clash.txt"), "mine\n").await.unwrap();
+        fs::write(root.join("old.txt"), "old\n").await.unwrap();
+        fs::write(root.join("kept.txt"), "kept but edited\n")
+            .await
+            .unwrap();
+
+        let base = template(&[
+            ("untouched.txt", "v1\n"),
+            ("edited.txt", "a\nb\nc\n"),
+            ("clash.txt", "base\n"),
+            ("old.txt", "old\n"),
+            ("kept.txt", "kept\n"),
+            ("deleted.txt", "x\n"),
+            ("same.txt", "same\n"),
+        ]);
+        let target = template(&[
+            ("untouched.txt", "v2\n"),
+            ("edited.txt", "A\nb\nc\n"),
+            ("clash.txt", "theirs\n"),
+            ("deleted.txt", "y\n"),
+            ("same.txt", "same\n"),
+            ("new/added.txt", "new\n"),
+        ]);
+
+        let updater = ProjectUpdater::new(root);
+        let plan = updater.plan(&base, &target).await.unwrap();
+        let kinds: Vec<(String, FileUpdateKind)> = plan
+            .files
+            .iter()
+            .map(|file| (file.path.display().to_string(), file.kind.clone()))
+            .collect();
+
+        let skipped = |reason: &str| FileUpdateKind::Skipped {
+            reason: reason.to_string(),
+        };
+        assert_eq!(
+            kinds,
+            vec![
+                (
+                    "clash.txt".to_string(),
+                    FileUpdateKind::Conflicted { conflicts: 1 }
+                ),
+                ("deleted.txt".to_string(), skipped("deleted in the project")),
+                ("edited.txt".to_string(), FileUpdateKind::Merged),
+                (
+                    "kept.txt".to_string(),
+                    skipped("removed from the template but modified in the project")
+                ),
+                ("new/added.txt".to_string(), FileUpdateKind::Added),
+                ("old.txt".to_string(), FileUpdateKind::Removed),
+                ("untouched.txt".to_string(), FileUpdateKind::Updated),
+            ]
+        );
+        assert!(plan.has_conflicts());
+
+        updater.apply(&plan).await.unwrap();
+        let read = |path: &str| std::fs::read_to_string(root.join(path)).unwrap();
+        assert_eq!(read("untouched.txt"), "v2\n");
+        assert_eq!(read("edited.txt"), "A\nb\nc\nlocal\n");
+        assert_eq!(read("new/added.txt"), "new\n");
+        assert_eq!(read("kept.txt"), "kept but edited\n");
+        assert!(read("clash.txt").starts_with("<<<<<<< ours\nmine\n"));
+        assert!(!root.join("old.txt").exists());
+        assert!(!root.join("deleted.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn test_plan_service_removal() {
+        let project = TempDir::new().unwrap();
+        let root = project.path();
+        let clerk = FileSource::Service {
+            category: ServiceCategory::Auth,
+            provider: "clerk".to_string(),
+        };
+        let merged = FileSource::Merged {
+            sources: vec![FileSource::BaseTemplate, clerk.clone()],
+        };
+        let file = |path: &str, content: &str, source: &FileSource| ProcessedFile {
+            output_path: PathBuf::from(path),
+            content: content.into(),
+            mode: None,
+            executable: false,
+            source: source.clone(),
+            merge_strategy: None,
+        };
+
+        let generated_package =
+            r#"{"dependencies": {"@clerk/nextjs": "^4.0.0", "next": "14.0.0"}}"#;
+        let generated = vec![
+            file("middleware.ts", "// clerk", &clerk),
+            file("lib/auth.ts", "// auth", &clerk),
+            file("README.md", "# app\n\nUses Clerk\n", &merged),
+            file("package.json", generated_package, &merged),
+        ];
+        let manifest = Manifest::from_files(&generated);
+
+        fs::create_dir_all(root.join("lib")).await.unwrap();
+        fs::write(root.join("middleware.ts"), "// clerk")
+            .await
+            .unwrap();
+        fs::write(root.join("lib/auth.ts"), "// auth, edited")
+            .await
+            .unwrap();
+        fs::write(root.join("README.md"), "# app\n\nUses Clerk\n")
+            .await
+            .unwrap();
+        fs::write(
+            root.join("package.json"),
+            r#"{"dependencies": {"@clerk/nextjs": "^4.0.0", "next": "14.0.0", "zod": "3"}}"#,
+        )
+        .await
+        .unwrap();
+
+        let service_files = vec![file(
+            "package.json",
+            r#"{"dependencies": {"@clerk/nextjs": "^4.0.0"}}"#,
+            &clerk,
+        )];
+        let remaining = ProcessedTemplate {
+            files: vec![
+                file("README.md", "# app\n", &FileSource::BaseTemplate),
+                file(
+                    "package.json",
+                    r#"{"dependencies": {"next": "14.0.0"}}"#,
+                    &FileSource::BaseTemplate,
+                ),
+            ],
+            hooks: Hooks::default(),
+        };
+
+        let updater = ProjectUpdater::new(root);
+        let plan = updater
+            .plan_service_removal(
+                &manifest,
+                &ServiceCategory::Auth,
+                &service_files,
+                &remaining,
+            )
+            .await
+            .unwrap();
+        let kinds: Vec<(String, FileUpdateKind)> = plan
+            .files
+            .iter()
+            .map(|file| (file.path.display().to_string(), file.kind.clone()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                ("README.md".to_string(), FileUpdateKind::Updated),
+                (
+                    "lib/auth.ts".to_string(),
+                    FileUpdateKind::Skipped {
+                        reason: "modified since it was generated".to_string()
+                    }
+                ),
+                ("middleware.ts".to_string(), FileUpdateKind::Removed),
+                ("package.json".to_string(), FileUpdateKind::Merged),
+            ]
+        );
+
+        updater.apply(&plan).await.unwrap();
+        let package: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(root.join("package.json")).unwrap())
+                .unwrap();
+        assert_eq!(
+            package["dependencies"],
+            serde_json::json!({"next": "14.0.0", "zod": "3"})
+        );
+        assert!(!root.join("middleware.ts").exists());
+        assert!(root.join("lib/auth.ts").exists());
+        assert!(root.exists());
+
+        let mut manifest = manifest;
+        plan.update_manifest(&mut manifest);
+        assert!(manifest.entry(Path::new("middleware.ts")).is_none());
+        let package = manifest.entry(Path::new("package.json")).unwrap();
+        assert_eq!(package.sources, vec![FileSource::BaseTemplate]);
+    }
+}
@@ -0,0 +1,186 @@
+/*
+Module for deciding which template variables are asked for, and in what order.
+A variable's `when` condition is checked against the answers collected before it,
+so variables are visited with everything a condition mentions first, keeping
+declaration order otherwise.
+*/
+
+use crate::condition::Condition;
+use crate::config::TemplateVariable;
+use crate::engine::Context;
+use crate::error::{EngineError, EngineResult};
+
+/*
+Returns the variables in the order to collect them. Conditions may only mention
+other variables, and may not depend on each other in a cycle.
+*/
+pub fn variable_order(variables: &[TemplateVariable]) -> EngineResult<Vec<&TemplateVariable>> {
+    let dependencies = variables
+        .iter()
+        .map(|variable| variable_dependencies(variable, variables))
+        .collect::<EngineResult<Vec<_>>>()?;
+
+    let mut ordered: Vec<&TemplateVariable> = Vec::with_capacity(variables.len());
+    let mut placed = vec![false; variables.len()];
+
+    while ordered.len() < variables.len() {
+        let next = (0..variables.len()).find(|&index| {
+            !placed[index]
+                && dependencies[index]
+                    .iter()
+                    .all(|name| ordered.iter().any(|variable| variable.name == *name))
+        });
+
+        let Some(index) = next else {
+            let waiting: Vec<&str> = variables
+                .iter()
+                .zip(&placed)
+                .filter(|(_, placed)| !**placed)
+                .map(|(variable, _)| variable.name.as_str())
+                .collect();
+            return Err(EngineError::invalid_config(format!(
+                "Variable conditions depend on each other in a cycle: {}",
+                waiting.join(", ")
+            )));
+        };
+
+        placed[index] = true;
+        ordered.push(&variables[index]);
+    }
+
+    Ok(ordered)
+}
+
+/*
+Whether `variable` applies given the answers in `context`. Declared variables that
+have no answer were skipped by their own conditions, and read as null.
+*/
+pub fn variable_applies(
+    variable: &TemplateVariable,
+    variables: &[TemplateVariable],
+    context: &Context,
+) -> EngineResult<bool> {
+    let Some(when) = &variable.when else {
+        return Ok(true);
+    };
+
+    let mut answers = context.clone();
+    for name in variable_dependencies(variable, variables)? {
+        if answers.get_variable(name).is_none() {
+            answers.add_variable(name.to_string(), serde_yaml::Value::Null);
+        }
+    }
+
+    Condition::parse(when)?.evaluate(&answers)
+}
+
+fn variable_dependencies<'a>(
+    variable: &TemplateVariable,
+    variables: &'a [TemplateVariable],
+) -> EngineResult<Vec<&'a str>> {
+    let Some(when) = &variable.when else {
+        return Ok(Vec::new());
+    };
+
+    let condition = Condition::parse(when)?;
+    let mut dependencies: Vec<&str> = Vec::new();
+    for name in condition.identifiers() {
+        let Some(dependency) = variables.iter().find(|other| other.name == name) else {
+            return Err(EngineError::invalid_config(format!(
+                "Variable '{}': condition '{}' mentions '{}', which is not a template variable",
+                variable.name, when, name
+            )));
+        };
+        if dependency.name == variable.name {
+            return Err(EngineError::invalid_config(format!(
+                "Variable '{}': condition '{}' cannot depend on the variable itself",
+                variable.name, when
+            )));
+        }
+        if !dependencies.contains(&dependency.name.as_str()) {
+            dependencies.push(&dependency.name);
+        }
+    }
+
+    Ok(dependencies)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::VariableType;
+
+    fn variable(name: &str, when: Option<&str>) -> TemplateVariable {
+        TemplateVariable {
+            name: name.to_string(),
+            var_type: VariableType::String {
+                min_length: 0,
+                max_length: None,
+                pattern: None,
+            },
+            prompt: format!("{}?", name),
+            description: None,
+            default: None,
+            required: true,
+            when: when.map(|c| c.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_variable_order_and_conditions() {
+        let variables = vec![
+            variable("project_name", None),
+            variable("database_url_env_name", Some("database_provider != 'none'")),
+            variable("pool_size", Some("database_url_env_name && use_pooling")),
+            variable("database_provider", None),
+            variable("use_pooling", Some("database_provider == 'postgres'")),
+        ];
+
+        let order: Vec<&str> = variable_order(&variables)
+            .unwrap()
+            .iter()
+            .map(|variable| variable.name.as_str())
+            .collect();
+        assert_eq!(
+            order,
+            vec![
+                "project_name",
+                "database_provider",
+                "database_url_env_name",
+                "use_pooling",
+                "pool_size",
+            ]
+        );
+
+        let applies = |name: &str, context: &Context| {
+            let variable = variables.iter().find(|v| v.name == name).unwrap();
+            variable_applies(variable, &variables, context).unwrap()
+        };
+
+        let none = Context::builder()
+            .variable("project_name", "app")
+            .variable("database_provider", "none")
+            .build();
+        assert!(applies("project_name", &none));
+        assert!(!applies("database_url_env_name", &none));
+        assert!(!applies("use_pooling", &none));
+        // Skipped variables read as null rather than failing the condition
+        assert!(!applies("pool_size", &none));
+
+        let postgres = Context::builder()
+            .variable("database_provider", "postgres")
+            .variable("database_url_env_name", "DATABASE_URL")
+            .variable("use_pooling", true)
+            .build();
+        assert!(applies("database_url_env_name", &postgres));
+        assert!(applies("pool_size", &postgres));
+
+        let cycle = vec![
+            variable("a", Some("b == 'x'")),
+            variable("b", Some("a == 'y'")),
+        ];
+        assert!(variable_order(&cycle).is_err());
+        assert!(variable_order(&[variable("a", Some("a"))]).is_err());
+        assert!(variable_order(&[variable("a", Some("typo == 'x'"))]).is_err());
+    }
+}
@@ -41,8 +41,7 @@ impl BuildVerificationTests {
             timeout_seconds: 300,
         };
         let mut result1 = suite.generate_project(&config1).await?;
-        if result1.success && result1.project_name.is_some() {
-            let project_name = result1.project_name.as_ref().unwrap();
+        if let (true, Some(project_name)) = (result1.success, result1.project_name.as_ref()) {
             result1.build_success = Some(suite.verify_build(project_name, "npm").await?);
         }
         results.push(result1);
@@ -66,8 +65,7 @@ impl BuildVerificationTests {
             timeout_seconds: 300,
         };
         let mut result2 = suite.generate_project(&config2).await?;
-        if result2.success && result2.project_name.is_some() {
-            let project_name = result2.project_name.as_ref().unwrap();
+        if let (true, Some(project_name)) = (result2.success, result2.project_name.as_ref()) {
             result2.build_success = Some(suite.verify_build(project_name, "pnpm").await?);
         }
         results.push(result2);
@@ -87,8 +85,7 @@ impl BuildVerificationTests {
             timeout_seconds: 300,
         };
         let mut result3 = suite.generate_project(&config3).await?;
-        if result3.success && result3.project_name.is_some() {
-            let project_name = result3.project_name.as_ref().unwrap();
+        if let (true, Some(project_name)) = (result3.success, result3.project_name.as_ref()) {
             result3.build_success = Some(suite.verify_build(project_name, "yarn").await?);
         }
         results.push(result3);
@@ -108,8 +105,7 @@ impl BuildVerificationTests {
             timeout_seconds: 300,
         };
         let mut result4 = suite.generate_project(&config4).await?;
-        if result4.success && result4.project_name.is_some() {
-            let project_name = result4.project_name.as_ref().unwrap();
+        if let (true, Some(project_name)) = (result4.success, result4.project_name.as_ref()) {
             result4.build_success = Some(suite.verify_build(project_name, "bun").await?);
         }
         results.push(result4);
@@ -136,8 +132,7 @@ impl BuildVerificationTests {
             timeout_seconds: 180,
         };
         let mut result1 = suite.generate_project(&config1).await?;
-        if result1.success && result1.project_name.is_some() {
-            let project_name = result1.project_name.as_ref().unwrap();
+        if let (true, Some(project_name)) = (result1.success, result1.project_name.as_ref()) {
             result1.build_success = Some(Self::verify_rust_build(&suite, project_name).await?);
         }
         results.push(result1);
@@ -156,8 +151,7 @@ impl BuildVerificationTests {
             timeout_seconds: 300, // Web API has more dependencies
         };
         let mut result2 = suite.generate_project(&config2).await?;
-        if result2.success && result2.project_name.is_some() {
-            let project_name = result2.project_name.as_ref().unwrap();
+        if let (true, Some(project_name)) = (result2.success, result2.project_name.as_ref()) {
             result2.build_success = Some(Self::verify_rust_build(&suite, project_name).await?);
         }
         results.push(result2);
@@ -179,7 +173,7 @@ impl BuildVerificationTests {
             ])),
             expected_files: vec![
                 "go.mod".to_string(),
-                "main.go".to_string(),
+                "cmd/{{ project_name }}/main.go".to_string(),
                 "cmd/root.go".to_string(),
             ],
             expected_dependencies: vec![],
@@ -188,8 +182,7 @@ impl BuildVerificationTests {
             timeout_seconds: 180,
         };
         let mut result = suite.generate_project(&config).await?;
-        if result.success && result.project_name.is_some() {
-            let project_name = result.project_name.as_ref().unwrap();
+        if let (true, Some(project_name)) = (result.success, result.project_name.as_ref()) {
             result.build_success = Some(Self::verify_go_build(&suite, project_name).await?);
         }
         results.push(result);
@@ -223,8 +216,7 @@ impl BuildVerificationTests {
             };
 
             let mut result = suite.generate_project(&config).await?;
-            if result.success && result.project_name.is_some() {
-                let project_name = result.project_name.as_ref().unwrap();
+            if let (true, Some(project_name)) = (result.success, result.project_name.as_ref()) {
                 // First ensure it builds
                 result.build_success = Some(suite.verify_build(project_name, pm).await?);
 
@@ -266,8 +258,7 @@ impl BuildVerificationTests {
         };
 
         let mut result = suite.generate_project(&config).await?;
-        if result.success && result.project_name.is_some() {
-            let project_name = result.project_name.as_ref().unwrap();
+        if let (true, Some(project_name)) = (result.success, result.project_name.as_ref()) {
             result.build_success = Some(Self::verify_typescript_check(&suite, project_name).await?);
         }
         results.push(result);
@@ -296,8 +287,7 @@ impl BuildVerificationTests {
         };
 
         let mut result = suite.generate_project(&config).await?;
-        if result.success && result.project_name.is_some() {
-            let project_name = result.project_name.as_ref().unwrap();
+        if let (true, Some(project_name)) = (result.success, result.project_name.as_ref()) {
             result.build_success = Some(Self::verify_linting(&suite, project_name).await?);
         }
         results.push(result);
@@ -341,8 +331,7 @@ impl BuildVerificationTests {
         };
 
         let mut result = suite.generate_project(&config).await?;
-        if result.success && result.project_name.is_some() {
-            let project_name = result.project_name.as_ref().unwrap();
+        if let (true, Some(project_name)) = (result.success, result.project_name.as_ref()) {
             // Test build
             result.build_success = Some(suite.verify_build(project_name, "npm").await?);
 
@@ -408,7 +397,7 @@ impl BuildVerificationTests {
             Duration::from_secs(180),
             Command::new("go")
                 .arg("build")
-                .arg(".")
+                .arg("./...")
                 .current_dir(&project_dir)
                 .output(),
         )
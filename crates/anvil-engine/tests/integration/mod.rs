@@ -152,6 +152,7 @@ impl IntegrationTestSuite {
             .arg("--output")
             .arg(self.temp_dir.path())
             .arg("--no-input")
+            .arg("--no-hooks")
             .arg("--force")
             .current_dir(&self.workspace_dir);
 
@@ -214,10 +215,11 @@ impl IntegrationTestSuite {
         // Count created files
         let files_created = self.count_files_recursive(&project_dir)?;
 
-        // Verify expected files exist
+        // Verify expected files exist, naming them the way template paths do
         let mut warnings = Vec::new();
         for expected_file in &config.expected_files {
-            let file_path = project_dir.join(expected_file);
+            let expected_file = expected_file.replace("{{ project_name }}", &project_name);
+            let file_path = project_dir.join(&expected_file);
             if !file_path.exists() {
                 warnings.push(format!("Expected file not found: {}", expected_file));
             }
@@ -150,7 +150,7 @@ impl TemplateGenerationTests {
             variables: HashMap::new(),
             expected_files: vec![
                 "go.mod".to_string(),
-                "main.go".to_string(),
+                "cmd/{{ project_name }}/main.go".to_string(),
                 "cmd/root.go".to_string(),
             ],
             expected_dependencies: vec![],
@@ -294,6 +294,7 @@ async fn smoke_test_basic_generation() {
        .arg("--template")
        .arg("rust-hello-world")
        .arg("--no-input")
+       .arg("--no-hooks")
        .current_dir(&suite.workspace_dir);
     
     let output = cmd.output().await.expect("Failed to execute anvil command");
@@ -51,7 +51,8 @@ async fn benchmark_generation_performance() {
             .arg("--template")
             .arg(template)
             .arg("--no-input")
-            .current_dir(&workspace_dir)
+            .arg("--no-hooks")
+            .current_dir(workspace_dir)
             .output()
             .expect("Failed to execute anvil command");
         
@@ -135,6 +136,7 @@ async fn benchmark_concurrent_generation() {
                 .arg("--template")
                 .arg("rust-hello-world")
                 .arg("--no-input")
+                .arg("--no-hooks")
                 .current_dir(&workspace)
                 .output()
                 .expect("Failed to execute anvil command");
@@ -218,7 +220,8 @@ fn benchmark_memory_usage() {
         .arg("--template")
         .arg("rust-hello-world")
         .arg("--no-input")
-        .current_dir(&workspace_dir)
+        .arg("--no-hooks")
+        .current_dir(workspace_dir)
         .output()
         .expect("Failed to execute anvil command");
     
@@ -0,0 +1,240 @@
+/*!
+ * Project Command Tests
+ *
+ * Runs anvil add, remove, update and diff against projects generated from small
+ * templates written to a temporary directory
+ */
+
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+use tempfile::TempDir;
+
+/* Finds the anvil binary the same way the other integration tests do */
+fn anvil_binary() -> PathBuf {
+    let current_dir = std::env::current_dir().expect("Failed to get current dir");
+    let workspace_dir = current_dir
+        .parent()
+        .expect("Failed to get parent")
+        .parent()
+        .expect("Failed to get grandparent");
+
+    let binary_name = if cfg!(target_os = "windows") {
+        "anvil.exe"
+    } else {
+        "anvil"
+    };
+
+    // Try release binary first, then debug binary
+    if workspace_dir
+        .join("target/release")
+        .join(binary_name)
+        .exists()
+    {
+        workspace_dir.join("target/release").join(binary_name)
+    } else {
+        workspace_dir.join("target/debug").join(binary_name)
+    }
+}
+
+/* Runs anvil from `dir`, which is where it looks for a `templates` directory */
+fn anvil(dir: &Path, args: &[&str]) -> Output {
+    let output = Command::new(anvil_binary())
+        .args(args)
+        .current_dir(dir)
+        .output()
+        .expect("Failed to execute anvil command");
+
+    println!(
+        "Command output: {}",
+        String::from_utf8_lossy(&output.stdout)
+    );
+    if !output.stderr.is_empty() {
+        println!(
+            "Command stderr: {}",
+            String::from_utf8_lossy(&output.stderr)
+        );
+    }
+    output
+}
+
+fn write(path: &Path, content: &str) {
+    std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+    std::fs::write(path, content).unwrap();
+}
+
+fn read(path: &Path) -> String {
+    std::fs::read_to_string(path).unwrap()
+}
+
+/*
+Writes an `app` template at `version` under `root/templates`, with an optional clerk
+auth service. Version 1.1.0 changes the last line of src/main.txt and adds NEW.md.
+*/
+fn write_templates(root: &Path, version: &str) {
+    let app = root.join("templates/app");
+    write(
+        &app.join("anvil.yaml"),
+        &format!(
+            r#"name: "app"
+description: "Test app"
+version: "{}"
+services:
+  - name: "auth"
+    category: "auth"
+    prompt: "Choose auth provider"
+    options: ["clerk"]
+    required: false
+"#,
+            version
+        ),
+    );
+    write(&app.join("README.md.tera"), "# {{ project_name }}\n");
+    if version == "1.0.0" {
+        write(&app.join("src/main.txt"), "one\ntwo\nthree\n");
+    } else {
+        write(&app.join("src/main.txt"), "one\ntwo\nTHREE\n");
+        write(&app.join("NEW.md"), "new in 1.1.0\n");
+    }
+
+    let clerk = root.join("templates/shared/auth/clerk");
+    write(
+        &clerk.join("anvil.yaml"),
+        r#"name: "clerk"
+description: "Clerk auth"
+version: "1.0.0"
+category: "auth"
+"#,
+    );
+    write(&clerk.join("lib/auth.ts"), "// clerk\n");
+}
+
+/* Generates `demo` from the 1.0.0 templates and returns its directory */
+fn create_project(root: &Path) -> PathBuf {
+    write_templates(root, "1.0.0");
+    let output = anvil(
+        root,
+        &[
+            "create",
+            "demo",
+            "--template",
+            "app",
+            "--output",
+            "out",
+            "--no-input",
+            "--no-hooks",
+        ],
+    );
+    assert!(output.status.success(), "anvil create failed");
+    root.join("out/demo")
+}
+
+#[test]
+fn test_add_and_remove_service() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let root = temp_dir.path();
+    let project_dir = create_project(root);
+    let project = project_dir.to_str().unwrap();
+    assert!(!project_dir.join("lib/auth.ts").exists());
+
+    let output = anvil(
+        root,
+        &[
+            "add",
+            "auth",
+            "clerk",
+            "--path",
+            project,
+            "--no-input",
+            "--no-hooks",
+        ],
+    );
+    assert!(output.status.success(), "anvil add failed");
+    assert_eq!(read(&project_dir.join("lib/auth.ts")), "// clerk\n");
+    assert!(read(&project_dir.join(".anvil/answers.yaml")).contains("provider: clerk"));
+
+    // The project now renders the same as a fresh generation with the service
+    let output = anvil(root, &["diff", project, "--no-input", "--exit-code"]);
+    assert!(
+        output.status.success(),
+        "project should match its template after add"
+    );
+
+    let output = anvil(root, &["remove", "auth", "--path", project, "--no-input"]);
+    assert!(output.status.success(), "anvil remove failed");
+    assert!(!project_dir.join("lib/auth.ts").exists());
+    assert!(!read(&project_dir.join(".anvil/answers.yaml")).contains("clerk"));
+    assert_eq!(read(&project_dir.join("README.md")), "# demo\n");
+
+    let output = anvil(root, &["diff", project, "--no-input", "--exit-code"]);
+    assert!(
+        output.status.success(),
+        "project should match its template after remove"
+    );
+}
+
+#[test]
+fn test_update_merges_template_changes() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let root = temp_dir.path();
+    let project_dir = create_project(root);
+    let project = project_dir.to_str().unwrap();
+
+    // A local edit to a line the new template version leaves alone
+    write(&project_dir.join("src/main.txt"), "ONE\ntwo\nthree\n");
+
+    let target = root.join("v1.1");
+    write_templates(&target, "1.1.0");
+    let base_templates = root.join("templates");
+    let output = anvil(
+        &target,
+        &[
+            "update",
+            project,
+            "--from",
+            base_templates.to_str().unwrap(),
+            "--no-input",
+        ],
+    );
+    assert!(output.status.success(), "anvil update failed");
+
+    assert_eq!(read(&project_dir.join("src/main.txt")), "ONE\ntwo\nTHREE\n");
+    assert_eq!(read(&project_dir.join("NEW.md")), "new in 1.1.0\n");
+    assert!(read(&project_dir.join(".anvil/answers.yaml")).contains("template_version: 1.1.0"));
+}
+
+#[test]
+fn test_diff_reports_local_changes() {
+    let temp_dir = TempDir::new().expect("Failed to create temp dir");
+    let root = temp_dir.path();
+    let project_dir = create_project(root);
+    let project = project_dir.to_str().unwrap();
+
+    let output = anvil(root, &["diff", project, "--no-input", "--exit-code"]);
+    assert!(
+        output.status.success(),
+        "a fresh project should match its template"
+    );
+
+    write(&project_dir.join("README.md"), "# demo\n\nLocal notes\n");
+    std::fs::remove_file(project_dir.join("src/main.txt")).unwrap();
+    write(&project_dir.join("notes.txt"), "not from the template\n");
+
+    let output = anvil(root, &["diff", project, "--no-input", "--exit-code"]);
+    assert!(
+        !output.status.success(),
+        "diff --exit-code should fail on changes"
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    // Patches go from the copy on disk to what the template renders
+    assert!(stdout.contains("-Local notes"));
+    assert!(stdout.contains("1 new, 1 changed, 1 not produced by the template"));
+
+    let output = anvil(root, &["diff", project, "--no-input", "--name-only"]);
+    assert!(
+        output.status.success(),
+        "diff without --exit-code should succeed"
+    );
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("README.md") && stdout.contains("src/main.txt"));
+    assert!(!stdout.contains("Local notes"));
+}
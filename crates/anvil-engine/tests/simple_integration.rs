@@ -45,6 +45,7 @@ async fn test_basic_anvil_generation() {
         .arg("--template")
         .arg("rust-hello-world")
         .arg("--no-input")
+        .arg("--no-hooks")
         .current_dir(workspace_dir)
         .output()
         .expect("Failed to execute anvil command");
@@ -65,7 +65,8 @@ async fn test_template_generation_subset() {
             .arg("--template")
             .arg(template)
             .arg("--no-input")
-            .current_dir(&workspace_dir)
+            .arg("--no-hooks")
+            .current_dir(workspace_dir)
             .output()
             .expect("Failed to execute anvil command");
 
@@ -168,7 +169,8 @@ async fn test_component_integration_subset() {
             .arg("--template")
             .arg("fullstack-saas")
             .arg("--no-input")
-            .current_dir(&workspace_dir)
+            .arg("--no-hooks")
+            .current_dir(workspace_dir)
             .output()
             .expect("Failed to execute anvil command");
 
@@ -257,7 +259,8 @@ async fn test_build_verification_subset() {
         .arg("--template")
         .arg("rust-hello-world")
         .arg("--no-input")
-        .current_dir(&workspace_dir)
+        .arg("--no-hooks")
+        .current_dir(workspace_dir)
         .output()
         .expect("Failed to execute anvil command");
 
@@ -434,6 +437,7 @@ async fn test_performance_stress_subset() {
                     .arg("--template")
                     .arg("rust-hello-world")
                     .arg("--no-input")
+                    .arg("--no-hooks")
                     .current_dir(&workspace)
                     .output()
                     .expect("Failed to execute anvil command");